@@ -0,0 +1,77 @@
+//! Throughput of concurrent DB queries on a single actix worker.
+//! "inline" runs diesel on the worker thread(the old behavior), "blocking_pool" uses run_blocking.
+//! Requires DATABASE_URL.(.env is read)
+use chan_core::model::Log;
+use chan_core::pagination::KeysetPage;
+use chan_core::{run_blocking, DBPool};
+use criterion::{criterion_group, criterion_main, BenchmarkId, Criterion, Throughput};
+use diesel::r2d2::{ConnectionManager, Pool};
+use diesel::PgConnection;
+use dotenv::dotenv;
+
+const PAGE: KeysetPage = KeysetPage {
+    after: None,
+    before: None,
+    limit: 51,
+    backward: false,
+};
+
+fn concurrent_queries(c: &mut Criterion) {
+    dotenv().ok();
+    let database_url = std::env::var("DATABASE_URL").expect("DATABASE_URL must be set");
+    let db_pool: DBPool = Pool::builder()
+        .max_size(20)
+        .build(ConnectionManager::<PgConnection>::new(database_url))
+        .unwrap();
+
+    //actix workers are single threaded runtimes
+    let runtime = tokio::runtime::Builder::new_current_thread()
+        .enable_all()
+        .build()
+        .unwrap();
+
+    let mut group = c.benchmark_group("concurrent_queries");
+    for requests in [1, 8, 32, 128] {
+        group.throughput(Throughput::Elements(requests as u64));
+        group.bench_with_input(BenchmarkId::new("inline", requests), &requests, |b, &n| {
+            b.to_async(&runtime).iter(|| {
+                let db_pool = db_pool.clone();
+                async move {
+                    let tasks: Vec<_> = (0..n)
+                        .map(|_| {
+                            let db_pool = db_pool.clone();
+                            tokio::spawn(async move { Log::page(&db_pool, true, &PAGE) })
+                        })
+                        .collect();
+                    for task in tasks {
+                        task.await.unwrap().unwrap();
+                    }
+                }
+            })
+        });
+        group.bench_with_input(
+            BenchmarkId::new("blocking_pool", requests),
+            &requests,
+            |b, &n| {
+                b.to_async(&runtime).iter(|| {
+                    let db_pool = db_pool.clone();
+                    async move {
+                        let tasks: Vec<_> = (0..n)
+                            .map(|_| {
+                                let db_pool = db_pool.clone();
+                                tokio::spawn(run_blocking(move || Log::page(&db_pool, true, &PAGE)))
+                            })
+                            .collect();
+                        for task in tasks {
+                            task.await.unwrap().unwrap();
+                        }
+                    }
+                })
+            },
+        );
+    }
+    group.finish();
+}
+
+criterion_group!(benches, concurrent_queries);
+criterion_main!(benches);
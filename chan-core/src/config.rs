@@ -0,0 +1,665 @@
+//! Configuration of the server, loaded from a TOML file.
+//! Every value has a default, and environment variables override the file.(e.g. DATABASE_URL)
+use crate::graphql::QueryLimits;
+use crate::model::TokenConfig;
+use anyhow::{anyhow, Result};
+use serde::Deserialize;
+use std::net::IpAddr;
+use std::path::{Path, PathBuf};
+use std::str::FromStr;
+use std::time::Duration;
+use tracing::{event, Level};
+use uuid::Uuid;
+
+#[derive(Deserialize, Debug, Clone, Default)]
+#[serde(default, deny_unknown_fields)]
+pub struct Config {
+    pub server: ServerConfig,
+    pub database: DatabaseConfig,
+    pub search: SearchConfig,
+    pub auth: AuthConfig,
+    pub cookie: CookieConfig,
+    pub captcha: CaptchaConfig,
+    pub graphql: QueryLimits,
+    pub rate_limit: RateLimitConfig,
+    pub flood: FloodConfig,
+    pub content_filter: ContentFilterConfig,
+    pub system_info: SystemInfoConfig,
+}
+
+#[derive(Deserialize, Debug, Clone)]
+#[serde(default, deny_unknown_fields)]
+pub struct ServerConfig {
+    pub bind_address: String,
+    /// Allows missing and non-random cookie keys, short keys are rejected anyway.(never enable it in production)
+    pub dev_mode: bool,
+    /// GraphQL server of chan-web.
+    /// None to run operations in process, set it when the API is served separately.
+    pub graphql_url: Option<String>,
+    /// Reverse proxies(or chan-web servers) in front of this server.
+    /// X-Forwarded-For is used as the client IP address only on requests from them.
+    pub trusted_proxies: Vec<IpAddr>,
+}
+
+impl Default for ServerConfig {
+    fn default() -> Self {
+        ServerConfig {
+            bind_address: "127.0.0.1:8080".to_string(),
+            dev_mode: false,
+            graphql_url: None,
+            trusted_proxies: Vec::new(),
+        }
+    }
+}
+
+#[derive(Deserialize, Debug, Clone)]
+#[serde(default, deny_unknown_fields)]
+pub struct DatabaseConfig {
+    pub url: String,
+    pub pool_max_size: u32,
+}
+
+impl Default for DatabaseConfig {
+    fn default() -> Self {
+        DatabaseConfig {
+            url: String::new(),
+            pool_max_size: 20,
+        }
+    }
+}
+
+#[derive(Deserialize, Debug, Clone)]
+#[serde(default, deny_unknown_fields)]
+pub struct SearchConfig {
+    /// Directory of the tantivy index, relative to the working directory.
+    pub index_dir: PathBuf,
+    /// Memory budget of the index writer.(bytes)
+    pub writer_heap_bytes: usize,
+}
+
+impl Default for SearchConfig {
+    fn default() -> Self {
+        SearchConfig {
+            index_dir: PathBuf::from("tantivy"),
+            writer_heap_bytes: 100_000_000,
+        }
+    }
+}
+
+#[derive(Deserialize, Debug, Clone)]
+#[serde(default, deny_unknown_fields)]
+pub struct AuthConfig {
+    /// Secret key to sign access tokens.
+    pub jwt_secret_key: String,
+    pub access_token_lifetime_minutes: i64,
+    pub refresh_token_lifetime_days: i64,
+}
+
+impl Default for AuthConfig {
+    fn default() -> Self {
+        AuthConfig {
+            jwt_secret_key: String::new(),
+            access_token_lifetime_minutes: 60,
+            refresh_token_lifetime_days: 30,
+        }
+    }
+}
+
+/// Keys of the identity, session and flash message cookies.
+/// Hex encoded, 32 bytes or more.(e.g. openssl rand -hex 64)
+#[derive(Deserialize, Debug, Clone, Default)]
+#[serde(default, deny_unknown_fields)]
+pub struct CookieConfig {
+    /// The first key signs new cookies, the others are only accepted.(keep old keys here while rotating)
+    pub keys: Vec<String>,
+    /// File with one key per line in the same order, used when keys is empty.
+    pub key_file: Option<PathBuf>,
+}
+
+/// Decoded cookie keys.
+#[derive(Debug, Clone)]
+pub struct CookieKeys {
+    /// Signs new cookies.
+    pub current: Vec<u8>,
+    /// Accepted until they are removed from the config.
+    pub previous: Vec<Vec<u8>>,
+}
+
+impl CookieKeys {
+    /// All keys, the current key first.
+    pub fn all(&self) -> Vec<Vec<u8>> {
+        std::iter::once(self.current.clone())
+            .chain(self.previous.iter().cloned())
+            .collect()
+    }
+}
+
+pub const MIN_COOKIE_KEY_BYTES: usize = 32;
+
+fn decode_hex(s: &str) -> Result<Vec<u8>> {
+    if s.len() % 2 != 0 || !s.is_ascii() {
+        return Err(anyhow!("Invalid hex string."));
+    }
+    (0..s.len())
+        .step_by(2)
+        .map(|i| {
+            u8::from_str_radix(&s[i..i + 2], 16).map_err(|e| anyhow!("Invalid hex string: {}", e))
+        })
+        .collect()
+}
+
+/// Too few distinct bytes to be random.(e.g. all zero)
+fn is_weak_key(key: &[u8]) -> bool {
+    let distinct = key.iter().collect::<std::collections::HashSet<_>>().len();
+    distinct < MIN_COOKIE_KEY_BYTES / 2
+}
+
+/// Which actions require a CAPTCHA.
+#[derive(Deserialize, Debug, Clone)]
+#[serde(default, deny_unknown_fields)]
+pub struct CaptchaConfig {
+    pub registration: bool,
+    pub thread_creation: bool,
+    pub posting: bool,
+    /// Boards requiring a CAPTCHA for both thread creation and posting, regardless of the above.
+    pub boards: Vec<Uuid>,
+    /// Challenges expire after this.
+    pub lifetime_seconds: i64,
+}
+
+impl Default for CaptchaConfig {
+    fn default() -> Self {
+        CaptchaConfig {
+            registration: true,
+            thread_creation: true,
+            posting: false,
+            boards: Vec::new(),
+            lifetime_seconds: 600,
+        }
+    }
+}
+
+impl CaptchaConfig {
+    pub fn required_for_thread_creation(&self, board_uuid: &Uuid) -> bool {
+        self.thread_creation || self.boards.contains(board_uuid)
+    }
+
+    pub fn required_for_posting(&self, board_uuid: &Uuid) -> bool {
+        self.posting || self.boards.contains(board_uuid)
+    }
+
+    pub fn lifetime(&self) -> chrono::Duration {
+        chrono::Duration::seconds(self.lifetime_seconds)
+    }
+}
+
+/// Where the rate limiter keeps its counters.
+#[derive(Deserialize, Debug, Clone, Copy, PartialEq)]
+#[serde(rename_all = "lowercase")]
+pub enum RateLimitBackendType {
+    /// Per process, reset on restart.
+    Memory,
+    /// Shared by every process using the database.
+    Database,
+}
+
+impl FromStr for RateLimitBackendType {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> Result<Self> {
+        match s {
+            "memory" => Ok(RateLimitBackendType::Memory),
+            "database" => Ok(RateLimitBackendType::Database),
+            _ => Err(anyhow!("expected memory or database")),
+        }
+    }
+}
+
+/// Requests per client IP address.
+#[derive(Deserialize, Debug, Clone)]
+#[serde(default, deny_unknown_fields)]
+pub struct RateLimitConfig {
+    pub backend: RateLimitBackendType,
+    pub interval_seconds: u64,
+    pub max_requests: u64,
+}
+
+impl Default for RateLimitConfig {
+    fn default() -> Self {
+        RateLimitConfig {
+            backend: RateLimitBackendType::Memory,
+            interval_seconds: 60,
+            max_requests: 60,
+        }
+    }
+}
+
+/// At most max_count actions in period_seconds.
+/// "max_count/period_seconds" in environment variables.(e.g. FLOOD_POSTING=1/15)
+#[derive(Deserialize, Debug, Clone, Copy, PartialEq)]
+#[serde(deny_unknown_fields)]
+pub struct FloodRule {
+    /// 0 disables the rule.
+    pub max_count: u32,
+    pub period_seconds: u64,
+}
+
+impl FloodRule {
+    pub fn is_disabled(&self) -> bool {
+        self.max_count == 0
+    }
+
+    pub fn period(&self) -> Duration {
+        Duration::from_secs(self.period_seconds)
+    }
+}
+
+impl FromStr for FloodRule {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> Result<Self> {
+        let (max_count, period_seconds) = s
+            .split_once('/')
+            .ok_or_else(|| anyhow!("expected max_count/period_seconds"))?;
+        Ok(FloodRule {
+            max_count: max_count.trim().parse()?,
+            period_seconds: period_seconds.trim().parse()?,
+        })
+    }
+}
+
+/// Flood control of actions, applied by chan-core to GraphQL clients and chan-web alike.
+#[derive(Deserialize, Debug, Clone)]
+#[serde(default, deny_unknown_fields)]
+pub struct FloodConfig {
+    /// Per user.
+    pub thread_creation: FloodRule,
+    /// Per user.
+    pub posting: FloodRule,
+    /// Per client IP address.
+    pub registration: FloodRule,
+    /// CAPTCHA challenges, per client IP address.
+    pub captcha: FloodRule,
+}
+
+impl Default for FloodConfig {
+    fn default() -> Self {
+        FloodConfig {
+            thread_creation: FloodRule {
+                max_count: 1,
+                period_seconds: 300,
+            },
+            posting: FloodRule {
+                max_count: 1,
+                period_seconds: 15,
+            },
+            registration: FloodRule {
+                max_count: 3,
+                period_seconds: 3600,
+            },
+            captcha: FloodRule {
+                max_count: 20,
+                period_seconds: 60,
+            },
+        }
+    }
+}
+
+/// Built-in filters of new threadposts, 0 disables each of them.
+/// Banned words and regexes are set per board by moderators.
+#[derive(Deserialize, Debug, Clone)]
+#[serde(default, deny_unknown_fields)]
+pub struct ContentFilterConfig {
+    /// Reject the same body as a threadpost posted within this.
+    pub duplicate_window_seconds: u64,
+    /// Hold threadposts with more links than this.
+    pub max_links: usize,
+    /// Reject threadposts repeating a character more than this in a row.
+    pub max_repeated_characters: usize,
+}
+
+impl Default for ContentFilterConfig {
+    fn default() -> Self {
+        ContentFilterConfig {
+            duplicate_window_seconds: 600,
+            max_links: 5,
+            max_repeated_characters: 50,
+        }
+    }
+}
+
+#[derive(Deserialize, Debug, Clone)]
+#[serde(default, deny_unknown_fields)]
+pub struct SystemInfoConfig {
+    /// System info is cached for this interval.
+    pub refresh_interval_seconds: u64,
+}
+
+impl Default for SystemInfoConfig {
+    fn default() -> Self {
+        SystemInfoConfig {
+            refresh_interval_seconds: 10,
+        }
+    }
+}
+
+/// Replace the value if the environment variable is set.
+fn override_from_env<T: FromStr>(value: &mut T, name: &str) -> Result<()>
+where
+    T::Err: std::fmt::Display,
+{
+    if let Ok(s) = std::env::var(name) {
+        *value = s.parse().map_err(|e| anyhow!("Invalid {}: {}", name, e))?;
+    }
+    Ok(())
+}
+
+impl Config {
+    /// Load the file, apply environment overrides and validate.
+    /// The file is optional, only defaults and environment variables are used without it.
+    pub fn load(path: &Path) -> Result<Self> {
+        let mut config: Config = if path.exists() {
+            toml::from_str(&std::fs::read_to_string(path)?)
+                .map_err(|e| anyhow!("Invalid config file {}: {}", path.display(), e))?
+        } else {
+            Config::default()
+        };
+        config.apply_env()?;
+        config.validate()?;
+        Ok(config)
+    }
+
+    fn apply_env(&mut self) -> Result<()> {
+        override_from_env(&mut self.server.bind_address, "BIND_ADDRESS")?;
+        override_from_env(&mut self.server.dev_mode, "DEV_MODE")?;
+        if let Ok(url) = std::env::var("GRAPHQL_URL") {
+            self.server.graphql_url = Some(url);
+        }
+        if let Ok(proxies) = std::env::var("TRUSTED_PROXIES") {
+            self.server.trusted_proxies = proxies
+                .split(',')
+                .map(|p| p.trim().parse())
+                .collect::<std::result::Result<_, _>>()
+                .map_err(|e| anyhow!("Invalid TRUSTED_PROXIES: {}", e))?;
+        }
+        override_from_env(&mut self.database.url, "DATABASE_URL")?;
+        override_from_env(&mut self.database.pool_max_size, "DATABASE_POOL_MAX_SIZE")?;
+        override_from_env(&mut self.search.index_dir, "SEARCH_INDEX_DIR")?;
+        override_from_env(
+            &mut self.search.writer_heap_bytes,
+            "SEARCH_WRITER_HEAP_BYTES",
+        )?;
+        override_from_env(&mut self.auth.jwt_secret_key, "JWT_SECRET_KEY")?;
+        override_from_env(
+            &mut self.auth.access_token_lifetime_minutes,
+            "ACCESS_TOKEN_LIFETIME_MINUTES",
+        )?;
+        override_from_env(
+            &mut self.auth.refresh_token_lifetime_days,
+            "REFRESH_TOKEN_LIFETIME_DAYS",
+        )?;
+        if let Ok(keys) = std::env::var("COOKIE_KEYS") {
+            self.cookie.keys = keys.split(',').map(|k| k.trim().to_string()).collect();
+        }
+        if let Ok(path) = std::env::var("COOKIE_KEY_FILE") {
+            self.cookie.key_file = Some(PathBuf::from(path));
+        }
+        override_from_env(&mut self.captcha.registration, "CAPTCHA_REGISTRATION")?;
+        override_from_env(&mut self.captcha.thread_creation, "CAPTCHA_THREAD_CREATION")?;
+        override_from_env(&mut self.captcha.posting, "CAPTCHA_POSTING")?;
+        override_from_env(&mut self.graphql.max_depth, "GRAPHQL_MAX_DEPTH")?;
+        override_from_env(&mut self.graphql.max_complexity, "GRAPHQL_MAX_COMPLEXITY")?;
+        override_from_env(
+            &mut self.graphql.persisted_query_capacity,
+            "GRAPHQL_PERSISTED_QUERY_CAPACITY",
+        )?;
+        override_from_env(&mut self.rate_limit.backend, "RATE_LIMIT_BACKEND")?;
+        override_from_env(
+            &mut self.rate_limit.interval_seconds,
+            "RATE_LIMIT_INTERVAL_SECONDS",
+        )?;
+        override_from_env(&mut self.rate_limit.max_requests, "RATE_LIMIT_MAX_REQUESTS")?;
+        override_from_env(&mut self.flood.thread_creation, "FLOOD_THREAD_CREATION")?;
+        override_from_env(&mut self.flood.posting, "FLOOD_POSTING")?;
+        override_from_env(&mut self.flood.registration, "FLOOD_REGISTRATION")?;
+        override_from_env(&mut self.flood.captcha, "FLOOD_CAPTCHA")?;
+        override_from_env(
+            &mut self.content_filter.duplicate_window_seconds,
+            "CONTENT_FILTER_DUPLICATE_WINDOW_SECONDS",
+        )?;
+        override_from_env(
+            &mut self.content_filter.max_links,
+            "CONTENT_FILTER_MAX_LINKS",
+        )?;
+        override_from_env(
+            &mut self.content_filter.max_repeated_characters,
+            "CONTENT_FILTER_MAX_REPEATED_CHARACTERS",
+        )?;
+        override_from_env(
+            &mut self.system_info.refresh_interval_seconds,
+            "SYSTEM_INFO_REFRESH_INTERVAL_SECONDS",
+        )?;
+        Ok(())
+    }
+
+    fn validate(&self) -> Result<()> {
+        if self.database.url.is_empty() {
+            return Err(anyhow!("database.url(DATABASE_URL) must be set."));
+        }
+        if self.database.pool_max_size == 0 {
+            return Err(anyhow!("database.pool_max_size must be positive."));
+        }
+        if self.auth.jwt_secret_key.is_empty() {
+            return Err(anyhow!("auth.jwt_secret_key(JWT_SECRET_KEY) must be set."));
+        }
+        if self.auth.access_token_lifetime_minutes <= 0
+            || self.auth.refresh_token_lifetime_days * 24 * 60
+                <= self.auth.access_token_lifetime_minutes
+        {
+            return Err(anyhow!(
+                "Invalid token lifetimes.(refresh token must outlive access token)"
+            ));
+        }
+        if self.graphql.max_depth == 0 || self.graphql.max_complexity == 0 {
+            return Err(anyhow!("Invalid query limits."));
+        }
+        if self.search.writer_heap_bytes == 0 {
+            return Err(anyhow!("search.writer_heap_bytes must be positive."));
+        }
+        if self.captcha.lifetime_seconds <= 0 {
+            return Err(anyhow!("captcha.lifetime_seconds must be positive."));
+        }
+        if self.rate_limit.interval_seconds == 0 || self.rate_limit.max_requests == 0 {
+            return Err(anyhow!("Invalid rate limit."));
+        }
+        for rule in [
+            &self.flood.thread_creation,
+            &self.flood.posting,
+            &self.flood.registration,
+            &self.flood.captcha,
+        ] {
+            if !rule.is_disabled() && rule.period_seconds == 0 {
+                return Err(anyhow!(
+                    "Invalid flood rule.(period_seconds must be positive)"
+                ));
+            }
+        }
+        if self.system_info.refresh_interval_seconds == 0 {
+            return Err(anyhow!(
+                "system_info.refresh_interval_seconds must be positive."
+            ));
+        }
+        Ok(())
+    }
+
+    pub fn token_config(&self) -> TokenConfig {
+        TokenConfig {
+            secret_key: self.auth.jwt_secret_key.clone(),
+            access_token_lifetime: chrono::Duration::minutes(
+                self.auth.access_token_lifetime_minutes,
+            ),
+            refresh_token_lifetime: chrono::Duration::days(self.auth.refresh_token_lifetime_days),
+        }
+    }
+
+    /// Decode the cookie keys.(from keys, or key_file)
+    /// Missing or weak keys are errors, except in dev mode where a random key is generated for missing keys.
+    pub fn cookie_keys(&self) -> Result<CookieKeys> {
+        let mut hex_keys = self.cookie.keys.clone();
+        if hex_keys.is_empty() {
+            if let Some(key_file) = &self.cookie.key_file {
+                hex_keys = std::fs::read_to_string(key_file)
+                    .map_err(|e| anyhow!("Failed to read {}: {}", key_file.display(), e))?
+                    .lines()
+                    .map(|line| line.trim().to_string())
+                    .filter(|line| !line.is_empty() && !line.starts_with('#'))
+                    .collect();
+            }
+        }
+
+        let mut keys = Vec::new();
+        for hex_key in hex_keys.iter().filter(|k| !k.is_empty()) {
+            let key = decode_hex(hex_key)?;
+            //shorter keys panic when the cookie keys are derived, even in dev mode
+            if key.len() < MIN_COOKIE_KEY_BYTES {
+                return Err(anyhow!(
+                    "Cookie key is too short.(use {} random bytes or more)",
+                    MIN_COOKIE_KEY_BYTES
+                ));
+            }
+            if is_weak_key(&key) && !self.server.dev_mode {
+                return Err(anyhow!(
+                    "Weak cookie key.(use {} random bytes or more)",
+                    MIN_COOKIE_KEY_BYTES
+                ));
+            }
+            keys.push(key);
+        }
+        if keys.is_empty() {
+            if !self.server.dev_mode {
+                return Err(anyhow!(
+                    "cookie.keys(COOKIE_KEYS) or cookie.key_file must be set."
+                ));
+            }
+            event!(
+                Level::WARN,
+                "No cookie key is set, using a random key.(cookies are invalidated on restart)"
+            );
+            let mut key = vec![0; 64];
+            openssl::rand::rand_bytes(&mut key)?;
+            keys.push(key);
+        }
+
+        let current = keys.remove(0);
+        Ok(CookieKeys {
+            current: current,
+            previous: keys,
+        })
+    }
+
+    pub fn rate_limit_interval(&self) -> Duration {
+        Duration::from_secs(self.rate_limit.interval_seconds)
+    }
+
+    pub fn system_info_refresh_interval(&self) -> Duration {
+        Duration::from_secs(self.system_info.refresh_interval_seconds)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn partial_file_keeps_defaults() {
+        let config: Config = toml::from_str(
+            r#"
+            [database]
+            url = "postgres://localhost/chan"
+
+            [rate_limit]
+            max_requests = 120
+            "#,
+        )
+        .unwrap();
+        assert_eq!(config.database.url, "postgres://localhost/chan");
+        assert_eq!(config.database.pool_max_size, 20);
+        assert_eq!(config.rate_limit.max_requests, 120);
+        assert_eq!(config.rate_limit.interval_seconds, 60);
+        assert_eq!(config.rate_limit.backend, RateLimitBackendType::Memory);
+        assert_eq!(config.server.bind_address, "127.0.0.1:8080");
+    }
+
+    #[test]
+    fn rate_limit_backend_parses() {
+        let config: Config = toml::from_str("[rate_limit]\nbackend = \"database\"").unwrap();
+        assert_eq!(config.rate_limit.backend, RateLimitBackendType::Database);
+        assert!(toml::from_str::<Config>("[rate_limit]\nbackend = \"redis\"").is_err());
+    }
+
+    #[test]
+    fn weak_cookie_keys_are_rejected() {
+        let mut config = Config::default();
+        config.cookie.keys = vec!["00".repeat(64)];
+        assert!(config.cookie_keys().is_err());
+
+        config.cookie.keys = vec!["ab".repeat(16)];
+        assert!(config.cookie_keys().is_err());
+
+        let key: String = (0..64u8).map(|b| format!("{:02x}", b)).collect();
+        config.cookie.keys = vec![key, "00".repeat(64)];
+        assert!(config.cookie_keys().is_err());
+
+        config.server.dev_mode = true;
+        let keys = config.cookie_keys().unwrap();
+        assert_eq!(keys.current.len(), 64);
+        assert_eq!(keys.previous.len(), 1);
+
+        config.cookie.keys = vec!["ab".repeat(16)];
+        assert!(config.cookie_keys().is_err());
+    }
+
+    #[test]
+    fn unknown_keys_are_rejected() {
+        assert!(toml::from_str::<Config>("[database]\nurll = \"x\"").is_err());
+    }
+
+    #[test]
+    fn flood_rules_parse() {
+        let config: Config =
+            toml::from_str("[flood]\nposting = { max_count = 2, period_seconds = 30 }").unwrap();
+        assert_eq!(
+            config.flood.posting,
+            FloodRule {
+                max_count: 2,
+                period_seconds: 30
+            }
+        );
+        assert_eq!(
+            config.flood.thread_creation,
+            FloodConfig::default().thread_creation
+        );
+        assert_eq!(
+            "1/15".parse::<FloodRule>().unwrap(),
+            FloodRule {
+                max_count: 1,
+                period_seconds: 15
+            }
+        );
+        assert!("15".parse::<FloodRule>().is_err());
+    }
+
+    #[test]
+    fn captcha_boards_require_captcha() {
+        let board_uuid = Uuid::new_v4();
+        let config: Config = toml::from_str(&format!(
+            "[captcha]\nthread_creation = false\nboards = [\"{}\"]",
+            board_uuid
+        ))
+        .unwrap();
+        assert!(config.captcha.required_for_thread_creation(&board_uuid));
+        assert!(config.captcha.required_for_posting(&board_uuid));
+        assert!(!config.captcha.required_for_thread_creation(&Uuid::new_v4()));
+        assert!(!config.captcha.required_for_posting(&Uuid::new_v4()));
+    }
+}
@@ -0,0 +1,269 @@
+//! Filters of new threadposts, run before they are inserted.
+//! Each filter allows, holds for review, or rejects the threadpost.
+use crate::config::ContentFilterConfig;
+use crate::model::{compile_regex, ContentFilterRule, FilterAction};
+use crate::DBPool;
+use async_graphql::Result;
+use chrono::{DateTime, Duration, Utc};
+use diesel::prelude::*;
+use regex::Regex;
+use std::collections::HashMap;
+use std::sync::{Arc, Mutex};
+use uuid::Uuid;
+
+/// Short replies(e.g. "thanks") are often the same, they are not checked for duplicates.
+const DUPLICATE_MIN_CHARS: usize = 16;
+/// The regex cache is cleared when it grows over this, dropping regexes of removed rules.
+const MAX_CACHED_REGEXES: usize = 4096;
+
+/// Threadpost being checked.
+pub struct PostCandidate<'a> {
+    pub poster_user_id: &'a str,
+    pub board_uuid: &'a Uuid,
+    /// Title of a new thread, None for replies.
+    pub thread_title: Option<&'a str>,
+    pub body: &'a str,
+}
+
+impl PostCandidate<'_> {
+    /// Title and body, for filters checking both.
+    fn texts(&self) -> impl Iterator<Item = &str> {
+        self.thread_title
+            .into_iter()
+            .chain(std::iter::once(self.body))
+    }
+}
+
+#[derive(PartialEq, Debug, Clone)]
+pub enum FilterDecision {
+    Allow,
+    /// Wait for review by moderators of the board, with the reason.
+    Hold(String),
+    /// With the reason shown to the poster.
+    Reject(String),
+}
+
+pub trait ContentFilter: Send + Sync {
+    fn check(&self, db_pool: &DBPool, post: &PostCandidate) -> Result<FilterDecision>;
+}
+
+/// Filters run in order.
+/// The first rejection wins, otherwise the first hold.
+pub struct ContentFilterChain {
+    filters: Vec<Box<dyn ContentFilter>>,
+}
+
+impl ContentFilterChain {
+    /// Chain without filters, allows everything.
+    pub fn new() -> Self {
+        ContentFilterChain {
+            filters: Vec::new(),
+        }
+    }
+
+    pub fn with<F: ContentFilter + 'static>(mut self, filter: F) -> Self {
+        self.filters.push(Box::new(filter));
+        self
+    }
+
+    /// Built-in filters enabled by the config, cheap ones first.
+    pub fn from_config(config: &ContentFilterConfig) -> Self {
+        let mut chain = ContentFilterChain::new();
+        if config.max_repeated_characters > 0 {
+            chain = chain.with(RepeatedCharacterFilter {
+                max_repeated_characters: config.max_repeated_characters,
+            });
+        }
+        if config.max_links > 0 {
+            chain = chain.with(LinkLimitFilter {
+                max_links: config.max_links,
+            });
+        }
+        chain = chain.with(BoardRuleFilter::new());
+        if config.duplicate_window_seconds > 0 {
+            chain = chain.with(DuplicateBodyFilter {
+                window: Duration::seconds(config.duplicate_window_seconds as i64),
+            });
+        }
+        chain
+    }
+
+    pub fn run(&self, db_pool: &DBPool, post: &PostCandidate) -> Result<FilterDecision> {
+        let mut decision = FilterDecision::Allow;
+        for filter in &self.filters {
+            match filter.check(db_pool, post)? {
+                FilterDecision::Allow => {}
+                FilterDecision::Reject(reason) => return Ok(FilterDecision::Reject(reason)),
+                FilterDecision::Hold(reason) => {
+                    if decision == FilterDecision::Allow {
+                        decision = FilterDecision::Hold(reason);
+                    }
+                }
+            }
+        }
+        Ok(decision)
+    }
+}
+
+/// Length of the longest run of one character.
+fn longest_run(text: &str) -> usize {
+    let mut longest = 0;
+    let mut run = 0;
+    let mut previous = None;
+    for c in text.chars() {
+        if Some(c) == previous {
+            run += 1;
+        } else {
+            run = 1;
+            previous = Some(c);
+        }
+        longest = std::cmp::max(longest, run);
+    }
+    longest
+}
+
+fn count_links(text: &str) -> usize {
+    let text = text.to_lowercase();
+    text.matches("http://").count() + text.matches("https://").count()
+}
+
+/// Rejects texts repeating a character too many times in a row.(e.g. "aaaaaaaa...")
+pub struct RepeatedCharacterFilter {
+    pub max_repeated_characters: usize,
+}
+
+impl ContentFilter for RepeatedCharacterFilter {
+    fn check(&self, _db_pool: &DBPool, post: &PostCandidate) -> Result<FilterDecision> {
+        if post
+            .texts()
+            .any(|text| longest_run(text) > self.max_repeated_characters)
+        {
+            return Ok(FilterDecision::Reject(
+                "Too many repeated characters.".to_string(),
+            ));
+        }
+        Ok(FilterDecision::Allow)
+    }
+}
+
+/// Holds threadposts with many links, typical of link spam.
+pub struct LinkLimitFilter {
+    pub max_links: usize,
+}
+
+impl ContentFilter for LinkLimitFilter {
+    fn check(&self, _db_pool: &DBPool, post: &PostCandidate) -> Result<FilterDecision> {
+        let links: usize = post.texts().map(count_links).sum();
+        if links > self.max_links {
+            return Ok(FilterDecision::Hold(format!("{} links.", links)));
+        }
+        Ok(FilterDecision::Allow)
+    }
+}
+
+/// Banned words and regexes of the board.(ContentFilterRule)
+/// Regexes are compiled once per rule version.(rules are never edited, only added and removed)
+pub struct BoardRuleFilter {
+    regexes: Mutex<HashMap<(Uuid, DateTime<Utc>), Arc<Regex>>>,
+}
+
+impl BoardRuleFilter {
+    pub fn new() -> Self {
+        BoardRuleFilter {
+            regexes: Mutex::new(HashMap::new()),
+        }
+    }
+
+    fn regex(&self, rule: &ContentFilterRule) -> Result<Arc<Regex>> {
+        let key = (rule.uuid, rule.created_at);
+        if let Some(regex) = self.regexes.lock().unwrap().get(&key) {
+            return Ok(regex.clone());
+        }
+        //compiled without the lock, the same rule may be compiled twice on a race
+        let regex = Arc::new(compile_regex(&rule.pattern)?);
+        let mut regexes = self.regexes.lock().unwrap();
+        if regexes.len() >= MAX_CACHED_REGEXES {
+            regexes.clear();
+        }
+        regexes.insert(key, regex.clone());
+        Ok(regex)
+    }
+}
+
+impl ContentFilter for BoardRuleFilter {
+    fn check(&self, db_pool: &DBPool, post: &PostCandidate) -> Result<FilterDecision> {
+        let mut decision = FilterDecision::Allow;
+        for rule in ContentFilterRule::select_by_board(db_pool, post.board_uuid)? {
+            let is_match = if rule.is_regex {
+                let regex = self.regex(&rule)?;
+                post.texts().any(|text| regex.is_match(text))
+            } else {
+                let word = rule.pattern.to_lowercase();
+                post.texts().any(|text| text.to_lowercase().contains(&word))
+            };
+            if !is_match {
+                continue;
+            }
+            //the reason is only shown to moderators for holds
+            match FilterAction::from_i32(rule.action)? {
+                FilterAction::Reject => {
+                    return Ok(FilterDecision::Reject(
+                        "Your post contains a banned word.".to_string(),
+                    ))
+                }
+                FilterAction::Hold => {
+                    if decision == FilterDecision::Allow {
+                        decision = FilterDecision::Hold(format!("Matched rule {}.", rule.uuid));
+                    }
+                }
+            }
+        }
+        Ok(decision)
+    }
+}
+
+/// Rejects the same body as a recent threadpost, by anyone.
+pub struct DuplicateBodyFilter {
+    pub window: Duration,
+}
+
+impl ContentFilter for DuplicateBodyFilter {
+    fn check(&self, db_pool: &DBPool, post: &PostCandidate) -> Result<FilterDecision> {
+        use crate::schema::threadposts::dsl::*;
+        if post.body.chars().count() < DUPLICATE_MIN_CHARS {
+            return Ok(FilterDecision::Allow);
+        }
+        let duplicates: i64 = threadposts
+            .filter(posted_at.gt(Utc::now() - self.window))
+            //recent threadposts may be stored rewritten by the word filters
+            .filter(body_text.eq(post.body).or(original_body_text.eq(post.body)))
+            .count()
+            .get_result(&db_pool.get()?)?;
+        if duplicates > 0 {
+            return Ok(FilterDecision::Reject("Duplicate post.".to_string()));
+        }
+        Ok(FilterDecision::Allow)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn longest_runs() {
+        assert_eq!(longest_run(""), 0);
+        assert_eq!(longest_run("abc"), 1);
+        assert_eq!(longest_run("abbbc"), 3);
+        assert_eq!(longest_run("ｗｗｗｗ"), 4);
+    }
+
+    #[test]
+    fn links() {
+        assert_eq!(count_links("no links"), 0);
+        assert_eq!(
+            count_links("http://a.example HTTPS://b.example https://c.example"),
+            3
+        );
+    }
+}
@@ -0,0 +1,45 @@
+use async_graphql::{Error, ErrorExtensions};
+
+/// Kind of a GraphQL error, sent to clients as extensions.code.
+/// Errors without a code are internal errors.(e.g. DB errors)
+#[derive(PartialEq, Debug, Clone, Copy)]
+pub enum ErrorCode {
+    NotFound,
+    Forbidden,
+    Unauthenticated,
+    /// Invalid input.(e.g. empty post, too short password)
+    Validation,
+    RateLimited,
+    /// The content was accepted but waits for review by moderators.
+    HeldForReview,
+}
+
+impl ErrorCode {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            ErrorCode::NotFound => "NOT_FOUND",
+            ErrorCode::Forbidden => "FORBIDDEN",
+            ErrorCode::Unauthenticated => "UNAUTHENTICATED",
+            ErrorCode::Validation => "VALIDATION",
+            ErrorCode::RateLimited => "RATE_LIMITED",
+            ErrorCode::HeldForReview => "HELD_FOR_REVIEW",
+        }
+    }
+
+    pub fn from_str(code: &str) -> Option<Self> {
+        match code {
+            "NOT_FOUND" => Some(ErrorCode::NotFound),
+            "FORBIDDEN" => Some(ErrorCode::Forbidden),
+            "UNAUTHENTICATED" => Some(ErrorCode::Unauthenticated),
+            "VALIDATION" => Some(ErrorCode::Validation),
+            "RATE_LIMITED" => Some(ErrorCode::RateLimited),
+            "HELD_FOR_REVIEW" => Some(ErrorCode::HeldForReview),
+            _ => None,
+        }
+    }
+
+    /// GraphQL error with the message and this code.
+    pub fn error(self, message: impl Into<String>) -> Error {
+        Error::new(message.into()).extend_with(|_, e| e.set("code", self.as_str()))
+    }
+}
@@ -0,0 +1,203 @@
+//! Flood control of actions, per user or per client IP address.
+//! Counted in memory of the process, so counters reset on restart.
+use crate::config::{FloodConfig, FloodRule};
+use crate::error::ErrorCode;
+use async_graphql::{Error, ErrorExtensions, Result};
+use std::collections::{HashMap, VecDeque};
+use std::sync::Mutex;
+use std::time::{Duration, Instant};
+
+/// Keys without recent actions are swept when the number of keys exceeds this.
+const SWEEP_THRESHOLD: usize = 10_000;
+
+#[derive(PartialEq, Eq, Hash, Debug, Clone, Copy)]
+pub enum FloodAction {
+    /// Keyed by user ID.
+    ThreadCreation,
+    /// Keyed by user ID.
+    Posting,
+    /// Keyed by client IP address.
+    Registration,
+    /// Keyed by client IP address.
+    CaptchaIssue,
+}
+
+/// Sliding window counters of actions.
+pub struct FloodControl {
+    config: FloodConfig,
+    history: Mutex<HashMap<(FloodAction, String), VecDeque<Instant>>>,
+}
+
+/// Drop the times older than the period.
+fn prune(times: &mut VecDeque<Instant>, now: Instant, period: Duration) {
+    while let Some(oldest) = times.front() {
+        if now.duration_since(*oldest) < period {
+            break;
+        }
+        times.pop_front();
+    }
+}
+
+/// RateLimited error with extensions.retryAfter, seconds until the action is allowed again.
+fn rate_limited_error(retry_after: Duration) -> Error {
+    //round up, retrying at the returned time must succeed
+    let seconds = retry_after.as_secs() + u64::from(retry_after.subsec_nanos() > 0);
+    ErrorCode::RateLimited
+        .error(format!(
+            "You are doing this too often. Try again in {} seconds.",
+            seconds
+        ))
+        .extend_with(|_, e| e.set("retryAfter", seconds))
+}
+
+impl FloodControl {
+    pub fn new(config: FloodConfig) -> Self {
+        FloodControl {
+            config: config,
+            history: Mutex::new(HashMap::new()),
+        }
+    }
+
+    fn rule(&self, action: FloodAction) -> FloodRule {
+        match action {
+            FloodAction::ThreadCreation => self.config.thread_creation,
+            FloodAction::Posting => self.config.posting,
+            FloodAction::Registration => self.config.registration,
+            FloodAction::CaptchaIssue => self.config.captcha,
+        }
+    }
+
+    /// Reserve an action of the key, or RateLimited error if the key already did it max_count times in the period.
+    /// Checked and counted under one lock, so concurrent requests can't exceed the limit.
+    /// The reservation is released when dropped, commit it once the action succeeded.
+    pub fn reserve(&self, action: FloodAction, key: &str) -> Result<FloodReservation<'_>> {
+        let rule = self.rule(action);
+        let mut reservation = FloodReservation {
+            flood_control: self,
+            action: action,
+            key: key.to_string(),
+            time: None,
+        };
+        if rule.is_disabled() {
+            return Ok(reservation);
+        }
+        let now = Instant::now();
+        let mut history = self.history.lock().unwrap_or_else(|e| e.into_inner());
+        if history.len() > SWEEP_THRESHOLD {
+            history.retain(|(action, _), times| {
+                prune(times, now, self.rule(*action).period());
+                !times.is_empty()
+            });
+        }
+        let times = history.entry((action, key.to_string())).or_default();
+        prune(times, now, rule.period());
+        if times.len() >= rule.max_count as usize {
+            let retry_after = rule.period() - now.duration_since(times[0]);
+            return Err(rate_limited_error(retry_after));
+        }
+        times.push_back(now);
+        reservation.time = Some(now);
+        Ok(reservation)
+    }
+
+    /// Uncount the reserved action.
+    fn release(&self, action: FloodAction, key: &str, time: Instant) {
+        let mut history = self.history.lock().unwrap_or_else(|e| e.into_inner());
+        if let Some(times) = history.get_mut(&(action, key.to_string())) {
+            if let Some(i) = times.iter().position(|t| *t == time) {
+                times.remove(i);
+            }
+        }
+    }
+}
+
+/// Action counted by FloodControl::reserve, uncounted on drop unless committed.
+/// So failed attempts(e.g. rejected posts) don't count.
+#[must_use]
+pub struct FloodReservation<'a> {
+    flood_control: &'a FloodControl,
+    action: FloodAction,
+    key: String,
+    /// None once committed, or if the rule is disabled.
+    time: Option<Instant>,
+}
+
+impl FloodReservation<'_> {
+    /// Keep the action counted.
+    pub fn commit(mut self) {
+        self.time = None;
+    }
+}
+
+impl Drop for FloodReservation<'_> {
+    fn drop(&mut self) {
+        if let Some(time) = self.time {
+            self.flood_control.release(self.action, &self.key, time);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn flood_control(posting: FloodRule) -> FloodControl {
+        FloodControl::new(FloodConfig {
+            posting: posting,
+            ..FloodConfig::default()
+        })
+    }
+
+    #[test]
+    fn limits_per_key() {
+        let flood_control = flood_control(FloodRule {
+            max_count: 2,
+            period_seconds: 60,
+        });
+        for _ in 0..2 {
+            flood_control
+                .reserve(FloodAction::Posting, "a")
+                .unwrap()
+                .commit();
+        }
+        let error = flood_control
+            .reserve(FloodAction::Posting, "a")
+            .err()
+            .unwrap();
+        assert!(error.message.contains("60 seconds"));
+        //other keys and actions are counted separately
+        assert!(flood_control.reserve(FloodAction::Posting, "b").is_ok());
+        assert!(flood_control
+            .reserve(FloodAction::ThreadCreation, "a")
+            .is_ok());
+    }
+
+    #[test]
+    fn pending_reservations_count() {
+        let flood_control = flood_control(FloodRule {
+            max_count: 1,
+            period_seconds: 60,
+        });
+        let reservation = flood_control.reserve(FloodAction::Posting, "a").unwrap();
+        //a concurrent request can't take the same slot
+        assert!(flood_control.reserve(FloodAction::Posting, "a").is_err());
+        //failed actions are released
+        drop(reservation);
+        assert!(flood_control.reserve(FloodAction::Posting, "a").is_ok());
+    }
+
+    #[test]
+    fn disabled_rule_never_limits() {
+        let flood_control = flood_control(FloodRule {
+            max_count: 0,
+            period_seconds: 60,
+        });
+        for _ in 0..10 {
+            flood_control
+                .reserve(FloodAction::Posting, "a")
+                .unwrap()
+                .commit();
+        }
+        assert!(flood_control.reserve(FloodAction::Posting, "a").is_ok());
+    }
+}
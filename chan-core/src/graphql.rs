@@ -1,15 +1,24 @@
+use crate::config::Config;
+use crate::content_filter::ContentFilterChain;
+use crate::error::ErrorCode;
+use crate::flood::{FloodAction, FloodControl};
+use crate::guard::{AuthGuard, AuthenticatedUser, RoleGuard};
 use crate::model::{
-    verify_token, Board, Log, SystemInfo, SystemInfoContext, Thread, ThreadPost, User, UserType,
+    require, AuthTokens, Ban, Board, BoardModerator, Capability, Captcha, CaptchaAnswer,
+    CaptchaProvider, ClientInfo, ContentFilterRule, ContentRemovalPolicy, FilterAction,
+    HeldThreadPost, IssuedCaptcha, IssuedToken, Log, ModerationAction, ModerationActionFilter,
+    ModerationActionType, Notification, PasswordResetToken, RefreshToken, Scope, SystemInfo,
+    SystemInfoContext, Thread, ThreadPost, ThreadWatch, TokenConfig, User, UserStatus, UserType,
+    WordFilterRule,
 };
-use crate::DBPool;
+use crate::pagination::{keyset_connection, list_complexity, KeysetConnection, MAX_PAGE_SIZE};
+use crate::{run_blocking, DBPool};
 //use async_graphql::*;
-use async_graphql::{
-    connection::{query, Connection, Edge, EmptyFields},
-    Context, EmptySubscription, Object, Result, Schema, SimpleObject,
-};
+use async_graphql::{Context, EmptySubscription, Error, Object, Result, Schema, SimpleObject};
 use diesel::prelude::*;
 use diesel::r2d2::{ConnectionManager, PooledConnection};
 use diesel::PgConnection;
+use serde::Deserialize;
 use std::sync::Arc;
 use tantivy::{
     collector::TopDocs, query::FuzzyTermQuery, query::QueryParser, DocAddress, DocId, Document,
@@ -23,6 +32,28 @@ use uuid::Uuid;
 pub type DBConnection = PooledConnection<ConnectionManager<PgConnection>>;
 pub type ChanSchema = Schema<QueryRoot, MutationRoot, EmptySubscription>;
 
+/// Limits applied to every query sent to the schema.
+#[derive(Deserialize, Debug, Clone, Copy)]
+#[serde(default, deny_unknown_fields)]
+pub struct QueryLimits {
+    /// Max nesting depth of fields.(introspection queries of playground need about 13)
+    pub max_depth: usize,
+    /// Max sum of field complexities, list fields count per requested item.
+    pub max_complexity: usize,
+    /// Max number of stored persisted queries, the least recently used ones are evicted.(0 disables storing)
+    pub persisted_query_capacity: usize,
+}
+
+impl Default for QueryLimits {
+    fn default() -> Self {
+        QueryLimits {
+            max_depth: 16,
+            max_complexity: 10_000,
+            persisted_query_capacity: 10_000,
+        }
+    }
+}
+
 pub struct TokenString(pub String);
 
 #[derive(SimpleObject)]
@@ -32,17 +63,56 @@ struct SearchResult {
     score: Option<f32>,
 }
 
+/// Returns Err unless the issuer is the target user or can manage sessions of others.
+fn check_self_or_admin(db_pool: &DBPool, issuer_user_id: &str, target_user_id: &str) -> Result<()> {
+    if issuer_user_id == target_user_id {
+        return Ok(());
+    }
+    require(
+        &db_pool,
+        issuer_user_id,
+        Capability::ManageSessions,
+        Scope::Global,
+    )
+}
+
+/// Returns Err unless the issuer can suspend or ban the target user.
+/// Moderators can target normal users, admins can target moderators and normal users.
+fn check_can_moderate(db_pool: &DBPool, issuer_user_id: &str, target_user_id: &str) -> Result<()> {
+    require(&db_pool, issuer_user_id, Capability::BanUser, Scope::Global)?;
+    let issuer_user = User::select_by_user_id(&db_pool, &issuer_user_id)?;
+    if issuer_user.is_none() {
+        return Err(ErrorCode::NotFound.error("User does not exist"));
+    }
+    let issuer_type = UserType::from_i32(issuer_user.unwrap().user_type)?;
+    let target_user = User::select_by_user_id(&db_pool, &target_user_id)?;
+    if target_user.is_none() {
+        return Err(ErrorCode::NotFound.error("User does not exist"));
+    }
+    let target_user = target_user.unwrap();
+    if UserStatus::from_i32(target_user.user_status)? == UserStatus::Removed {
+        return Err(ErrorCode::Validation.error("The account is removed."));
+    }
+    let target_type = UserType::from_i32(target_user.user_type)?;
+
+    let allowed = match issuer_type {
+        UserType::Admin => target_type != UserType::Admin,
+        UserType::Moderator => target_type == UserType::Normal,
+        UserType::Normal => false,
+    };
+    if !allowed || issuer_user_id == target_user_id {
+        return Err(ErrorCode::Forbidden.error("Not allowed."));
+    }
+    Ok(())
+}
+
 pub struct QueryRoot;
 
 #[Object]
 impl QueryRoot {
     /// SystemInfo
+    #[graphql(guard = "AuthGuard")]
     async fn system_info(&self, context: &Context<'_>) -> Result<SystemInfo> {
-        let db_pool = context.data::<DBPool>()?;
-        let token = context.data::<TokenString>()?;
-        //verify token
-        verify_token(&db_pool, &token.0)?;
-
         let sys_info_ctx = context.data::<Arc<RwLock<SystemInfoContext>>>()?;
         let info;
         {
@@ -52,153 +122,380 @@ impl QueryRoot {
         Ok(info)
     }
 
+    /// Whether sign_up requires a CAPTCHA.
+    async fn captcha_required_for_registration(&self, context: &Context<'_>) -> Result<bool> {
+        Ok(context.data::<Config>()?.captcha.registration)
+    }
+
     /// Find user by ID.
+    #[graphql(guard = "AuthGuard")]
     async fn user(&self, context: &Context<'_>, user_id: String) -> Result<Option<User>> {
-        let db_pool = context.data::<DBPool>()?;
+        let db_pool = context.data::<DBPool>()?.clone();
 
-        let token = context.data::<TokenString>()?;
-        //verify token
-        verify_token(&db_pool, &token.0)?;
+        run_blocking(move || User::select_by_user_id(&db_pool, &user_id)).await
+    }
 
-        User::select_by_user_id(&db_pool, &user_id)
+    /// Active sessions(tokens) of the user.
+    /// Only for the user and admin.
+    #[graphql(
+        guard = "AuthGuard",
+        complexity = "list_complexity(None, None, child_complexity)"
+    )]
+    async fn sessions(&self, context: &Context<'_>, user_id: String) -> Result<Vec<IssuedToken>> {
+        let db_pool = context.data::<DBPool>()?.clone();
+        let issuer_user_id = AuthenticatedUser::from_context(context)?.user_id.clone();
+
+        run_blocking(move || {
+            check_self_or_admin(&db_pool, &issuer_user_id, &user_id)?;
+            IssuedToken::active_tokens(&db_pool, &user_id)
+        })
+        .await
     }
 
-    /// Find board by ID.
-    async fn board(&self, context: &Context<'_>, board_id: Uuid) -> Result<Option<Board>> {
-        let db_pool = context.data::<DBPool>()?;
+    /// Number of unread notifications of the token owner.
+    #[graphql(guard = "AuthGuard")]
+    async fn unread_notification_count(&self, context: &Context<'_>) -> Result<i64> {
+        let db_pool = context.data::<DBPool>()?.clone();
+        let user_id = AuthenticatedUser::from_context(context)?.user_id.clone();
 
-        let token = context.data::<TokenString>()?;
-        //verify token
-        verify_token(&db_pool, &token.0)?;
-        Board::select_by_uuid(&db_pool, &board_id)
+        run_blocking(move || Notification::count_unread(&db_pool, &user_id)).await
     }
 
-    async fn boards(
+    /// Notifications of the token owner, newest first.
+    #[graphql(
+        guard = "AuthGuard",
+        complexity = "list_complexity(first, last, child_complexity)"
+    )]
+    async fn notifications(
         &self,
         context: &Context<'_>,
+        unread_only: Option<bool>,
         after: Option<String>,
         before: Option<String>,
         first: Option<i32>,
         last: Option<i32>,
-    ) -> Result<Connection<usize, Board, EmptyFields, EmptyFields>> {
-        let db_pool = context.data::<DBPool>()?;
-        let token = context.data::<TokenString>()?;
+    ) -> Result<KeysetConnection<Notification>> {
+        let db_pool = context.data::<DBPool>()?.clone();
+        let user_id = AuthenticatedUser::from_context(context)?.user_id.clone();
+        let unread_only = unread_only.unwrap_or(false);
+
+        keyset_connection(after, before, first, last, Notification::key, move |page| {
+            Notification::page(&db_pool, &user_id, unread_only, page)
+        })
+        .await
+    }
 
-        verify_token(&db_pool, &token.0)?;
+    /// Threads watched by the token owner, newest watch first.
+    #[graphql(
+        guard = "AuthGuard",
+        complexity = "list_complexity(None, None, child_complexity)"
+    )]
+    async fn watched_threads(&self, context: &Context<'_>) -> Result<Vec<ThreadWatch>> {
+        let db_pool = context.data::<DBPool>()?.clone();
+        let user_id = AuthenticatedUser::from_context(context)?.user_id.clone();
+
+        run_blocking(move || ThreadWatch::select_by_user(&db_pool, &user_id)).await
+    }
+
+    /// Ban history of the user, newest first.
+    /// Only for admin and moderator
+    #[graphql(
+        guard = "RoleGuard::new(UserType::Moderator)",
+        complexity = "list_complexity(None, None, child_complexity)"
+    )]
+    async fn bans(&self, context: &Context<'_>, user_id: String) -> Result<Vec<Ban>> {
+        let db_pool = context.data::<DBPool>()?.clone();
+        let issuer_user_id = AuthenticatedUser::from_context(context)?.user_id.clone();
+
+        run_blocking(move || {
+            require(
+                &db_pool,
+                &issuer_user_id,
+                Capability::BanUser,
+                Scope::Global,
+            )?;
+            Ban::history(&db_pool, &user_id)
+        })
+        .await
+    }
 
-        query(
+    /// Audit log of moderator actions, newest first.
+    /// Only for admin
+    #[graphql(
+        guard = "RoleGuard::new(UserType::Admin)",
+        complexity = "list_complexity(first, last, child_complexity)"
+    )]
+    async fn moderation_actions(
+        &self,
+        context: &Context<'_>,
+        actor_user_id: Option<String>,
+        action_type: Option<i32>,
+        target: Option<String>,
+        after: Option<String>,
+        before: Option<String>,
+        first: Option<i32>,
+        last: Option<i32>,
+    ) -> Result<KeysetConnection<ModerationAction>> {
+        let db_pool = context.data::<DBPool>()?.clone();
+        let issuer_user_id = AuthenticatedUser::from_context(context)?.user_id.clone();
+        {
+            let db_pool = db_pool.clone();
+            run_blocking(move || {
+                require(
+                    &db_pool,
+                    &issuer_user_id,
+                    Capability::ViewAuditLog,
+                    Scope::Global,
+                )
+            })
+            .await?;
+        }
+
+        let filter = ModerationActionFilter {
+            actor_user_id: actor_user_id,
+            action_type: match action_type {
+                Some(t) => Some(ModerationActionType::from_i32(t)?),
+                None => None,
+            },
+            target: target,
+        };
+
+        keyset_connection(
             after,
             before,
             first,
             last,
-            |after, before, first, last| async move {
-                let start = after.map(|after| after + 1).unwrap_or(0);
-                let board_count = Board::count(&db_pool).unwrap_or(100);
-                let mut end = Board::count(&db_pool).unwrap_or(100) + 1;
-
-                if let Some(before) = before {
-                    if before == 0 {
-                        return Ok(Connection::new(false, false));
-                    }
-                    end = before;
-                }
+            ModerationAction::key,
+            move |page| ModerationAction::page(&db_pool, &filter, page),
+        )
+        .await
+    }
 
-                event!(Level::DEBUG, "Fetch board range {} ~ {}", start, end);
+    /// Moderators assigned to the board.
+    #[graphql(
+        guard = "AuthGuard",
+        complexity = "list_complexity(None, None, child_complexity)"
+    )]
+    async fn board_moderators(
+        &self,
+        context: &Context<'_>,
+        board_id: Uuid,
+    ) -> Result<Vec<BoardModerator>> {
+        let db_pool = context.data::<DBPool>()?.clone();
 
-                //fetch boards
-                let boards = Board::pagination_query(
-                    &db_pool,
-                    //std::cmp::max(0,end-start-1).try_into().unwrap(),
-                    (end - start - 1).try_into().unwrap(),
-                    start.try_into().unwrap(),
-                )?;
-                let mut connection = Connection::new(start > 0, end < board_count);
-                connection.append(
-                    boards
-                        .into_iter()
-                        .enumerate()
-                        .map(|(index, board)| Edge::new(start + index, board)),
-                );
-                Ok::<_, async_graphql::Error>(connection)
-            },
-        )
+        run_blocking(move || BoardModerator::select_by_board(&db_pool, &board_id)).await
+    }
+
+    /// Banned words and regexes of the board.
+    /// Only for admin and moderators of the board
+    #[graphql(
+        guard = "AuthGuard",
+        complexity = "list_complexity(None, None, child_complexity)"
+    )]
+    async fn content_filter_rules(
+        &self,
+        context: &Context<'_>,
+        board_id: Uuid,
+    ) -> Result<Vec<ContentFilterRule>> {
+        let db_pool = context.data::<DBPool>()?.clone();
+        let issuer_user_id = AuthenticatedUser::from_context(context)?.user_id.clone();
+
+        run_blocking(move || {
+            require(
+                &db_pool,
+                &issuer_user_id,
+                Capability::ManageContentFilters,
+                Scope::Board(&board_id),
+            )?;
+            ContentFilterRule::select_by_board(&db_pool, &board_id)
+        })
+        .await
+    }
+
+    /// Threadposts of the board held by the content filters, oldest first.
+    /// Only for admin and moderators of the board
+    #[graphql(
+        guard = "AuthGuard",
+        complexity = "list_complexity(None, None, child_complexity)"
+    )]
+    async fn held_threadposts(
+        &self,
+        context: &Context<'_>,
+        board_id: Uuid,
+    ) -> Result<Vec<HeldThreadPost>> {
+        let db_pool = context.data::<DBPool>()?.clone();
+        let issuer_user_id = AuthenticatedUser::from_context(context)?.user_id.clone();
+
+        run_blocking(move || {
+            require(
+                &db_pool,
+                &issuer_user_id,
+                Capability::ReviewHeldThreadposts,
+                Scope::Board(&board_id),
+            )?;
+            HeldThreadPost::select_by_board(&db_pool, &board_id)
+        })
+        .await
+    }
+
+    /// Find-and-replace rules of the board, in the order they are applied.
+    /// Only for admin and moderators of the board
+    #[graphql(
+        guard = "AuthGuard",
+        complexity = "list_complexity(None, None, child_complexity)"
+    )]
+    async fn word_filter_rules(
+        &self,
+        context: &Context<'_>,
+        board_id: Uuid,
+    ) -> Result<Vec<WordFilterRule>> {
+        let db_pool = context.data::<DBPool>()?.clone();
+        let issuer_user_id = AuthenticatedUser::from_context(context)?.user_id.clone();
+
+        run_blocking(move || {
+            require(
+                &db_pool,
+                &issuer_user_id,
+                Capability::ManageWordFilters,
+                Scope::Board(&board_id),
+            )?;
+            WordFilterRule::select_by_board(&db_pool, &board_id)
+        })
+        .await
+    }
+
+    /// Find board by ID.
+    #[graphql(guard = "AuthGuard")]
+    async fn board(&self, context: &Context<'_>, board_id: Uuid) -> Result<Option<Board>> {
+        let db_pool = context.data::<DBPool>()?.clone();
+
+        run_blocking(move || Board::select_by_uuid(&db_pool, &board_id)).await
+    }
+
+    /// Boards, oldest first.
+    #[graphql(
+        guard = "AuthGuard",
+        complexity = "list_complexity(first, last, child_complexity)"
+    )]
+    async fn boards(
+        &self,
+        context: &Context<'_>,
+        after: Option<String>,
+        before: Option<String>,
+        first: Option<i32>,
+        last: Option<i32>,
+    ) -> Result<KeysetConnection<Board>> {
+        let db_pool = context.data::<DBPool>()?.clone();
+
+        keyset_connection(after, before, first, last, Board::key, move |page| {
+            Board::page(&db_pool, page)
+        })
         .await
     }
 
     // Search boards by keyword.
     // TODO: better function name
+    #[graphql(
+        guard = "AuthGuard",
+        complexity = "list_complexity(Some(20), None, child_complexity)"
+    )]
     async fn boards_by_keyword(
         &self,
         context: &Context<'_>,
         search_keyword: String,
     ) -> Result<Vec<Board>> {
-        let db_pool = context.data::<DBPool>()?;
-        let token = context.data::<TokenString>()?;
-
-        //verify token
-        verify_token(&db_pool, &token.0)?;
+        let db_pool = context.data::<DBPool>()?.clone();
 
         //do search
-        let index = context.data::<Index>()?;
-        let boards = Board::search_by_keyword(&db_pool, &index, &search_keyword)?;
-
-        Ok(boards)
+        let index = context.data::<Index>()?.clone();
+        run_blocking(move || Board::search_by_keyword(&db_pool, &index, &search_keyword)).await
     }
 
-    async fn threads(&self, context: &Context<'_>, board_id: Uuid) -> Result<Vec<Thread>> {
-        let db_pool = context.data::<DBPool>()?;
-        let token = context.data::<TokenString>()?;
+    /// Child threads of the board, oldest first.
+    #[graphql(
+        guard = "AuthGuard",
+        complexity = "list_complexity(first, last, child_complexity)"
+    )]
+    async fn threads(
+        &self,
+        context: &Context<'_>,
+        board_id: Uuid,
+        after: Option<String>,
+        before: Option<String>,
+        first: Option<i32>,
+        last: Option<i32>,
+    ) -> Result<KeysetConnection<Thread>> {
+        let db_pool = context.data::<DBPool>()?.clone();
 
-        //verify token
-        verify_token(&db_pool, &token.0)?;
-        let threads = Board::child_threads(&db_pool, &board_id);
-        threads
+        keyset_connection(after, before, first, last, Thread::key, move |page| {
+            Board::child_thread_page(&db_pool, &board_id, page)
+        })
+        .await
     }
 
     /// Find thread by ID.
+    #[graphql(guard = "AuthGuard")]
     async fn thread(&self, context: &Context<'_>, thread_id: Uuid) -> Result<Option<Thread>> {
-        let db_pool = context.data::<DBPool>()?;
+        let db_pool = context.data::<DBPool>()?.clone();
 
-        let token = context.data::<TokenString>()?;
-        //verify token
-        verify_token(&db_pool, &token.0)?;
-        Thread::select_by_uuid(&db_pool, &thread_id)
+        run_blocking(move || Thread::select_by_uuid(&db_pool, &thread_id)).await
     }
 
+    /// Threadposts of the thread, ordered by number.
+    /// Cursors are threadpost numbers.
+    #[graphql(
+        guard = "AuthGuard",
+        complexity = "list_complexity(first, last, child_complexity)"
+    )]
     async fn threadposts_by_thread_id(
         &self,
         context: &Context<'_>,
         parent_thread_id: Uuid,
-        start: Option<i32>,
-        end: Option<i32>,
-    ) -> Result<Vec<ThreadPost>> {
-        let db_pool = context.data::<DBPool>()?;
-
-        let token = context.data::<TokenString>()?;
-        //verify token
-        verify_token(&db_pool, &token.0)?;
+        after: Option<String>,
+        before: Option<String>,
+        first: Option<i32>,
+        last: Option<i32>,
+    ) -> Result<KeysetConnection<ThreadPost>> {
+        let db_pool = context.data::<DBPool>()?.clone();
 
-        let start = start.unwrap_or(0);
-        let end = end.unwrap_or(1000);
-        Thread::thread_post_range(&db_pool, &parent_thread_id, start.into(), end.into())
+        keyset_connection(
+            after,
+            before,
+            first,
+            last,
+            |threadpost: &ThreadPost| threadpost.number,
+            move |page| Thread::thread_post_page(&db_pool, &parent_thread_id, page),
+        )
+        .await
     }
 
+    /// Logs, oldest first unless newest_first is true.
+    #[graphql(
+        guard = "AuthGuard",
+        complexity = "list_complexity(first, last, child_complexity)"
+    )]
     async fn logs(
         &self,
         context: &Context<'_>,
-        start: Option<i32>,
-        end: Option<i32>,
-    ) -> Result<Vec<Log>> {
-        let db_pool = context.data::<DBPool>()?;
-
-        let token = context.data::<TokenString>()?;
-        //verify token
-        verify_token(&db_pool, &token.0)?;
-        let start = start.unwrap_or(0);
-        let end = end.unwrap_or(1000);
-        Log::range(&db_pool, start.into(), end.into())
+        after: Option<String>,
+        before: Option<String>,
+        first: Option<i32>,
+        last: Option<i32>,
+        newest_first: Option<bool>,
+    ) -> Result<KeysetConnection<Log>> {
+        let db_pool = context.data::<DBPool>()?.clone();
+        let newest_first = newest_first.unwrap_or(false);
+
+        keyset_connection(after, before, first, last, Log::key, move |page| {
+            Log::page(&db_pool, newest_first, page)
+        })
+        .await
     }
 
+    #[graphql(
+        guard = "AuthGuard",
+        complexity = "list_complexity(Some(k), None, child_complexity)"
+    )]
     async fn search_top_k(
         &self,
         context: &Context<'_>,
@@ -207,15 +504,11 @@ impl QueryRoot {
         search_thread: bool,
         search_threadpost: bool,
     ) -> Result<Vec<SearchResult>> {
-        let db_pool = context.data::<DBPool>()?;
-        let token = context.data::<TokenString>()?;
-        //verify token
-        verify_token(&db_pool, &token.0)?;
-
+        if k <= 0 || k as usize > MAX_PAGE_SIZE {
+            return Err(ErrorCode::Validation.error("Invalid k."));
+        }
         if !search_thread && !search_threadpost {
-            return Err(async_graphql::Error::new(
-                "Invalid search target combination.",
-            ));
+            return Err(ErrorCode::Validation.error("Invalid search target combination."));
         }
 
         let index_reader = context.data::<IndexReader>()?;
@@ -275,20 +568,68 @@ pub struct MutationRoot;
 
 #[Object]
 impl MutationRoot {
-    async fn sign_up(&self, context: &Context<'_>, password: String) -> Result<User> {
-        let db_pool = context.data::<DBPool>()?;
+    /// New CAPTCHA challenge for sign_up, create_thread or post_threadpost.
+    /// Limited per client IP address, clients without one are not limited.
+    async fn issue_captcha(&self, context: &Context<'_>) -> Result<IssuedCaptcha> {
+        let db_pool = context.data::<DBPool>()?.clone();
+        let lifetime = context.data::<Config>()?.captcha.lifetime();
+        let provider = context.data::<Arc<dyn CaptchaProvider>>()?.clone();
+        let flood_control = context.data::<FloodControl>()?;
+        let reservation = match context
+            .data_opt::<ClientInfo>()
+            .and_then(|info| info.ip_address.as_ref())
+        {
+            Some(ip_address) => Some(flood_control.reserve(FloodAction::CaptchaIssue, ip_address)?),
+            None => None,
+        };
+
+        let captcha =
+            run_blocking(move || Captcha::issue(&db_pool, provider.as_ref(), lifetime)).await?;
+        if let Some(reservation) = reservation {
+            reservation.commit();
+        }
+        Ok(captcha)
+    }
 
-        //sign up
-        let created_user = User::create_new(&db_pool, UserType::Normal, &password)?;
+    async fn sign_up(
+        &self,
+        context: &Context<'_>,
+        password: String,
+        captcha: Option<CaptchaAnswer>,
+    ) -> Result<User> {
+        let db_pool = context.data::<DBPool>()?.clone();
+        let captcha_required = context.data::<Config>()?.captcha.registration;
+        //signups are counted per IP address, clients without one are not limited
+        let flood_control = context.data::<FloodControl>()?;
+        let ip_address = context
+            .data_opt::<ClientInfo>()
+            .and_then(|info| info.ip_address.clone());
+        let reservation = match &ip_address {
+            Some(ip_address) => Some(flood_control.reserve(FloodAction::Registration, ip_address)?),
+            None => None,
+        };
+
+        let created_user = run_blocking(move || {
+            Captcha::require(&db_pool, captcha_required, captcha.as_ref())?;
+
+            //sign up
+            let created_user = User::create_new(&db_pool, UserType::Normal, &password)?;
+
+            //log
+            Log::create_new(
+                &db_pool,
+                &format!("{} joined the network...", created_user.id),
+                None,
+                None,
+            )?;
 
-        //log
-        Log::create_new(
-            &db_pool,
-            &format!("{} joined the network...", created_user.id),
-            None,
-            None,
-        )?;
+            Ok(created_user)
+        })
+        .await?;
 
+        if let Some(reservation) = reservation {
+            reservation.commit();
+        }
         Ok(created_user)
     }
 
@@ -297,18 +638,254 @@ impl MutationRoot {
         context: &Context<'_>,
         user_id: String,
         password: String,
-    ) -> Result<String> {
-        let db_pool = context.data::<DBPool>()?;
+    ) -> Result<AuthTokens> {
+        let db_pool = context.data::<DBPool>()?.clone();
+        let token_config = context.data::<TokenConfig>()?.clone();
+
+        let client_info = match context.data_opt::<ClientInfo>() {
+            Some(info) => info.clone(),
+            None => ClientInfo::default(),
+        };
 
         //try login
-        let token = User::login(&db_pool, &user_id, &password)?;
-        Ok(token)
+        run_blocking(move || {
+            User::login(&db_pool, &user_id, &password, &token_config, &client_info)
+        })
+        .await
+    }
+
+    /// Exchange a refresh token for a new access token and refresh token.
+    /// The refresh token can be used only once.
+    async fn refresh_token(
+        &self,
+        context: &Context<'_>,
+        refresh_token: String,
+    ) -> Result<AuthTokens> {
+        let db_pool = context.data::<DBPool>()?.clone();
+        let token_config = context.data::<TokenConfig>()?.clone();
+
+        run_blocking(move || RefreshToken::exchange(&db_pool, &refresh_token, &token_config)).await
+    }
+
+    /// Revoke the current token.
+    /// Return value is dummy
+    #[graphql(guard = "AuthGuard")]
+    async fn logout(&self, context: &Context<'_>) -> Result<i32> {
+        let db_pool = context.data::<DBPool>()?.clone();
+        let user = AuthenticatedUser::from_context(context)?;
+        let user_id = user.user_id.clone();
+        let token_uuid = user.token_uuid;
+
+        run_blocking(move || IssuedToken::revoke(&db_pool, &user_id, &token_uuid)).await?;
+        Ok(0x69)
+    }
+
+    /// Revoke all tokens of the token owner, including the current one.
+    /// Returns the number of revoked tokens.
+    #[graphql(guard = "AuthGuard")]
+    async fn logout_everywhere(&self, context: &Context<'_>) -> Result<i32> {
+        let db_pool = context.data::<DBPool>()?.clone();
+        let user_id = AuthenticatedUser::from_context(context)?.user_id.clone();
+
+        let revoked = run_blocking(move || IssuedToken::revoke_all(&db_pool, &user_id)).await?;
+        Ok(revoked.try_into()?)
+    }
+
+    /// Revoke a session(token) of the user.
+    /// Only for the user and admin.
+    /// Return value is dummy
+    #[graphql(guard = "AuthGuard")]
+    async fn revoke_session(
+        &self,
+        context: &Context<'_>,
+        user_id: String,
+        token_uuid: Uuid,
+    ) -> Result<i32> {
+        let db_pool = context.data::<DBPool>()?.clone();
+        let issuer_user_id = AuthenticatedUser::from_context(context)?.user_id.clone();
+
+        run_blocking(move || {
+            check_self_or_admin(&db_pool, &issuer_user_id, &user_id)?;
+            if !IssuedToken::revoke(&db_pool, &user_id, &token_uuid)? {
+                return Err(ErrorCode::NotFound.error("Session does not exist"));
+            }
+            Ok(0x69)
+        })
+        .await
+    }
+
+    /// Mark notifications of the token owner read, all unread ones if notification_uuids is not given.
+    /// Returns the number of marked notifications.
+    #[graphql(guard = "AuthGuard")]
+    async fn mark_notifications_read(
+        &self,
+        context: &Context<'_>,
+        notification_uuids: Option<Vec<Uuid>>,
+    ) -> Result<i32> {
+        let db_pool = context.data::<DBPool>()?.clone();
+        let user_id = AuthenticatedUser::from_context(context)?.user_id.clone();
+
+        let marked = run_blocking(move || {
+            Notification::mark_read(&db_pool, &user_id, notification_uuids.as_deref())
+        })
+        .await?;
+        Ok(marked.try_into()?)
+    }
+
+    /// Watch the thread, replies after the current last threadpost are new.
+    /// Return value is dummy
+    #[graphql(guard = "AuthGuard")]
+    async fn watch_thread(&self, context: &Context<'_>, thread_id: Uuid) -> Result<i32> {
+        let db_pool = context.data::<DBPool>()?.clone();
+        let user_id = AuthenticatedUser::from_context(context)?.user_id.clone();
+
+        run_blocking(move || ThreadWatch::watch(&db_pool, &user_id, &thread_id)).await?;
+        Ok(0x69)
+    }
+
+    /// Return value is dummy
+    #[graphql(guard = "AuthGuard")]
+    async fn unwatch_thread(&self, context: &Context<'_>, thread_id: Uuid) -> Result<i32> {
+        let db_pool = context.data::<DBPool>()?.clone();
+        let user_id = AuthenticatedUser::from_context(context)?.user_id.clone();
+
+        run_blocking(move || ThreadWatch::unwatch(&db_pool, &user_id, &thread_id)).await?;
+        Ok(0x69)
+    }
+
+    /// Mark the threadposts numbered first_number to last_number of the watched thread read.(the shown range)
+    /// The read marker moves forward only if no unread threadpost is before first_number, skipped ones stay unread.
+    /// Returns whether the token owner watches the thread.(nothing is marked if not)
+    #[graphql(guard = "AuthGuard")]
+    async fn mark_thread_read(
+        &self,
+        context: &Context<'_>,
+        thread_id: Uuid,
+        first_number: i32,
+        last_number: i32,
+    ) -> Result<bool> {
+        let db_pool = context.data::<DBPool>()?.clone();
+        let user_id = AuthenticatedUser::from_context(context)?.user_id.clone();
+
+        run_blocking(move || {
+            ThreadWatch::mark_read(&db_pool, &user_id, &thread_id, first_number, last_number)
+        })
+        .await
+    }
+
+    /// Delete the account of the token owner.(requires password)
+    /// Threadposts are anonymized or deleted based on the site policy.
+    /// Return value is dummy
+    #[graphql(guard = "AuthGuard")]
+    async fn delete_my_account(&self, context: &Context<'_>, password: String) -> Result<i32> {
+        let db_pool = context.data::<DBPool>()?.clone();
+        let user_id = AuthenticatedUser::from_context(context)?.user_id.clone();
+
+        let policy = *context.data::<ContentRemovalPolicy>()?;
+        let mut index_writer = context
+            .data::<Arc<RwLock<IndexWriter>>>()?
+            .clone()
+            .write_owned()
+            .await;
+        run_blocking(move || {
+            User::remove_account(&db_pool, &mut index_writer, &user_id, &password, policy)?;
+            drop(index_writer);
+
+            //log
+            Log::create_new(
+                &db_pool,
+                &format!("{} left the network...", user_id),
+                None,
+                None,
+            )?;
+            Ok(0x69)
+        })
+        .await
+    }
+
+    /// Change the password of the token owner.(requires the current password)
+    /// All tokens of the user including the current one are revoked.
+    /// Return value is dummy
+    #[graphql(guard = "AuthGuard")]
+    async fn change_password(
+        &self,
+        context: &Context<'_>,
+        old_password: String,
+        new_password: String,
+    ) -> Result<i32> {
+        let db_pool = context.data::<DBPool>()?.clone();
+        let user_id = AuthenticatedUser::from_context(context)?.user_id.clone();
+
+        run_blocking(move || {
+            User::change_password(&db_pool, &user_id, &old_password, &new_password)
+        })
+        .await?;
+        Ok(0x69)
+    }
+
+    /// Only for admin
+    /// Issue a one-time password reset token for the user.
+    /// Returns the reset token.(expires after 24 hours)
+    #[graphql(guard = "RoleGuard::new(UserType::Admin)")]
+    async fn issue_password_reset_token(
+        &self,
+        context: &Context<'_>,
+        user_id: String,
+    ) -> Result<String> {
+        let db_pool = context.data::<DBPool>()?.clone();
+        let issuer_user_id = AuthenticatedUser::from_context(context)?.user_id.clone();
+
+        run_blocking(move || {
+            require(
+                &db_pool,
+                &issuer_user_id,
+                Capability::IssuePasswordResetToken,
+                Scope::Global,
+            )?;
+
+            let db_connection = db_pool.get()?;
+            let reset_token = db_connection.build_transaction().run::<_, Error, _>(|| {
+                let reset_token =
+                    PasswordResetToken::issue(&db_connection, &issuer_user_id, &user_id)?;
+                ModerationAction::record(
+                    &db_connection,
+                    &issuer_user_id,
+                    ModerationActionType::IssuePasswordResetToken,
+                    &user_id,
+                    None,
+                    None,
+                )?;
+                Ok(reset_token)
+            })?;
+            Ok(reset_token)
+        })
+        .await
+    }
+
+    /// Reset the password with a reset token issued by admin.(no login required)
+    /// All tokens of the user are revoked.
+    /// Return value is dummy
+    async fn reset_password(
+        &self,
+        context: &Context<'_>,
+        user_id: String,
+        reset_token: String,
+        new_password: String,
+    ) -> Result<i32> {
+        let db_pool = context.data::<DBPool>()?.clone();
+
+        run_blocking(move || {
+            PasswordResetToken::reset_password(&db_pool, &user_id, &reset_token, &new_password)
+        })
+        .await?;
+        Ok(0x69)
     }
 
     /// Create a new board.
     /// Returns new board's id.
     /// TODO: maybe return error when indexing is failed(also delete the created board)
     /*
+    #[graphql(guard = "AuthGuard")]
     async fn create_board(
         &self,
         context: &Context<'_>,
@@ -318,10 +895,7 @@ impl MutationRoot {
         use tantivy::doc;
 
         let db_pool = context.data::<DBPool>()?;
-        let token = context.data::<TokenString>()?;
-
-        //verify token
-        let user_id = verify_token(&db_pool, &token.0)?;
+        let user_id = AuthenticatedUser::from_context(context)?.user_id.clone();
 
         let new_board = Board::create_new(&db_pool, &user_id, &board_name, &board_description)?;
 
@@ -346,166 +920,853 @@ impl MutationRoot {
         Ok(new_board.uuid)
     }*/
 
+    #[graphql(guard = "AuthGuard")]
     async fn create_thread(
         &self,
         context: &Context<'_>,
         thread_title: String,
         parent_board_uuid: Uuid,
         first_post_text: String,
+        captcha: Option<CaptchaAnswer>,
     ) -> Result<Uuid> {
-        let db_pool = context.data::<DBPool>()?;
-        let token = context.data::<TokenString>()?;
-        //verify token
-        let user_id = verify_token(&db_pool, &token.0)?;
-
-        let index_writer = context.data::<Arc<RwLock<IndexWriter>>>()?;
-
-        let new_thread;
+        let db_pool = context.data::<DBPool>()?.clone();
+        let user_id = AuthenticatedUser::from_context(context)?.user_id.clone();
+        let captcha_required = context
+            .data::<Config>()?
+            .captcha
+            .required_for_thread_creation(&parent_board_uuid);
+        let flood_control = context.data::<FloodControl>()?;
+        let reservation = flood_control.reserve(FloodAction::ThreadCreation, &user_id)?;
+        let content_filters = context.data::<Arc<ContentFilterChain>>()?.clone();
+
+        //before taking the index writer, wrong answers shouldn't block others
         {
-            let mut index_writer = index_writer.write().await;
+            let db_pool = db_pool.clone();
+            run_blocking(move || Captcha::require(&db_pool, captcha_required, captcha.as_ref()))
+                .await?;
+        }
+
+        let mut index_writer = context
+            .data::<Arc<RwLock<IndexWriter>>>()?
+            .clone()
+            .write_owned()
+            .await;
+        let new_thread_uuid = run_blocking(move || {
             //create new thread
-            new_thread = Thread::create_new(
+            let new_thread = Thread::create_new(
                 &db_pool,
                 &mut index_writer,
+                &content_filters,
                 &user_id,
                 &thread_title,
                 &parent_board_uuid,
                 &first_post_text,
             )?;
-        }
+            drop(index_writer);
 
-        let thread_link = format!("/thread/{}", new_thread.uuid);
-        //log
-        Log::create_new(
-            &db_pool,
-            &format!("{} created a new thread.", user_id),
-            Some(&thread_link),
-            Some(&new_thread.title),
-        )?;
+            let thread_link = format!("/thread/{}", new_thread.uuid);
+            //log
+            Log::create_new(
+                &db_pool,
+                &format!("{} created a new thread.", user_id),
+                Some(&thread_link),
+                Some(&new_thread.title),
+            )?;
 
-        Ok(new_thread.uuid)
+            Ok(new_thread.uuid)
+        })
+        .await?;
+
+        reservation.commit();
+        Ok(new_thread_uuid)
     }
 
     // i32 is dummy
-    /// Only for admin and moderator
-    async fn remove_thread(&self, context: &Context<'_>, thread_uuid: Uuid) -> Result<i32> {
-        let db_pool = context.data::<DBPool>()?;
-        let token = context.data::<TokenString>()?;
-        //verify token
-
-        let user_id = verify_token(&db_pool, &token.0)?;
-        //check user type
-        let user = User::select_by_user_id(&db_pool, &user_id)?;
-        if user.is_none() {
-            return Err(async_graphql::Error::new("User does not exist"));
-        }
-        let user = user.unwrap();
-        let user_type = UserType::from_i32(user.user_type)?;
-        if user_type != UserType::Admin && user_type != UserType::Moderator {
-            return Err(async_graphql::Error::new("Not allowed."));
-        }
+    /// Only for admin and moderators of the board
+    #[graphql(guard = "AuthGuard")]
+    async fn remove_thread(
+        &self,
+        context: &Context<'_>,
+        thread_uuid: Uuid,
+        reason: Option<String>,
+    ) -> Result<i32> {
+        let db_pool = context.data::<DBPool>()?.clone();
+        let user_id = AuthenticatedUser::from_context(context)?.user_id.clone();
+        let index_writer = context.data::<Arc<RwLock<IndexWriter>>>()?.clone();
+
+        //also used as snapshot for the audit log
+        let thread = {
+            let db_pool = db_pool.clone();
+            let user_id = user_id.clone();
+            run_blocking(move || {
+                let thread = match Thread::select_by_uuid(&db_pool, &thread_uuid)? {
+                    Some(t) => t,
+                    None => return Err(ErrorCode::NotFound.error("Invalid thread uuid.")),
+                };
+                require(
+                    &db_pool,
+                    &user_id,
+                    Capability::RemoveThread,
+                    Scope::Board(&thread.parent_board_id),
+                )?;
+                Ok(thread)
+            })
+            .await?
+        };
+
+        let mut index_writer = index_writer.write_owned().await;
+        run_blocking(move || {
+            let db_connection = db_pool.get()?;
+            db_connection.build_transaction().run::<_, Error, _>(|| {
+                //recorded first, the removal commits the index
+                ModerationAction::record(
+                    &db_connection,
+                    &user_id,
+                    ModerationActionType::RemoveThread,
+                    &thread_uuid.to_string(),
+                    reason.as_deref(),
+                    Some(
+                        format!(
+                            "creator: {}\ntitle: {}",
+                            thread.creator_user_id, thread.title
+                        )
+                        .as_str(),
+                    ),
+                )?;
+                Thread::remove_by_uuid(&db_connection, &mut index_writer, &thread_uuid)
+            })?;
+            drop(index_writer);
+            Ok(0x69) //return dummy
+        })
+        .await
+    }
 
-        assert!(user_type == UserType::Admin || user_type == UserType::Moderator);
-        let index_writer = context.data::<Arc<RwLock<IndexWriter>>>()?;
-        {
-            let mut index_writer = index_writer.write().await;
-            Thread::remove_by_uuid(&db_pool, &mut index_writer, &thread_uuid)?;
-        }
-        Ok(0x69) //return dummy
+    // i32 is dummy
+    /// Only for admin and moderators of the board
+    /// Locked threads reject new threadposts, including held ones being approved.
+    #[graphql(guard = "AuthGuard")]
+    async fn lock_thread(
+        &self,
+        context: &Context<'_>,
+        thread_uuid: Uuid,
+        locked: bool,
+        reason: Option<String>,
+    ) -> Result<i32> {
+        let db_pool = context.data::<DBPool>()?.clone();
+        let user_id = AuthenticatedUser::from_context(context)?.user_id.clone();
+
+        run_blocking(move || {
+            let thread = match Thread::select_by_uuid(&db_pool, &thread_uuid)? {
+                Some(t) => t,
+                None => return Err(ErrorCode::NotFound.error("Invalid thread uuid.")),
+            };
+            require(
+                &db_pool,
+                &user_id,
+                Capability::LockThread,
+                Scope::Board(&thread.parent_board_id),
+            )?;
+
+            let action_type = if locked {
+                ModerationActionType::LockThread
+            } else {
+                ModerationActionType::UnlockThread
+            };
+            let db_connection = db_pool.get()?;
+            db_connection.build_transaction().run::<_, Error, _>(|| {
+                Thread::set_locked(&db_connection, &thread_uuid, locked)?;
+                ModerationAction::record(
+                    &db_connection,
+                    &user_id,
+                    action_type,
+                    &thread_uuid.to_string(),
+                    reason.as_deref(),
+                    None,
+                )?;
+                Ok(())
+            })?;
+            Ok(0x69) //return dummy
+        })
+        .await
     }
 
+    #[graphql(guard = "AuthGuard")]
     async fn post_threadpost(
         &self,
         context: &Context<'_>,
         thread_uuid: Uuid,
         post_body: String,
+        captcha: Option<CaptchaAnswer>,
     ) -> Result<Uuid> {
-        let db_pool = context.data::<DBPool>()?;
-        let token = context.data::<TokenString>()?;
-
-        //verify token
-        let poster_user_id = verify_token(&db_pool, &token.0)?;
+        let db_pool = context.data::<DBPool>()?.clone();
+        let poster_user_id = AuthenticatedUser::from_context(context)?.user_id.clone();
+        let captcha_config = context.data::<Config>()?.captcha.clone();
+        let flood_control = context.data::<FloodControl>()?;
+        let reservation = flood_control.reserve(FloodAction::Posting, &poster_user_id)?;
+        let content_filters = context.data::<Arc<ContentFilterChain>>()?.clone();
+
+        //before taking the index writer, wrong answers shouldn't block others
+        {
+            let db_pool = db_pool.clone();
+            run_blocking(move || {
+                let parent_thread = match Thread::select_by_uuid(&db_pool, &thread_uuid)? {
+                    Some(t) => t,
+                    None => return Err(ErrorCode::NotFound.error("Invalid thread uuid.")),
+                };
+                Captcha::require(
+                    &db_pool,
+                    captcha_config.required_for_posting(&parent_thread.parent_board_id),
+                    captcha.as_ref(),
+                )
+            })
+            .await?;
+        }
 
-        let index_writer = context.data::<Arc<RwLock<IndexWriter>>>()?;
+        let mut index_writer = context
+            .data::<Arc<RwLock<IndexWriter>>>()?
+            .clone()
+            .write_owned()
+            .await;
         //TODO: check whether the parent thread is already full or not.
 
         //create new thread post
-        let new_threadpost;
-        {
-            let mut index_writer = index_writer.write().await;
-            new_threadpost = ThreadPost::create_new(
+        let created_threadpost = run_blocking(move || {
+            ThreadPost::create_new(
                 &db_pool,
                 &mut index_writer,
+                &content_filters,
                 &poster_user_id,
                 &thread_uuid,
                 &post_body,
-            )?;
-        }
-        //insert?
+            )
+        })
+        .await?;
 
-        Ok(new_threadpost.uuid)
+        //only posted threadposts count, the reservation is released on errors
+        reservation.commit();
+        Ok(created_threadpost.uuid)
     }
 
     // i32 is dummy
-    /// Only for admin and moderator
-    async fn remove_threadpost(&self, context: &Context<'_>, threadpost_uuid: Uuid) -> Result<i32> {
-        let db_pool = context.data::<DBPool>()?;
-        let token = context.data::<TokenString>()?;
-        //verify token
-
-        let user_id = verify_token(&db_pool, &token.0)?;
-        //check user type
-        let user = User::select_by_user_id(&db_pool, &user_id)?;
-        if user.is_none() {
-            return Err(async_graphql::Error::new("User does not exist"));
-        }
-        let user = user.unwrap();
-        let user_type = UserType::from_i32(user.user_type)?;
-        if user_type != UserType::Admin && user_type != UserType::Moderator {
-            return Err(async_graphql::Error::new("Not allowed."));
-        }
-
-        assert!(user_type == UserType::Admin || user_type == UserType::Moderator);
-        let index_writer = context.data::<Arc<RwLock<IndexWriter>>>()?;
-        {
-            let mut index_writer = index_writer.write().await;
-            ThreadPost::remove_by_uuid(&db_pool, &mut index_writer, &threadpost_uuid)?;
-        }
-        Ok(0x69) //return dummy
+    /// Only for admin and moderators of the board
+    #[graphql(guard = "AuthGuard")]
+    async fn remove_threadpost(
+        &self,
+        context: &Context<'_>,
+        threadpost_uuid: Uuid,
+        reason: Option<String>,
+    ) -> Result<i32> {
+        let db_pool = context.data::<DBPool>()?.clone();
+        let user_id = AuthenticatedUser::from_context(context)?.user_id.clone();
+        let index_writer = context.data::<Arc<RwLock<IndexWriter>>>()?.clone();
+
+        //also used as snapshot for the audit log
+        let threadpost = {
+            let db_pool = db_pool.clone();
+            let user_id = user_id.clone();
+            run_blocking(move || {
+                let threadpost = ThreadPost::select_by_uuid(&db_pool, &threadpost_uuid)?;
+                let parent_thread =
+                    match Thread::select_by_uuid(&db_pool, &threadpost.parent_thread_id)? {
+                        Some(t) => t,
+                        None => return Err(ErrorCode::NotFound.error("Invalid thread uuid.")),
+                    };
+                require(
+                    &db_pool,
+                    &user_id,
+                    Capability::RemoveThreadpost,
+                    Scope::Board(&parent_thread.parent_board_id),
+                )?;
+                Ok(threadpost)
+            })
+            .await?
+        };
+
+        let mut index_writer = index_writer.write_owned().await;
+        run_blocking(move || {
+            let db_connection = db_pool.get()?;
+            db_connection.build_transaction().run::<_, Error, _>(|| {
+                //recorded first, the removal commits the index
+                ModerationAction::record(
+                    &db_connection,
+                    &user_id,
+                    ModerationActionType::RemoveThreadpost,
+                    &threadpost_uuid.to_string(),
+                    reason.as_deref(),
+                    Some(
+                        format!(
+                            "poster: {}\nthread: {}\nnumber: {}\n\n{}",
+                            threadpost.poster_user_id,
+                            threadpost.parent_thread_id,
+                            threadpost.number,
+                            threadpost.body_text
+                        )
+                        .as_str(),
+                    ),
+                )?;
+                ThreadPost::remove_by_uuid(&db_connection, &mut index_writer, &threadpost_uuid)
+            })?;
+            drop(index_writer);
+            Ok(0x69) //return dummy
+        })
+        .await
     }
 
     /// Only for admin
     /// Return value is dummy
+    #[graphql(guard = "RoleGuard::new(UserType::Admin)")]
     async fn change_user_type(
         &self,
         context: &Context<'_>,
         user_id: String,
         new_type: i32,
+        reason: Option<String>,
     ) -> Result<i32> {
-        let db_pool = context.data::<DBPool>()?;
-        let token = context.data::<TokenString>()?;
-        //verify token
+        let db_pool = context.data::<DBPool>()?.clone();
+        let issuer_user_id = AuthenticatedUser::from_context(context)?.user_id.clone();
 
-        let issuer_user_id = verify_token(&db_pool, &token.0)?;
-
-        //check issuer user type
-        let issuer_user = User::select_by_user_id(&db_pool, &issuer_user_id)?;
-        if issuer_user.is_none() {
-            return Err(async_graphql::Error::new("User does not exist"));
-        }
-        let issuer_user = issuer_user.unwrap();
-        let user_type = UserType::from_i32(issuer_user.user_type)?;
-        if user_type != UserType::Admin {
-            return Err(async_graphql::Error::new("Not allowed."));
-        }
-        assert_eq!(user_type, UserType::Admin);
         let new_type = UserType::from_i32(new_type)?;
         if new_type == UserType::Admin {
-            return Err(async_graphql::Error::new("Not allowed!"));
+            return Err(ErrorCode::Forbidden.error("Not allowed!"));
         }
 
-        User::change_type(&db_pool, &user_id, new_type)?;
+        run_blocking(move || {
+            require(
+                &db_pool,
+                &issuer_user_id,
+                Capability::ChangeUserType,
+                Scope::Global,
+            )?;
 
-        Ok(0x69)
+            let snapshot = format!("new type: {:?}", new_type);
+            let db_connection = db_pool.get()?;
+            db_connection.build_transaction().run::<_, Error, _>(|| {
+                User::change_type(&db_connection, &user_id, new_type)?;
+                ModerationAction::record(
+                    &db_connection,
+                    &issuer_user_id,
+                    ModerationActionType::ChangeUserType,
+                    &user_id,
+                    reason.as_deref(),
+                    Some(snapshot.as_str()),
+                )?;
+                Ok(())
+            })?;
+            Ok(0x69)
+        })
+        .await
+    }
+
+    /// Only for admin and moderator
+    /// Suspend the user for the duration.(hours)
+    /// Return value is dummy
+    #[graphql(guard = "RoleGuard::new(UserType::Moderator)")]
+    async fn suspend_user(
+        &self,
+        context: &Context<'_>,
+        user_id: String,
+        reason: String,
+        duration_hours: i32,
+    ) -> Result<i32> {
+        let db_pool = context.data::<DBPool>()?.clone();
+        let issuer_user_id = AuthenticatedUser::from_context(context)?.user_id.clone();
+
+        run_blocking(move || {
+            check_can_moderate(&db_pool, &issuer_user_id, &user_id)?;
+
+            if duration_hours <= 0 {
+                return Err(ErrorCode::Validation.error("Invalid duration."));
+            }
+            let ends_at = chrono::Utc::now() + chrono::Duration::hours(duration_hours as i64);
+            let db_connection = db_pool.get()?;
+            db_connection.build_transaction().run::<_, Error, _>(|| {
+                Ban::create_new(
+                    &db_connection,
+                    &issuer_user_id,
+                    &user_id,
+                    UserStatus::Suspended,
+                    &reason,
+                    Some(ends_at),
+                )?;
+                ModerationAction::record(
+                    &db_connection,
+                    &issuer_user_id,
+                    ModerationActionType::SuspendUser,
+                    &user_id,
+                    Some(reason.as_str()),
+                    Some(format!("until: {}", ends_at).as_str()),
+                )?;
+                Ok(())
+            })?;
+            Log::create_new(
+                &db_pool,
+                &format!("{} was suspended for {} hours.", user_id, duration_hours),
+                None,
+                None,
+            )?;
+            Ok(0x69)
+        })
+        .await
+    }
+
+    /// Only for admin and moderator
+    /// Ban the user for the duration.(hours) The ban is permanent if the duration is null.
+    /// Return value is dummy
+    #[graphql(guard = "RoleGuard::new(UserType::Moderator)")]
+    async fn ban_user(
+        &self,
+        context: &Context<'_>,
+        user_id: String,
+        reason: String,
+        duration_hours: Option<i32>,
+    ) -> Result<i32> {
+        let db_pool = context.data::<DBPool>()?.clone();
+        let issuer_user_id = AuthenticatedUser::from_context(context)?.user_id.clone();
+
+        run_blocking(move || {
+            check_can_moderate(&db_pool, &issuer_user_id, &user_id)?;
+
+            let ends_at = match duration_hours {
+                Some(h) if h <= 0 => return Err(ErrorCode::Validation.error("Invalid duration.")),
+                Some(h) => Some(chrono::Utc::now() + chrono::Duration::hours(h as i64)),
+                None => None,
+            };
+            let db_connection = db_pool.get()?;
+            db_connection.build_transaction().run::<_, Error, _>(|| {
+                Ban::create_new(
+                    &db_connection,
+                    &issuer_user_id,
+                    &user_id,
+                    UserStatus::Banned,
+                    &reason,
+                    ends_at,
+                )?;
+                ModerationAction::record(
+                    &db_connection,
+                    &issuer_user_id,
+                    ModerationActionType::BanUser,
+                    &user_id,
+                    Some(reason.as_str()),
+                    Some(
+                        match ends_at {
+                            Some(ends_at) => format!("until: {}", ends_at),
+                            None => "permanent".to_string(),
+                        }
+                        .as_str(),
+                    ),
+                )?;
+                Ok(())
+            })?;
+            Log::create_new(&db_pool, &format!("{} was banned.", user_id), None, None)?;
+            Ok(0x69)
+        })
+        .await
+    }
+
+    /// Only for admin and moderator
+    /// Lift the suspension or ban of the user.
+    /// Return value is dummy
+    #[graphql(guard = "RoleGuard::new(UserType::Moderator)")]
+    async fn lift_ban(&self, context: &Context<'_>, user_id: String) -> Result<i32> {
+        let db_pool = context.data::<DBPool>()?.clone();
+        let issuer_user_id = AuthenticatedUser::from_context(context)?.user_id.clone();
+
+        run_blocking(move || {
+            check_can_moderate(&db_pool, &issuer_user_id, &user_id)?;
+
+            let db_connection = db_pool.get()?;
+            db_connection.build_transaction().run::<_, Error, _>(|| {
+                Ban::lift(&db_connection, &issuer_user_id, &user_id)?;
+                ModerationAction::record(
+                    &db_connection,
+                    &issuer_user_id,
+                    ModerationActionType::LiftBan,
+                    &user_id,
+                    None,
+                    None,
+                )?;
+                Ok(())
+            })?;
+            Ok(0x69)
+        })
+        .await
+    }
+
+    /// Only for admin
+    /// Assign the user as a moderator of the board.
+    /// Return value is dummy
+    #[graphql(guard = "RoleGuard::new(UserType::Admin)")]
+    async fn assign_board_moderator(
+        &self,
+        context: &Context<'_>,
+        board_id: Uuid,
+        user_id: String,
+    ) -> Result<i32> {
+        let db_pool = context.data::<DBPool>()?.clone();
+        let issuer_user_id = AuthenticatedUser::from_context(context)?.user_id.clone();
+
+        run_blocking(move || {
+            require(
+                &db_pool,
+                &issuer_user_id,
+                Capability::ManageBoardModerators,
+                Scope::Board(&board_id),
+            )?;
+
+            let db_connection = db_pool.get()?;
+            db_connection.build_transaction().run::<_, Error, _>(|| {
+                BoardModerator::assign(&db_connection, &issuer_user_id, &board_id, &user_id)?;
+                ModerationAction::record(
+                    &db_connection,
+                    &issuer_user_id,
+                    ModerationActionType::AssignBoardModerator,
+                    &user_id,
+                    None,
+                    Some(format!("board: {}", board_id).as_str()),
+                )?;
+                Ok(())
+            })?;
+            Ok(0x69)
+        })
+        .await
+    }
+
+    /// Only for admin
+    /// Remove the user from moderators of the board.
+    /// Return value is dummy
+    #[graphql(guard = "RoleGuard::new(UserType::Admin)")]
+    async fn revoke_board_moderator(
+        &self,
+        context: &Context<'_>,
+        board_id: Uuid,
+        user_id: String,
+    ) -> Result<i32> {
+        let db_pool = context.data::<DBPool>()?.clone();
+        let issuer_user_id = AuthenticatedUser::from_context(context)?.user_id.clone();
+
+        run_blocking(move || {
+            require(
+                &db_pool,
+                &issuer_user_id,
+                Capability::ManageBoardModerators,
+                Scope::Board(&board_id),
+            )?;
+
+            let db_connection = db_pool.get()?;
+            db_connection.build_transaction().run::<_, Error, _>(|| {
+                BoardModerator::revoke(&db_connection, &board_id, &user_id)?;
+                ModerationAction::record(
+                    &db_connection,
+                    &issuer_user_id,
+                    ModerationActionType::RevokeBoardModerator,
+                    &user_id,
+                    None,
+                    Some(format!("board: {}", board_id).as_str()),
+                )?;
+                Ok(())
+            })?;
+            Ok(0x69)
+        })
+        .await
+    }
+
+    /// Only for admin and moderators of the board
+    /// Add a banned word(or a regex if is_regex) to the board.
+    /// action is 1 to reject matching threadposts, 2 to hold them for review.
+    /// Returns the new rule's uuid.
+    #[graphql(guard = "AuthGuard")]
+    async fn add_content_filter_rule(
+        &self,
+        context: &Context<'_>,
+        board_id: Uuid,
+        pattern: String,
+        is_regex: bool,
+        action: i32,
+    ) -> Result<Uuid> {
+        let db_pool = context.data::<DBPool>()?.clone();
+        let issuer_user_id = AuthenticatedUser::from_context(context)?.user_id.clone();
+        let action = FilterAction::from_i32(action)?;
+
+        run_blocking(move || {
+            require(
+                &db_pool,
+                &issuer_user_id,
+                Capability::ManageContentFilters,
+                Scope::Board(&board_id),
+            )?;
+
+            let db_connection = db_pool.get()?;
+            let rule = db_connection.build_transaction().run::<_, Error, _>(|| {
+                let rule = ContentFilterRule::create_new(
+                    &db_connection,
+                    &issuer_user_id,
+                    &board_id,
+                    &pattern,
+                    is_regex,
+                    action,
+                )?;
+                ModerationAction::record(
+                    &db_connection,
+                    &issuer_user_id,
+                    ModerationActionType::AddContentFilterRule,
+                    &rule.uuid.to_string(),
+                    None,
+                    Some(
+                        format!(
+                            "board: {}\npattern: {}\nregex: {}\naction: {:?}",
+                            board_id, pattern, is_regex, action
+                        )
+                        .as_str(),
+                    ),
+                )?;
+                Ok(rule)
+            })?;
+            Ok(rule.uuid)
+        })
+        .await
+    }
+
+    /// Only for admin and moderators of the board
+    /// Return value is dummy
+    #[graphql(guard = "AuthGuard")]
+    async fn remove_content_filter_rule(
+        &self,
+        context: &Context<'_>,
+        rule_uuid: Uuid,
+    ) -> Result<i32> {
+        let db_pool = context.data::<DBPool>()?.clone();
+        let issuer_user_id = AuthenticatedUser::from_context(context)?.user_id.clone();
+
+        run_blocking(move || {
+            let rule = match ContentFilterRule::select_by_uuid(&db_pool, &rule_uuid)? {
+                Some(r) => r,
+                None => return Err(ErrorCode::NotFound.error("Invalid rule uuid.")),
+            };
+            require(
+                &db_pool,
+                &issuer_user_id,
+                Capability::ManageContentFilters,
+                Scope::Board(&rule.board_id),
+            )?;
+
+            let db_connection = db_pool.get()?;
+            db_connection.build_transaction().run::<_, Error, _>(|| {
+                ContentFilterRule::remove_by_uuid(&db_connection, &rule_uuid)?;
+                ModerationAction::record(
+                    &db_connection,
+                    &issuer_user_id,
+                    ModerationActionType::RemoveContentFilterRule,
+                    &rule_uuid.to_string(),
+                    None,
+                    Some(
+                        format!(
+                            "board: {}\npattern: {}\nregex: {}",
+                            rule.board_id, rule.pattern, rule.is_regex
+                        )
+                        .as_str(),
+                    ),
+                )?;
+                Ok(())
+            })?;
+            Ok(0x69)
+        })
+        .await
+    }
+
+    /// Only for admin and moderators of the board
+    /// Post the held threadpost as its poster, held new threads are created with it as the first post.
+    /// Returns the new threadpost's uuid.
+    #[graphql(guard = "AuthGuard")]
+    async fn approve_held_threadpost(
+        &self,
+        context: &Context<'_>,
+        held_threadpost_uuid: Uuid,
+    ) -> Result<Uuid> {
+        let db_pool = context.data::<DBPool>()?.clone();
+        let issuer_user_id = AuthenticatedUser::from_context(context)?.user_id.clone();
+        let index_writer = context.data::<Arc<RwLock<IndexWriter>>>()?.clone();
+
+        let held = {
+            let db_pool = db_pool.clone();
+            let issuer_user_id = issuer_user_id.clone();
+            run_blocking(move || {
+                let held = match HeldThreadPost::select_by_uuid(&db_pool, &held_threadpost_uuid)? {
+                    Some(h) => h,
+                    None => return Err(ErrorCode::NotFound.error("Invalid held threadpost uuid.")),
+                };
+                require(
+                    &db_pool,
+                    &issuer_user_id,
+                    Capability::ReviewHeldThreadposts,
+                    Scope::Board(&held.board_id),
+                )?;
+                Ok(held)
+            })
+            .await?
+        };
+
+        let mut index_writer = index_writer.write_owned().await;
+        run_blocking(move || {
+            let db_connection = db_pool.get()?;
+            //the held threadpost is put back if this fails
+            let threadpost = db_connection.build_transaction().run::<_, Error, _>(|| {
+                let threadpost =
+                    ThreadPost::approve_held(&db_pool, &db_connection, &mut index_writer, &held)?;
+                ModerationAction::record(
+                    &db_connection,
+                    &issuer_user_id,
+                    ModerationActionType::ApproveHeldThreadpost,
+                    &threadpost.uuid.to_string(),
+                    None,
+                    Some(held.snapshot().as_str()),
+                )?;
+                Ok(threadpost)
+            })?;
+            drop(index_writer);
+            Ok(threadpost.uuid)
+        })
+        .await
+    }
+
+    /// Only for admin and moderators of the board
+    /// Delete the held threadpost without posting it.
+    /// Return value is dummy
+    #[graphql(guard = "AuthGuard")]
+    async fn discard_held_threadpost(
+        &self,
+        context: &Context<'_>,
+        held_threadpost_uuid: Uuid,
+        reason: Option<String>,
+    ) -> Result<i32> {
+        let db_pool = context.data::<DBPool>()?.clone();
+        let issuer_user_id = AuthenticatedUser::from_context(context)?.user_id.clone();
+
+        run_blocking(move || {
+            let held = match HeldThreadPost::select_by_uuid(&db_pool, &held_threadpost_uuid)? {
+                Some(h) => h,
+                None => return Err(ErrorCode::NotFound.error("Invalid held threadpost uuid.")),
+            };
+            require(
+                &db_pool,
+                &issuer_user_id,
+                Capability::ReviewHeldThreadposts,
+                Scope::Board(&held.board_id),
+            )?;
+
+            let db_connection = db_pool.get()?;
+            db_connection.build_transaction().run::<_, Error, _>(|| {
+                HeldThreadPost::remove_by_uuid(&db_connection, &held_threadpost_uuid)?;
+                ModerationAction::record(
+                    &db_connection,
+                    &issuer_user_id,
+                    ModerationActionType::DiscardHeldThreadpost,
+                    &held_threadpost_uuid.to_string(),
+                    reason.as_deref(),
+                    Some(held.snapshot().as_str()),
+                )?;
+                Ok(())
+            })?;
+            Ok(0x69)
+        })
+        .await
+    }
+
+    /// Only for admin and moderators of the board
+    /// Add a find-and-replace rule to the board, applied to new titles and threadposts.
+    /// pattern is a word matched case-insensitively, or a regex if is_regex.
+    /// Returns the new rule's uuid.
+    #[graphql(guard = "AuthGuard")]
+    async fn add_word_filter_rule(
+        &self,
+        context: &Context<'_>,
+        board_id: Uuid,
+        pattern: String,
+        is_regex: bool,
+        replacement: String,
+    ) -> Result<Uuid> {
+        let db_pool = context.data::<DBPool>()?.clone();
+        let issuer_user_id = AuthenticatedUser::from_context(context)?.user_id.clone();
+
+        run_blocking(move || {
+            require(
+                &db_pool,
+                &issuer_user_id,
+                Capability::ManageWordFilters,
+                Scope::Board(&board_id),
+            )?;
+
+            let db_connection = db_pool.get()?;
+            let rule = db_connection.build_transaction().run::<_, Error, _>(|| {
+                let rule = WordFilterRule::create_new(
+                    &db_connection,
+                    &issuer_user_id,
+                    &board_id,
+                    &pattern,
+                    is_regex,
+                    &replacement,
+                )?;
+                ModerationAction::record(
+                    &db_connection,
+                    &issuer_user_id,
+                    ModerationActionType::AddWordFilterRule,
+                    &rule.uuid.to_string(),
+                    None,
+                    Some(
+                        format!(
+                            "board: {}\npattern: {}\nregex: {}\nreplacement: {}",
+                            board_id, pattern, is_regex, replacement
+                        )
+                        .as_str(),
+                    ),
+                )?;
+                Ok(rule)
+            })?;
+            Ok(rule.uuid)
+        })
+        .await
+    }
+
+    /// Only for admin and moderators of the board
+    /// Already posted texts are not changed.
+    /// Return value is dummy
+    #[graphql(guard = "AuthGuard")]
+    async fn remove_word_filter_rule(&self, context: &Context<'_>, rule_uuid: Uuid) -> Result<i32> {
+        let db_pool = context.data::<DBPool>()?.clone();
+        let issuer_user_id = AuthenticatedUser::from_context(context)?.user_id.clone();
+
+        run_blocking(move || {
+            let rule = match WordFilterRule::select_by_uuid(&db_pool, &rule_uuid)? {
+                Some(r) => r,
+                None => return Err(ErrorCode::NotFound.error("Invalid rule uuid.")),
+            };
+            require(
+                &db_pool,
+                &issuer_user_id,
+                Capability::ManageWordFilters,
+                Scope::Board(&rule.board_id),
+            )?;
+
+            let db_connection = db_pool.get()?;
+            db_connection.build_transaction().run::<_, Error, _>(|| {
+                WordFilterRule::remove_by_uuid(&db_connection, &rule_uuid)?;
+                ModerationAction::record(
+                    &db_connection,
+                    &issuer_user_id,
+                    ModerationActionType::RemoveWordFilterRule,
+                    &rule_uuid.to_string(),
+                    None,
+                    Some(
+                        format!(
+                            "board: {}\npattern: {}\nregex: {}\nreplacement: {}",
+                            rule.board_id, rule.pattern, rule.is_regex, rule.replacement
+                        )
+                        .as_str(),
+                    ),
+                )?;
+                Ok(())
+            })?;
+            Ok(0x69)
+        })
+        .await
     }
 }
@@ -0,0 +1,85 @@
+use crate::error::ErrorCode;
+use crate::model::{verify_token_claim, TokenConfig, User, UserType};
+use crate::DBPool;
+use async_graphql::{Context, Guard, Result};
+use num_traits::ToPrimitive;
+use uuid::Uuid;
+
+/// Owner of the token sent with the request.
+/// Resolved once per request by handler::index.
+pub struct AuthenticatedUser {
+    pub user_id: String,
+    pub user_type: UserType,
+    /// UUID of the token used in the request.(the current session)
+    pub token_uuid: Uuid,
+}
+
+/// Why the token sent with the request was rejected.(e.g. "Token expired.")
+pub(crate) struct AuthenticationError(pub String);
+
+impl AuthenticatedUser {
+    /// Verify the token and select the owner.
+    pub fn from_token(db_pool: &DBPool, token_config: &TokenConfig, token: &str) -> Result<Self> {
+        let claim = verify_token_claim(&db_pool, &token_config, token)?;
+        let user = match User::select_by_user_id(&db_pool, &claim.issuer_user_id)? {
+            Some(u) => u,
+            None => return Err(ErrorCode::Unauthenticated.error("User does not exist")),
+        };
+        Ok(AuthenticatedUser {
+            user_id: user.id,
+            user_type: UserType::from_i32(user.user_type)?,
+            token_uuid: claim.token_uuid,
+        })
+    }
+
+    /// Returns the authenticated user of the request.
+    /// Returns an unauthenticated error if no valid token is sent.
+    pub fn from_context<'a>(context: &'a Context<'_>) -> Result<&'a Self> {
+        if let Some(user) = context.data_opt::<AuthenticatedUser>() {
+            return Ok(user);
+        }
+        let message = match context.data_opt::<AuthenticationError>() {
+            Some(e) => e.0.clone(),
+            None => "Unauthenticated. Login required.".to_string(),
+        };
+        Err(ErrorCode::Unauthenticated.error(message))
+    }
+
+    /// Admin > Moderator > Normal
+    pub fn has_role(&self, role: &UserType) -> bool {
+        self.user_type.to_i32().unwrap() <= role.to_i32().unwrap()
+    }
+}
+
+/// Requires a valid token.
+pub struct AuthGuard;
+
+#[async_graphql::async_trait::async_trait]
+impl Guard for AuthGuard {
+    async fn check(&self, context: &Context<'_>) -> Result<()> {
+        AuthenticatedUser::from_context(context)?;
+        Ok(())
+    }
+}
+
+/// Requires a valid token and the role or higher.
+pub struct RoleGuard {
+    role: UserType,
+}
+
+impl RoleGuard {
+    pub fn new(role: UserType) -> Self {
+        RoleGuard { role: role }
+    }
+}
+
+#[async_graphql::async_trait::async_trait]
+impl Guard for RoleGuard {
+    async fn check(&self, context: &Context<'_>) -> Result<()> {
+        let user = AuthenticatedUser::from_context(context)?;
+        if !user.has_role(&self.role) {
+            return Err(ErrorCode::Forbidden.error("Not allowed."));
+        }
+        Ok(())
+    }
+}
@@ -1,8 +1,13 @@
+use crate::config::Config;
 use crate::graphql::ChanSchema;
 use crate::graphql::TokenString;
+use crate::guard::{AuthenticatedUser, AuthenticationError};
+use crate::model::{ClientInfo, TokenConfig};
+use crate::{run_blocking, DBPool};
 use actix_web::http::header::HeaderMap;
 use actix_web::{web, HttpRequest, HttpResponse, Result};
 use async_graphql::http::{playground_source, GraphQLPlaygroundConfig};
+use async_graphql::Request;
 use async_graphql_actix_web::{GraphQLRequest, GraphQLResponse};
 use tracing::{event, Level};
 
@@ -12,16 +17,74 @@ fn get_token_from_headers(headers: &HeaderMap) -> Option<TokenString> {
         .and_then(|value| value.to_str().map(|s| TokenString(s.to_string())).ok())
 }
 
+fn get_client_info(http_request: &HttpRequest) -> ClientInfo {
+    ClientInfo {
+        user_agent: http_request
+            .headers()
+            .get("User-Agent")
+            .and_then(|value| value.to_str().map(|s| s.to_string()).ok()),
+        ip_address: client_ip_address(http_request),
+    }
+}
+
+/// IP address of the client.
+/// Anyone can set X-Forwarded-For, so it is used only on requests from trusted proxies.
+pub fn client_ip_address(http_request: &HttpRequest) -> Option<String> {
+    let peer_ip = http_request.peer_addr().map(|addr| addr.ip());
+    let from_trusted_proxy = match (peer_ip, http_request.app_data::<web::Data<Config>>()) {
+        (Some(ip), Some(config)) => config.server.trusted_proxies.contains(&ip),
+        _ => false,
+    };
+    if from_trusted_proxy {
+        http_request
+            .connection_info()
+            .realip_remote_addr()
+            .map(|s| s.to_string())
+    } else {
+        peer_ip.map(|ip| ip.to_string())
+    }
+}
+
+/// Attach the owner of the token(or why the token was rejected) and the client info to the request.
+/// Shared by the /graphql endpoint and in-process execution of chan-web.
+pub async fn authenticate_request(
+    db_pool: &DBPool,
+    token_config: &TokenConfig,
+    mut request: Request,
+    token: Option<TokenString>,
+    client_info: ClientInfo,
+) -> Request {
+    if let Some(token) = token {
+        event!(Level::DEBUG, "request with the token ",);
+        //resolve the token once, guards and resolvers use the result
+        let db_pool = db_pool.clone();
+        let token_config = token_config.clone();
+        let authenticated =
+            run_blocking(move || AuthenticatedUser::from_token(&db_pool, &token_config, &token.0))
+                .await;
+        match authenticated {
+            Ok(user) => request = request.data(user),
+            Err(e) => request = request.data(AuthenticationError(e.message)),
+        }
+    }
+    request.data(client_info)
+}
+
 pub async fn index(
     schema: web::Data<ChanSchema>,
+    db_pool: web::Data<DBPool>,
+    token_config: web::Data<TokenConfig>,
     http_request: HttpRequest,
     graphql_req: GraphQLRequest,
 ) -> GraphQLResponse {
-    let mut request = graphql_req.into_inner();
-    if let Some(token) = get_token_from_headers(http_request.headers()) {
-        event!(Level::DEBUG, "request with the token ",);
-        request = request.data(token);
-    }
+    let request = authenticate_request(
+        &db_pool,
+        &token_config,
+        graphql_req.into_inner(),
+        get_token_from_headers(http_request.headers()),
+        get_client_info(&http_request),
+    )
+    .await;
     schema.execute(request).await.into()
 }
 
@@ -7,9 +7,34 @@ use diesel::PgConnection;
 
 pub type DBPool = Pool<ConnectionManager<PgConnection>>;
 
+/// Run blocking work(e.g. diesel queries, tantivy commits) on the blocking thread pool.
+/// Calling diesel directly in async fns stalls every request on the same worker.
+pub async fn run_blocking<F, T>(f: F) -> async_graphql::Result<T>
+where
+    F: FnOnce() -> async_graphql::Result<T> + Send + 'static,
+    T: Send + 'static,
+{
+    match tokio::task::spawn_blocking(f).await {
+        Ok(result) => result,
+        Err(e) => Err(async_graphql::Error::new(format!(
+            "Blocking task failed: {}",
+            e
+        ))),
+    }
+}
+
+pub mod config;
+pub mod content_filter;
+pub mod error;
+pub mod flood;
 pub mod graphql;
+pub mod guard;
 pub mod handler;
+pub mod loader;
 pub mod model;
+pub mod pagination;
+pub mod persisted_query;
+pub mod rate_limit;
 mod schema;
 pub mod search_engine;
 
@@ -0,0 +1,338 @@
+//! DataLoaders for relational fields.
+//! Nested queries(e.g. board -> threads -> posts -> poster) are batched into one SQL statement per level.
+//! Statements run on the blocking thread pool.
+use crate::model::{Board, Thread, ThreadPost, User};
+use crate::{run_blocking, DBPool};
+use async_graphql::dataloader::{DataLoader, Loader};
+use async_graphql::{Error, SchemaBuilder};
+use diesel::dsl::count_star;
+use diesel::prelude::*;
+use diesel::sql_types::{Array, BigInt, Integer, Nullable, Uuid as SqlUuid};
+use std::collections::HashMap;
+use uuid::Uuid;
+
+/// Board by uuid.
+pub struct BoardLoader(DBPool);
+
+#[async_graphql::async_trait::async_trait]
+impl Loader<Uuid> for BoardLoader {
+    type Value = Board;
+    type Error = Error;
+
+    async fn load(&self, keys: &[Uuid]) -> Result<HashMap<Uuid, Self::Value>, Self::Error> {
+        let db_pool = self.0.clone();
+        let keys = keys.to_vec();
+        run_blocking(move || {
+            use crate::schema::boards::dsl::*;
+            let v = boards
+                .filter(uuid.eq_any(&keys))
+                .load::<Board>(&db_pool.get()?)?;
+            Ok(v.into_iter().map(|b| (b.uuid, b)).collect())
+        })
+        .await
+    }
+}
+
+/// Thread by uuid.
+pub struct ThreadLoader(DBPool);
+
+#[async_graphql::async_trait::async_trait]
+impl Loader<Uuid> for ThreadLoader {
+    type Value = Thread;
+    type Error = Error;
+
+    async fn load(&self, keys: &[Uuid]) -> Result<HashMap<Uuid, Self::Value>, Self::Error> {
+        let db_pool = self.0.clone();
+        let keys = keys.to_vec();
+        run_blocking(move || {
+            use crate::schema::threads::dsl::*;
+            let v = threads
+                .filter(uuid.eq_any(&keys))
+                .load::<Thread>(&db_pool.get()?)?;
+            Ok(v.into_iter().map(|t| (t.uuid, t)).collect())
+        })
+        .await
+    }
+}
+
+/// Threadpost by uuid.
+pub struct ThreadPostLoader(DBPool);
+
+#[async_graphql::async_trait::async_trait]
+impl Loader<Uuid> for ThreadPostLoader {
+    type Value = ThreadPost;
+    type Error = Error;
+
+    async fn load(&self, keys: &[Uuid]) -> Result<HashMap<Uuid, Self::Value>, Self::Error> {
+        let db_pool = self.0.clone();
+        let keys = keys.to_vec();
+        run_blocking(move || {
+            use crate::schema::threadposts::dsl::*;
+            let v = threadposts
+                .filter(uuid.eq_any(&keys))
+                .load::<ThreadPost>(&db_pool.get()?)?;
+            Ok(v.into_iter().map(|t| (t.uuid, t)).collect())
+        })
+        .await
+    }
+}
+
+/// User by id.
+pub struct UserLoader(DBPool);
+
+#[async_graphql::async_trait::async_trait]
+impl Loader<String> for UserLoader {
+    type Value = User;
+    type Error = Error;
+
+    async fn load(&self, keys: &[String]) -> Result<HashMap<String, Self::Value>, Self::Error> {
+        let db_pool = self.0.clone();
+        let keys = keys.to_vec();
+        run_blocking(move || {
+            use crate::schema::users::dsl::*;
+            let v = users
+                .filter(id.eq_any(&keys))
+                .load::<User>(&db_pool.get()?)?;
+            Ok(v.into_iter().map(|u| (u.id.clone(), u)).collect())
+        })
+        .await
+    }
+}
+
+/// Child threads of a board created after the thread `after`, at most `first` of them.
+#[derive(Clone, Copy, PartialEq, Eq, Hash)]
+pub struct BoardThreadRange {
+    pub board_id: Uuid,
+    pub after: Option<Uuid>,
+    pub first: i32,
+}
+
+/// Child threads by range, oldest first.
+/// One statement per distinct after and first.(like ThreadPostsLoader)
+pub struct BoardThreadsLoader(DBPool);
+
+#[async_graphql::async_trait::async_trait]
+impl Loader<BoardThreadRange> for BoardThreadsLoader {
+    type Value = Vec<Thread>;
+    type Error = Error;
+
+    async fn load(
+        &self,
+        keys: &[BoardThreadRange],
+    ) -> Result<HashMap<BoardThreadRange, Self::Value>, Self::Error> {
+        let db_pool = self.0.clone();
+        let mut board_ids_by_range: HashMap<(Option<Uuid>, i32), Vec<Uuid>> = HashMap::new();
+        for key in keys {
+            board_ids_by_range
+                .entry((key.after, key.first))
+                .or_default()
+                .push(key.board_id);
+        }
+        run_blocking(move || {
+            let db_connection = db_pool.get()?;
+            let mut map: HashMap<BoardThreadRange, Self::Value> = HashMap::new();
+            for ((after, first), board_ids) in board_ids_by_range {
+                //an unknown after thread matches nothing, instead of restarting from the first
+                let v = diesel::sql_query(
+                    "SELECT primary_key, uuid, created_at, parent_board_id, title, creator_user_id, \
+                     original_title, locked FROM \
+                     (SELECT *, ROW_NUMBER() OVER (PARTITION BY parent_board_id ORDER BY primary_key) \
+                     AS rank_in_board FROM threads \
+                     WHERE parent_board_id = ANY($1) AND ($2::UUID IS NULL OR \
+                     primary_key > (SELECT primary_key FROM threads WHERE uuid = $2))) ranked \
+                     WHERE rank_in_board <= $3 ORDER BY primary_key",
+                )
+                .bind::<Array<SqlUuid>, _>(&board_ids)
+                .bind::<Nullable<SqlUuid>, _>(after)
+                .bind::<BigInt, _>(first as i64)
+                .load::<Thread>(&db_connection)?;
+                for thread in v {
+                    let key = BoardThreadRange {
+                        board_id: thread.parent_board_id,
+                        after: after,
+                        first: first,
+                    };
+                    map.entry(key).or_default().push(thread);
+                }
+            }
+            Ok(map)
+        })
+        .await
+    }
+}
+
+/// Threadposts of a thread numbered after `after`, at most `first` of them.
+#[derive(Clone, Copy, PartialEq, Eq, Hash)]
+pub struct ThreadPostRange {
+    pub thread_id: Uuid,
+    pub after: i32,
+    pub first: i32,
+}
+
+/// Threadposts by range, ordered by number.
+/// One statement per distinct after and first.(usually one, every thread of a page asks the same range)
+pub struct ThreadPostsLoader(DBPool);
+
+#[async_graphql::async_trait::async_trait]
+impl Loader<ThreadPostRange> for ThreadPostsLoader {
+    type Value = Vec<ThreadPost>;
+    type Error = Error;
+
+    async fn load(
+        &self,
+        keys: &[ThreadPostRange],
+    ) -> Result<HashMap<ThreadPostRange, Self::Value>, Self::Error> {
+        let db_pool = self.0.clone();
+        let mut thread_ids_by_range: HashMap<(i32, i32), Vec<Uuid>> = HashMap::new();
+        for key in keys {
+            thread_ids_by_range
+                .entry((key.after, key.first))
+                .or_default()
+                .push(key.thread_id);
+        }
+        run_blocking(move || {
+            let db_connection = db_pool.get()?;
+            let mut map: HashMap<ThreadPostRange, Self::Value> = HashMap::new();
+            for ((after, first), thread_ids) in thread_ids_by_range {
+                //numbers are unique per thread but removals leave gaps, so rank rows per thread
+                //instead of taking number <= after + first
+                let v = diesel::sql_query(
+                    "SELECT primary_key, uuid, number, posted_at, poster_user_id, parent_thread_id, \
+                     body_text, original_body_text FROM \
+                     (SELECT *, ROW_NUMBER() OVER (PARTITION BY parent_thread_id ORDER BY number) \
+                     AS rank_in_thread FROM threadposts \
+                     WHERE parent_thread_id = ANY($1) AND number > $2) ranked \
+                     WHERE rank_in_thread <= $3 ORDER BY number",
+                )
+                .bind::<Array<SqlUuid>, _>(&thread_ids)
+                .bind::<Integer, _>(after)
+                .bind::<BigInt, _>(first as i64)
+                .load::<ThreadPost>(&db_connection)?;
+                for threadpost in v {
+                    let key = ThreadPostRange {
+                        thread_id: threadpost.parent_thread_id,
+                        after: after,
+                        first: first,
+                    };
+                    map.entry(key).or_default().push(threadpost);
+                }
+            }
+            Ok(map)
+        })
+        .await
+    }
+}
+
+/// Number of threadposts by parent thread uuid.
+pub struct ThreadPostCountLoader(DBPool);
+
+#[async_graphql::async_trait::async_trait]
+impl Loader<Uuid> for ThreadPostCountLoader {
+    type Value = i64;
+    type Error = Error;
+
+    async fn load(&self, keys: &[Uuid]) -> Result<HashMap<Uuid, Self::Value>, Self::Error> {
+        let db_pool = self.0.clone();
+        let keys = keys.to_vec();
+        run_blocking(move || {
+            use crate::schema::threadposts::dsl::*;
+            let v = threadposts
+                .filter(parent_thread_id.eq_any(&keys))
+                .group_by(parent_thread_id)
+                .select((parent_thread_id, count_star()))
+                .load::<(Uuid, i64)>(&db_pool.get()?)?;
+            Ok(v.into_iter().collect())
+        })
+        .await
+    }
+}
+
+/// Threadposts of a thread numbered after `after`.(e.g. unread threadposts of a watched thread)
+#[derive(Clone, Copy, PartialEq, Eq, Hash)]
+pub struct ThreadPostsAfter {
+    pub thread_id: Uuid,
+    pub after: i32,
+}
+
+#[derive(QueryableByName)]
+struct ThreadPostsAfterCount {
+    #[sql_type = "SqlUuid"]
+    thread_id: Uuid,
+    #[sql_type = "Integer"]
+    after: i32,
+    #[sql_type = "BigInt"]
+    post_count: i64,
+}
+
+/// Number of threadposts by thread and number, missing keys have none.
+/// One statement for every key.(e.g. every watched thread of a user)
+pub struct ThreadPostsAfterCountLoader(DBPool);
+
+#[async_graphql::async_trait::async_trait]
+impl Loader<ThreadPostsAfter> for ThreadPostsAfterCountLoader {
+    type Value = i64;
+    type Error = Error;
+
+    async fn load(
+        &self,
+        keys: &[ThreadPostsAfter],
+    ) -> Result<HashMap<ThreadPostsAfter, Self::Value>, Self::Error> {
+        let db_pool = self.0.clone();
+        let thread_ids: Vec<Uuid> = keys.iter().map(|key| key.thread_id).collect();
+        let afters: Vec<i32> = keys.iter().map(|key| key.after).collect();
+        run_blocking(move || {
+            let v = diesel::sql_query(
+                "SELECT keys.thread_id, keys.after, COUNT(*) AS post_count \
+                 FROM UNNEST($1, $2) AS keys(thread_id, after) \
+                 JOIN threadposts ON threadposts.parent_thread_id = keys.thread_id \
+                 AND threadposts.number > keys.after \
+                 GROUP BY keys.thread_id, keys.after",
+            )
+            .bind::<Array<SqlUuid>, _>(&thread_ids)
+            .bind::<Array<Integer>, _>(&afters)
+            .load::<ThreadPostsAfterCount>(&db_pool.get()?)?;
+            Ok(v.into_iter()
+                .map(|c| {
+                    let key = ThreadPostsAfter {
+                        thread_id: c.thread_id,
+                        after: c.after,
+                    };
+                    (key, c.post_count)
+                })
+                .collect())
+        })
+        .await
+    }
+}
+
+/// Add all loaders to the schema.
+/// Loaders don't cache, so each request sees the latest data.
+pub fn register_loaders<Q, M, S>(
+    builder: SchemaBuilder<Q, M, S>,
+    db_pool: &DBPool,
+) -> SchemaBuilder<Q, M, S> {
+    builder
+        .data(DataLoader::new(BoardLoader(db_pool.clone()), tokio::spawn))
+        .data(DataLoader::new(ThreadLoader(db_pool.clone()), tokio::spawn))
+        .data(DataLoader::new(
+            ThreadPostLoader(db_pool.clone()),
+            tokio::spawn,
+        ))
+        .data(DataLoader::new(UserLoader(db_pool.clone()), tokio::spawn))
+        .data(DataLoader::new(
+            BoardThreadsLoader(db_pool.clone()),
+            tokio::spawn,
+        ))
+        .data(DataLoader::new(
+            ThreadPostsLoader(db_pool.clone()),
+            tokio::spawn,
+        ))
+        .data(DataLoader::new(
+            ThreadPostCountLoader(db_pool.clone()),
+            tokio::spawn,
+        ))
+        .data(DataLoader::new(
+            ThreadPostsAfterCountLoader(db_pool.clone()),
+            tokio::spawn,
+        ))
+}
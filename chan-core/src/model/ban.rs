@@ -0,0 +1,251 @@
+use crate::error::ErrorCode;
+use crate::model::{User, UserStatus};
+use crate::schema::bans;
+use crate::DBPool;
+use async_graphql::{Error, Result, SimpleObject};
+use chrono::{DateTime, Utc};
+use diesel::prelude::*;
+use diesel::PgConnection;
+use diesel::{Insertable, Queryable};
+use num_traits::ToPrimitive;
+use uuid::Uuid;
+
+/// Statuses set by bans.
+fn banned_statuses() -> Vec<i32> {
+    vec![
+        UserStatus::Suspended.to_i32().unwrap(),
+        UserStatus::Banned.to_i32().unwrap(),
+    ]
+}
+
+/// Statuses of accounts which can be banned.(a new ban replaces the active one)
+fn bannable_statuses() -> Vec<i32> {
+    let mut statuses = banned_statuses();
+    statuses.push(UserStatus::Normal.to_i32().unwrap());
+    statuses
+}
+
+/// Suspension or ban of a user.
+/// ends_at is None for permanent bans.
+#[derive(Queryable, SimpleObject, Clone)]
+pub struct Ban {
+    #[graphql(skip)]
+    primary_key: i32,
+    pub uuid: Uuid,
+    pub user_id: String,
+    pub issuer_user_id: String,
+    /// UserStatus while the ban is active.(Suspended or Banned)
+    pub user_status: i32,
+    pub reason: String,
+    pub starts_at: DateTime<Utc>,
+    pub ends_at: Option<DateTime<Utc>>,
+    pub lifted_at: Option<DateTime<Utc>>,
+    pub lifter_user_id: Option<String>,
+}
+
+impl Ban {
+    /// Suspend or ban the user and change the user status.
+    /// Removed accounts can't be banned.
+    /// Returns the created ban.
+    pub fn create_new(
+        db_connection: &PgConnection,
+        issuer_user_id: &str,
+        user_id: &str,
+        status: UserStatus,
+        reason: &str,
+        ends_at: Option<DateTime<Utc>>,
+    ) -> Result<Self> {
+        if status != UserStatus::Suspended && status != UserStatus::Banned {
+            return Err(ErrorCode::Validation.error("Invalid ban status."));
+        }
+        if status == UserStatus::Suspended && ends_at.is_none() {
+            return Err(ErrorCode::Validation.error("Suspension needs an end date."));
+        }
+        if reason.len() == 0 {
+            return Err(ErrorCode::Validation.error("Empty reason."));
+        }
+        let datetime_now = Utc::now();
+        if let Some(ends_at) = ends_at {
+            if ends_at <= datetime_now {
+                return Err(ErrorCode::Validation.error("End date must be in the future."));
+            }
+        }
+
+        let new_ban = NewBan {
+            uuid: &Uuid::new_v4(),
+            user_id: user_id,
+            issuer_user_id: issuer_user_id,
+            user_status: status.to_i32().unwrap(),
+            reason: reason,
+            starts_at: &datetime_now,
+            ends_at: ends_at.as_ref(),
+        };
+
+        let created_ban = db_connection.transaction::<_, Error, _>(|| {
+            //only one active ban per user, the new one replaces older ones
+            {
+                use crate::schema::bans::dsl;
+                diesel::update(
+                    dsl::bans
+                        .filter(dsl::user_id.eq(user_id))
+                        .filter(dsl::lifted_at.is_null()),
+                )
+                .set((
+                    dsl::lifted_at.eq(Some(datetime_now)),
+                    dsl::lifter_user_id.eq(Some(issuer_user_id)),
+                ))
+                .execute(db_connection)?;
+            }
+
+            let created_ban = diesel::insert_into(bans::table)
+                .values(&new_ban)
+                .get_result::<Ban>(db_connection)?;
+
+            //removed accounts stay removed
+            use crate::schema::users;
+            let updated = diesel::update(
+                users::table
+                    .filter(users::id.eq(user_id))
+                    .filter(users::user_status.eq_any(bannable_statuses())),
+            )
+            .set(users::user_status.eq(status.to_i32().unwrap()))
+            .execute(db_connection)?;
+            if updated == 0 {
+                return Err(ErrorCode::Validation.error("The account can't be banned."));
+            }
+            Ok(created_ban)
+        })?;
+        Ok(created_ban)
+    }
+
+    /// Lift the active ban of the user and restore the user status.
+    /// The status is restored only if it was set by a ban, so removed accounts are never resurrected.
+    pub fn lift(db_connection: &PgConnection, lifter_user_id: &str, user_id: &str) -> Result<()> {
+        db_connection.transaction::<_, Error, _>(|| {
+            use crate::schema::bans::dsl;
+            let lifted = diesel::update(
+                dsl::bans
+                    .filter(dsl::user_id.eq(user_id))
+                    .filter(dsl::lifted_at.is_null()),
+            )
+            .set((
+                dsl::lifted_at.eq(Some(Utc::now())),
+                dsl::lifter_user_id.eq(Some(lifter_user_id)),
+            ))
+            .execute(db_connection)?;
+            if lifted == 0 {
+                return Err(ErrorCode::Validation.error("The user is not banned."));
+            }
+
+            //only a status set by a ban is restored
+            use crate::schema::users;
+            diesel::update(
+                users::table
+                    .filter(users::id.eq(user_id))
+                    .filter(users::user_status.eq_any(banned_statuses())),
+            )
+            .set(users::user_status.eq(UserStatus::Normal.to_i32().unwrap()))
+            .execute(db_connection)?;
+            Ok(())
+        })?;
+        Ok(())
+    }
+
+    /// The ban which is not lifted.(it may be ended already)
+    pub fn select_active(db_pool: &DBPool, target_user_id: &str) -> Result<Option<Self>> {
+        use crate::schema::bans::dsl::*;
+        let v = bans
+            .filter(user_id.eq(target_user_id))
+            .filter(lifted_at.is_null())
+            .order_by(starts_at.desc())
+            .limit(1)
+            .load::<Ban>(&db_pool.get()?)?;
+        Ok(v.into_iter().next())
+    }
+
+    /// All bans of the user, newest first.
+    pub fn history(db_pool: &DBPool, target_user_id: &str) -> Result<Vec<Self>> {
+        use crate::schema::bans::dsl::*;
+        Ok(bans
+            .filter(user_id.eq(target_user_id))
+            .order_by(starts_at.desc())
+            .load::<Ban>(&db_pool.get()?)?)
+    }
+
+    /// Returns the current status of the user.
+    /// A suspended or banned user whose ban has ended is restored to Normal here,
+    /// so bans expire without any scheduled job.
+    pub fn effective_status(db_pool: &DBPool, user: &User) -> Result<UserStatus> {
+        let status = UserStatus::from_i32(user.user_status)?;
+        if status != UserStatus::Suspended && status != UserStatus::Banned {
+            return Ok(status);
+        }
+
+        //statuses set without a ban record never expire
+        let ban = match Ban::select_active(&db_pool, &user.id)? {
+            Some(b) => b,
+            None => return Ok(status),
+        };
+        match ban.ends_at {
+            Some(ends_at) if ends_at <= Utc::now() => {}
+            _ => return Ok(status), //still active
+        }
+
+        //ended, restore
+        let db_connection = db_pool.get()?;
+        db_connection.build_transaction().run::<_, Error, _>(|| {
+            use crate::schema::bans::dsl;
+            diesel::update(
+                dsl::bans
+                    .filter(dsl::user_id.eq(&user.id))
+                    .filter(dsl::lifted_at.is_null())
+                    .filter(dsl::ends_at.le(Utc::now())),
+            )
+            .set(dsl::lifted_at.eq(Some(Utc::now())))
+            .execute(&db_connection)?;
+
+            use crate::schema::users;
+            diesel::update(
+                users::table
+                    .filter(users::id.eq(&user.id))
+                    .filter(users::user_status.eq_any(banned_statuses())),
+            )
+            .set(users::user_status.eq(UserStatus::Normal.to_i32().unwrap()))
+            .execute(&db_connection)?;
+            Ok(())
+        })?;
+        Ok(UserStatus::Normal)
+    }
+
+    /// Message shown to the blocked user.
+    pub fn describe(&self) -> String {
+        let kind = if self.user_status == UserStatus::Suspended.to_i32().unwrap() {
+            "suspended"
+        } else {
+            "banned"
+        };
+        match self.ends_at {
+            Some(ends_at) => format!(
+                "Your account is {} until {}. Reason: {}",
+                kind, ends_at, self.reason
+            ),
+            None => format!(
+                "Your account is {} permanently. Reason: {}",
+                kind, self.reason
+            ),
+        }
+    }
+}
+
+/// diesel model
+#[derive(Insertable)]
+#[table_name = "bans"]
+struct NewBan<'a> {
+    pub uuid: &'a Uuid,
+    pub user_id: &'a str,
+    pub issuer_user_id: &'a str,
+    pub user_status: i32,
+    pub reason: &'a str,
+    pub starts_at: &'a DateTime<Utc>,
+    pub ends_at: Option<&'a DateTime<Utc>>,
+}
@@ -1,11 +1,17 @@
 use std::str::FromStr;
 
+use crate::config::Config;
+use crate::error::ErrorCode;
+use crate::loader::{BoardThreadRange, BoardThreadsLoader};
 use crate::model::Thread;
+use crate::pagination::{list_complexity, KeysetPage};
 use crate::schema::boards::{self};
 use crate::DBPool;
-use async_graphql::{Error, Result, SimpleObject};
+use async_graphql::dataloader::DataLoader;
+use async_graphql::{ComplexObject, Context, Error, Result, SimpleObject};
 use chrono::{DateTime, Utc};
 use diesel::prelude::*;
+use diesel::PgConnection;
 use diesel::{Insertable, Queryable};
 use tantivy::{
     collector::{Count, TopDocs},
@@ -18,6 +24,7 @@ use uuid::Uuid;
 use super::ThreadPost;
 
 #[derive(Queryable, SimpleObject, Clone)]
+#[graphql(complex)]
 pub struct Board {
     #[graphql(skip)]
     primary_key: i32,
@@ -27,6 +34,48 @@ pub struct Board {
     pub description: String,
 }
 
+#[ComplexObject]
+impl Board {
+    /// Child threads of the board, oldest first.
+    /// after is the uuid of the last thread already fetched.
+    #[graphql(complexity = "list_complexity(first.or(Some(100)), None, child_complexity)")]
+    async fn threads(
+        &self,
+        context: &Context<'_>,
+        first: Option<i32>,
+        after: Option<Uuid>,
+    ) -> Result<Vec<Thread>> {
+        let first = first.unwrap_or(100);
+        if first < 0 || first > 1000 {
+            return Err(ErrorCode::Validation.error("Invalid range."));
+        }
+        let range = BoardThreadRange {
+            board_id: self.uuid,
+            after: after,
+            first: first,
+        };
+
+        let loader = context.data::<DataLoader<BoardThreadsLoader>>()?;
+        Ok(loader.load_one(range).await?.unwrap_or_default())
+    }
+
+    /// Whether create_thread on the board requires a CAPTCHA.
+    async fn captcha_required_for_thread_creation(&self, context: &Context<'_>) -> Result<bool> {
+        Ok(context
+            .data::<Config>()?
+            .captcha
+            .required_for_thread_creation(&self.uuid))
+    }
+
+    /// Whether post_threadpost on threads of the board requires a CAPTCHA.
+    async fn captcha_required_for_posting(&self, context: &Context<'_>) -> Result<bool> {
+        Ok(context
+            .data::<Config>()?
+            .captcha
+            .required_for_posting(&self.uuid))
+    }
+}
+
 impl Board {
     /// Create a new board and insert it to DB.
     /// Returns the created board.
@@ -57,6 +106,16 @@ impl Board {
         Ok(Some(board_vec[0].clone()))
     }
 
+    /// Same as select_by_uuid(..).is_some(), on the connection.(e.g. in a transaction)
+    pub fn exists(db_connection: &PgConnection, board_uuid: &Uuid) -> Result<bool> {
+        use crate::schema::boards::dsl::*;
+        let count: i64 = boards
+            .filter(uuid.eq(board_uuid))
+            .count()
+            .get_result(db_connection)?;
+        Ok(count > 0)
+    }
+
     /*
     pub fn remove_by_uuid(
         db_pool: &DBPool,
@@ -133,25 +192,51 @@ impl Board {
         Ok(ret_boards)
     }
 
-    pub fn pagination_query(db_pool: &DBPool, limit: i64, offset: i64) -> Result<Vec<Board>> {
-        use crate::schema::boards::dsl::boards;
-        Ok(boards
-            .limit(limit)
-            .offset(offset)
-            .load::<Board>(&db_pool.get()?)?)
+    /// Cursor key of the board.
+    pub fn key(&self) -> i32 {
+        self.primary_key
     }
 
-    pub fn count(db_pool: &DBPool) -> Result<usize> {
-        use crate::schema::boards::dsl::boards;
-        let count: i64 = boards.count().get_result(&db_pool.get()?)?;
-        Ok(count as usize)
+    /// Oldest first, keyed by primary_key.
+    pub fn page(db_pool: &DBPool, page: &KeysetPage) -> Result<Vec<Board>> {
+        use crate::schema::boards::dsl::*;
+        let mut query = boards.into_boxed();
+        if let Some(key) = page.after {
+            query = query.filter(primary_key.gt(key));
+        }
+        if let Some(key) = page.before {
+            query = query.filter(primary_key.lt(key));
+        }
+        query = if page.backward {
+            query.order_by(primary_key.desc())
+        } else {
+            query.order_by(primary_key)
+        };
+        Ok(query.limit(page.limit).load::<Board>(&db_pool.get()?)?)
     }
 
-    pub fn child_threads(db_pool: &DBPool, parent_board_uuid: &Uuid) -> Result<Vec<Thread>> {
+    /// Child threads of the board, oldest first, keyed by primary_key.
+    pub fn child_thread_page(
+        db_pool: &DBPool,
+        parent_board_uuid: &Uuid,
+        page: &KeysetPage,
+    ) -> Result<Vec<Thread>> {
         use crate::schema::threads::dsl::*;
-        Ok(threads
+        let mut query = threads
             .filter(parent_board_id.eq(parent_board_uuid))
-            .load::<Thread>(&db_pool.get()?)?)
+            .into_boxed();
+        if let Some(key) = page.after {
+            query = query.filter(primary_key.gt(key));
+        }
+        if let Some(key) = page.before {
+            query = query.filter(primary_key.lt(key));
+        }
+        query = if page.backward {
+            query.order_by(primary_key.desc())
+        } else {
+            query.order_by(primary_key)
+        };
+        Ok(query.limit(page.limit).load::<Thread>(&db_pool.get()?)?)
     }
 }
 
@@ -0,0 +1,104 @@
+use crate::error::ErrorCode;
+use crate::model::{Board, User};
+use crate::schema::board_moderators;
+use crate::DBPool;
+use async_graphql::{Result, SimpleObject};
+use chrono::{DateTime, Utc};
+use diesel::prelude::*;
+use diesel::PgConnection;
+use diesel::{Insertable, Queryable};
+use uuid::Uuid;
+
+/// Moderator assigned to a board.
+/// Board moderators have moderation capabilities only in the board.
+#[derive(Queryable, SimpleObject, Clone)]
+pub struct BoardModerator {
+    #[graphql(skip)]
+    primary_key: i32,
+    pub board_id: Uuid,
+    pub user_id: String,
+    pub assigner_user_id: String,
+    pub assigned_at: DateTime<Utc>,
+}
+
+impl BoardModerator {
+    /// Assign the user as a moderator of the board.
+    pub fn assign(
+        db_connection: &PgConnection,
+        assigner_user_id: &str,
+        board_uuid: &Uuid,
+        user_id: &str,
+    ) -> Result<Self> {
+        if !Board::exists(db_connection, board_uuid)? {
+            return Err(ErrorCode::NotFound.error("Invalid board uuid."));
+        }
+        if !User::exists(db_connection, user_id)? {
+            return Err(ErrorCode::NotFound.error("User does not exist"));
+        }
+        let moderator_count: i64 = board_moderators::table
+            .filter(board_moderators::board_id.eq(board_uuid))
+            .filter(board_moderators::user_id.eq(user_id))
+            .count()
+            .get_result(db_connection)?;
+        if moderator_count > 0 {
+            return Err(ErrorCode::Validation.error("Already a moderator of the board."));
+        }
+
+        let new_moderator = NewBoardModerator {
+            board_id: board_uuid,
+            user_id: user_id,
+            assigner_user_id: assigner_user_id,
+            assigned_at: &Utc::now(),
+        };
+        Ok(diesel::insert_into(board_moderators::table)
+            .values(&new_moderator)
+            .get_result::<BoardModerator>(db_connection)?)
+    }
+
+    /// Remove the user from moderators of the board.
+    pub fn revoke(
+        db_connection: &PgConnection,
+        board_uuid: &Uuid,
+        target_user_id: &str,
+    ) -> Result<()> {
+        use crate::schema::board_moderators::dsl::*;
+        let removed = diesel::delete(
+            board_moderators
+                .filter(board_id.eq(board_uuid))
+                .filter(user_id.eq(target_user_id)),
+        )
+        .execute(db_connection)?;
+        if removed == 0 {
+            return Err(ErrorCode::Validation.error("Not a moderator of the board."));
+        }
+        Ok(())
+    }
+
+    pub fn is_moderator(db_pool: &DBPool, board_uuid: &Uuid, target_user_id: &str) -> Result<bool> {
+        use crate::schema::board_moderators::dsl::*;
+        let count: i64 = board_moderators
+            .filter(board_id.eq(board_uuid))
+            .filter(user_id.eq(target_user_id))
+            .count()
+            .get_result(&db_pool.get()?)?;
+        Ok(count > 0)
+    }
+
+    pub fn select_by_board(db_pool: &DBPool, board_uuid: &Uuid) -> Result<Vec<Self>> {
+        use crate::schema::board_moderators::dsl::*;
+        Ok(board_moderators
+            .filter(board_id.eq(board_uuid))
+            .order_by(assigned_at.asc())
+            .load::<BoardModerator>(&db_pool.get()?)?)
+    }
+}
+
+/// diesel model
+#[derive(Insertable)]
+#[table_name = "board_moderators"]
+struct NewBoardModerator<'a> {
+    pub board_id: &'a Uuid,
+    pub user_id: &'a str,
+    pub assigner_user_id: &'a str,
+    pub assigned_at: &'a DateTime<Utc>,
+}
@@ -0,0 +1,284 @@
+use crate::error::ErrorCode;
+use crate::schema::captchas;
+use crate::DBPool;
+use async_graphql::{InputObject, Result, SimpleObject};
+use chrono::{DateTime, Duration, Utc};
+use diesel::prelude::*;
+use diesel::{Insertable, Queryable};
+use uuid::Uuid;
+
+/// Digits of image challenges, 5x7 pixels per glyph, one row per byte.(high 5 bits unused)
+const DIGIT_GLYPHS: [[u8; 7]; 10] = [
+    [0x0e, 0x11, 0x13, 0x15, 0x19, 0x11, 0x0e],
+    [0x04, 0x0c, 0x04, 0x04, 0x04, 0x04, 0x0e],
+    [0x0e, 0x11, 0x01, 0x02, 0x04, 0x08, 0x1f],
+    [0x1f, 0x02, 0x04, 0x02, 0x01, 0x11, 0x0e],
+    [0x02, 0x06, 0x0a, 0x12, 0x1f, 0x02, 0x02],
+    [0x1f, 0x10, 0x1e, 0x01, 0x01, 0x11, 0x0e],
+    [0x06, 0x08, 0x10, 0x1e, 0x11, 0x11, 0x0e],
+    [0x1f, 0x01, 0x02, 0x04, 0x08, 0x08, 0x08],
+    [0x0e, 0x11, 0x11, 0x0e, 0x11, 0x11, 0x0e],
+    [0x0e, 0x11, 0x11, 0x0f, 0x01, 0x02, 0x0c],
+];
+
+/// Issued challenge, each challenge can be answered once.
+#[derive(Queryable, Clone)]
+pub struct Captcha {
+    primary_key: i32,
+    pub uuid: Uuid,
+    pub question: String,
+    pub answer: String,
+    pub issued_at: DateTime<Utc>,
+    pub expires_at: DateTime<Utc>,
+}
+
+/// Challenge returned to the client, without the answer.
+/// The image is not stored, it is returned only once.
+#[derive(SimpleObject)]
+#[graphql(name = "Captcha")]
+pub struct IssuedCaptcha {
+    pub uuid: Uuid,
+    pub question: String,
+    /// data URL of the image, shown with the question.
+    pub image: Option<String>,
+    pub issued_at: DateTime<Utc>,
+    pub expires_at: DateTime<Utc>,
+}
+
+/// Answer of a challenge, sent with the protected mutations.
+#[derive(InputObject)]
+pub struct CaptchaAnswer {
+    pub uuid: Uuid,
+    pub answer: String,
+}
+
+const IMAGE_DIGITS: usize = 6;
+const IMAGE_WIDTH: u32 = 220;
+const IMAGE_HEIGHT: u32 = 60;
+
+/// Challenge made by a provider.
+pub struct Challenge {
+    /// Instruction shown to the user.
+    pub question: String,
+    /// data URL of the image, None for challenges without one.
+    pub image: Option<String>,
+    /// Expected answer, compared with the trimmed and lowercased answer of the user.
+    pub answer: String,
+}
+
+/// Makes challenges, set as schema data.(Arc<dyn CaptchaProvider>)
+/// Replace it to serve another kind of challenge, answers are still verified by chan-core.
+pub trait CaptchaProvider: Send + Sync {
+    fn generate(&self) -> Result<Challenge>;
+}
+
+/// Random digits drawn as jittered dots on an SVG image with noise.(no JavaScript, no external service)
+/// The digits are not in the document as text, they have to be read from the image.
+pub struct ImageCaptcha;
+
+/// Random number in 0..n
+fn random_below(n: u32) -> Result<u32> {
+    let mut bytes = [0; 4];
+    openssl::rand::rand_bytes(&mut bytes)?;
+    Ok(u32::from_le_bytes(bytes) % n)
+}
+
+/// Random number in min..max, in steps of 0.01
+fn random_between(min: f32, max: f32) -> Result<f32> {
+    let steps = ((max - min) * 100.0) as u32;
+    Ok(min + random_below(steps.max(1))? as f32 / 100.0)
+}
+
+/// Random dark gray, the same tone as the noise so colors don't separate digits from noise.
+fn random_color() -> Result<String> {
+    let level = random_below(96)?;
+    Ok(format!("rgb({},{},{})", level, level, level))
+}
+
+/// Dots of a digit, rotated and scaled randomly around the center of the cell.
+fn draw_digit(svg: &mut String, digit: usize, cell_x: f32) -> Result<()> {
+    let dot_size = random_between(4.2, 5.4)?;
+    let x = cell_x + random_between(0.0, 8.0)?;
+    let y = random_between(4.0, IMAGE_HEIGHT as f32 - 7.0 * dot_size - 4.0)?;
+    let angle = random_between(-20.0, 20.0)?;
+    let skew = random_between(-15.0, 15.0)?;
+    svg.push_str(&format!(
+        "<g fill=\"{}\" transform=\"rotate({:.2} {:.2} {:.2}) skewX({:.2})\">",
+        random_color()?,
+        angle,
+        x + 2.5 * dot_size,
+        y + 3.5 * dot_size,
+        skew
+    ));
+    for (row, bits) in DIGIT_GLYPHS[digit].iter().enumerate() {
+        for column in 0..5 {
+            if bits & (0x10 >> column) == 0 {
+                continue;
+            }
+            svg.push_str(&format!(
+                "<circle cx=\"{:.2}\" cy=\"{:.2}\" r=\"{:.2}\"/>",
+                x + column as f32 * dot_size + random_between(-1.0, 1.0)?,
+                y + row as f32 * dot_size + random_between(-1.0, 1.0)?,
+                dot_size * random_between(0.45, 0.7)?
+            ));
+        }
+    }
+    svg.push_str("</g>");
+    Ok(())
+}
+
+/// Stray dots and lines crossing the digits.
+fn draw_noise(svg: &mut String) -> Result<()> {
+    for _ in 0..60 {
+        svg.push_str(&format!(
+            "<circle cx=\"{}\" cy=\"{}\" r=\"{:.2}\" fill=\"{}\"/>",
+            random_below(IMAGE_WIDTH)?,
+            random_below(IMAGE_HEIGHT)?,
+            random_between(1.0, 3.0)?,
+            random_color()?
+        ));
+    }
+    for _ in 0..4 {
+        svg.push_str(&format!(
+            "<path d=\"M0 {} Q{} {} {} {}\" stroke=\"{}\" stroke-width=\"{:.2}\" fill=\"none\"/>",
+            random_below(IMAGE_HEIGHT)?,
+            random_below(IMAGE_WIDTH)?,
+            random_below(IMAGE_HEIGHT)?,
+            IMAGE_WIDTH,
+            random_below(IMAGE_HEIGHT)?,
+            random_color()?,
+            random_between(1.0, 2.5)?
+        ));
+    }
+    Ok(())
+}
+
+impl CaptchaProvider for ImageCaptcha {
+    fn generate(&self) -> Result<Challenge> {
+        let mut answer = String::new();
+        let mut svg = format!(
+            "<svg xmlns=\"http://www.w3.org/2000/svg\" width=\"{w}\" height=\"{h}\" viewBox=\"0 0 {w} {h}\"><rect width=\"100%\" height=\"100%\" fill=\"#f4f4f4\"/>",
+            w = IMAGE_WIDTH,
+            h = IMAGE_HEIGHT
+        );
+        let cell_width = IMAGE_WIDTH as f32 / IMAGE_DIGITS as f32;
+        for i in 0..IMAGE_DIGITS {
+            let digit = random_below(10)? as usize;
+            answer.push_str(&digit.to_string());
+            draw_digit(&mut svg, digit, i as f32 * cell_width)?;
+        }
+        draw_noise(&mut svg)?;
+        svg.push_str("</svg>");
+
+        Ok(Challenge {
+            question: "Type the digits in the image.".to_string(),
+            image: Some(format!(
+                "data:image/svg+xml;base64,{}",
+                openssl::base64::encode_block(svg.as_bytes())
+            )),
+            answer: answer,
+        })
+    }
+}
+
+impl Captcha {
+    /// Issue a new challenge of the provider valid for the lifetime.
+    /// Expired challenges are deleted at the same time.
+    pub fn issue(
+        db_pool: &DBPool,
+        provider: &dyn CaptchaProvider,
+        lifetime: Duration,
+    ) -> Result<IssuedCaptcha> {
+        let conn = db_pool.get()?;
+        let datetime_now = Utc::now();
+        {
+            use crate::schema::captchas::dsl::*;
+            diesel::delete(captchas.filter(expires_at.lt(datetime_now))).execute(&conn)?;
+        }
+
+        let challenge = provider.generate()?;
+        let new_captcha = NewCaptcha {
+            uuid: &Uuid::new_v4(),
+            question: &challenge.question,
+            answer: &challenge.answer.to_lowercase(),
+            issued_at: &datetime_now,
+            expires_at: &(datetime_now + lifetime),
+        };
+        let captcha = diesel::insert_into(captchas::table)
+            .values(&new_captcha)
+            .get_result::<Captcha>(&conn)?;
+        Ok(IssuedCaptcha {
+            uuid: captcha.uuid,
+            question: captcha.question,
+            image: challenge.image,
+            issued_at: captcha.issued_at,
+            expires_at: captcha.expires_at,
+        })
+    }
+
+    /// Consume the challenge and check the answer.
+    /// The challenge is deleted even if the answer is wrong, so it can't be brute forced.
+    pub fn verify(db_pool: &DBPool, captcha_answer: &CaptchaAnswer) -> Result<()> {
+        let captcha = {
+            use crate::schema::captchas::dsl::*;
+            diesel::delete(captchas.filter(uuid.eq(&captcha_answer.uuid)))
+                .get_result::<Captcha>(&db_pool.get()?)
+                .optional()?
+        };
+        let captcha = match captcha {
+            Some(c) if c.expires_at > Utc::now() => c,
+            _ => {
+                return Err(ErrorCode::Validation.error("CAPTCHA expired. Please try again."));
+            }
+        };
+        if captcha_answer.answer.trim().to_lowercase() != captcha.answer {
+            return Err(ErrorCode::Validation.error("Wrong CAPTCHA answer."));
+        }
+        Ok(())
+    }
+
+    /// Verify the answer if a CAPTCHA is required.
+    pub fn require(
+        db_pool: &DBPool,
+        required: bool,
+        captcha_answer: Option<&CaptchaAnswer>,
+    ) -> Result<()> {
+        if !required {
+            return Ok(());
+        }
+        match captcha_answer {
+            Some(a) => Captcha::verify(db_pool, a),
+            None => Err(ErrorCode::Validation.error("CAPTCHA required.")),
+        }
+    }
+}
+
+#[derive(Insertable)]
+#[table_name = "captchas"]
+struct NewCaptcha<'a> {
+    pub uuid: &'a Uuid,
+    pub question: &'a str,
+    pub answer: &'a str,
+    pub issued_at: &'a DateTime<Utc>,
+    pub expires_at: &'a DateTime<Utc>,
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn image_challenge() {
+        let challenge = ImageCaptcha.generate().unwrap();
+        assert_eq!(challenge.answer.len(), IMAGE_DIGITS);
+        assert!(challenge.answer.chars().all(|c| c.is_ascii_digit()));
+        let image = challenge.image.unwrap();
+        assert!(image.starts_with("data:image/svg+xml;base64,"));
+        //the answer is drawn, never written in the document
+        let svg =
+            openssl::base64::decode_block(&image["data:image/svg+xml;base64,".len()..]).unwrap();
+        let svg = String::from_utf8(svg).unwrap();
+        assert!(svg.starts_with("<svg") && svg.ends_with("</svg>"));
+        assert!(!svg.contains("<text"));
+    }
+}
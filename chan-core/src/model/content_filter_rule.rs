@@ -0,0 +1,150 @@
+use crate::error::ErrorCode;
+use crate::model::Board;
+use crate::schema::content_filter_rules;
+use crate::DBPool;
+use async_graphql::{Result, SimpleObject};
+use chrono::{DateTime, Utc};
+use diesel::prelude::*;
+use diesel::PgConnection;
+use diesel::{Insertable, Queryable};
+use num_traits::{FromPrimitive, ToPrimitive};
+use regex::{Regex, RegexBuilder};
+use uuid::Uuid;
+
+const MAX_PATTERN_LENGTH: usize = 256;
+/// Compiled size limit of regexes, rules are checked on every threadpost.
+const REGEX_SIZE_LIMIT: usize = 1 << 20;
+
+/// What happens to a threadpost matching a rule.
+#[derive(FromPrimitive, PartialEq, Debug, ToPrimitive, Clone, Copy)]
+pub enum FilterAction {
+    Reject = 1,
+    Hold = 2,
+}
+
+impl FilterAction {
+    pub fn from_i32(action: i32) -> anyhow::Result<Self> {
+        let action = FromPrimitive::from_i32(action);
+        match action {
+            Some(a) => Ok(a),
+            None => Err(anyhow::Error::msg("Invalid filter action number")),
+        }
+    }
+}
+
+/// Case-insensitive regex of the pattern.
+pub fn compile_regex(pattern: &str) -> Result<Regex> {
+    RegexBuilder::new(pattern)
+        .case_insensitive(true)
+        .size_limit(REGEX_SIZE_LIMIT)
+        .build()
+        .map_err(|e| ErrorCode::Validation.error(format!("Invalid regex: {}", e)))
+}
+
+/// Check the regex pattern of a new rule.
+/// Patterns matching the empty string(e.g. "a*", "\b") match every text, or insert replacements everywhere.
+pub fn validate_rule_regex(pattern: &str) -> Result<()> {
+    let regex = compile_regex(pattern)?;
+    let matches_empty = ["", "a a\n0"]
+        .iter()
+        .any(|sample| regex.find_iter(sample).any(|m| m.as_str().is_empty()));
+    if matches_empty {
+        return Err(ErrorCode::Validation.error("Pattern must not match the empty string."));
+    }
+    Ok(())
+}
+
+/// Banned word or regex of a board.
+/// Only visible to moderators of the board, so spammers can't read them.
+#[derive(Queryable, SimpleObject, Clone)]
+pub struct ContentFilterRule {
+    #[graphql(skip)]
+    primary_key: i32,
+    pub uuid: Uuid,
+    pub board_id: Uuid,
+    /// Word matched case-insensitively, or a regex if is_regex.
+    pub pattern: String,
+    pub is_regex: bool,
+    /// FilterAction
+    pub action: i32,
+    pub creator_user_id: String,
+    pub created_at: DateTime<Utc>,
+}
+
+impl ContentFilterRule {
+    /// Add a rule to the board.
+    pub fn create_new(
+        db_connection: &PgConnection,
+        creator_user_id: &str,
+        board_uuid: &Uuid,
+        pattern: &str,
+        is_regex: bool,
+        action: FilterAction,
+    ) -> Result<Self> {
+        if pattern.trim().is_empty() || pattern.len() > MAX_PATTERN_LENGTH {
+            return Err(ErrorCode::Validation.error(format!(
+                "Pattern must be 1 to {} bytes.",
+                MAX_PATTERN_LENGTH
+            )));
+        }
+        if is_regex {
+            validate_rule_regex(pattern)?;
+        }
+        if !Board::exists(db_connection, board_uuid)? {
+            return Err(ErrorCode::NotFound.error("Invalid board uuid."));
+        }
+
+        let new_rule = NewContentFilterRule {
+            uuid: &Uuid::new_v4(),
+            board_id: board_uuid,
+            pattern: pattern,
+            is_regex: is_regex,
+            action: action.to_i32().unwrap(),
+            creator_user_id: creator_user_id,
+            created_at: &Utc::now(),
+        };
+        Ok(diesel::insert_into(content_filter_rules::table)
+            .values(&new_rule)
+            .get_result::<ContentFilterRule>(db_connection)?)
+    }
+
+    pub fn select_by_uuid(db_pool: &DBPool, rule_uuid: &Uuid) -> Result<Option<Self>> {
+        use crate::schema::content_filter_rules::dsl::*;
+        Ok(content_filter_rules
+            .filter(uuid.eq(rule_uuid))
+            .first::<ContentFilterRule>(&db_pool.get()?)
+            .optional()?)
+    }
+
+    /// Rules of the board, oldest first.
+    pub fn select_by_board(db_pool: &DBPool, board_uuid: &Uuid) -> Result<Vec<Self>> {
+        use crate::schema::content_filter_rules::dsl::*;
+        Ok(content_filter_rules
+            .filter(board_id.eq(board_uuid))
+            .order_by(created_at.asc())
+            .load::<ContentFilterRule>(&db_pool.get()?)?)
+    }
+
+    pub fn remove_by_uuid(db_connection: &PgConnection, rule_uuid: &Uuid) -> Result<()> {
+        use crate::schema::content_filter_rules::dsl::*;
+        let removed = diesel::delete(content_filter_rules.filter(uuid.eq(rule_uuid)))
+            .execute(db_connection)?;
+        if removed == 0 {
+            return Err(ErrorCode::NotFound.error("Invalid rule uuid."));
+        }
+        Ok(())
+    }
+}
+
+/// diesel model
+#[derive(Insertable)]
+#[table_name = "content_filter_rules"]
+struct NewContentFilterRule<'a> {
+    pub uuid: &'a Uuid,
+    pub board_id: &'a Uuid,
+    pub pattern: &'a str,
+    pub is_regex: bool,
+    pub action: i32,
+    pub creator_user_id: &'a str,
+    pub created_at: &'a DateTime<Utc>,
+}
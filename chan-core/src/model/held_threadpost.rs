@@ -0,0 +1,133 @@
+use crate::error::ErrorCode;
+use crate::schema::held_threadposts;
+use crate::DBPool;
+use async_graphql::{Result, SimpleObject};
+use chrono::{DateTime, Utc};
+use diesel::prelude::*;
+use diesel::PgConnection;
+use diesel::{Insertable, Queryable};
+use uuid::Uuid;
+
+/// Threadpost held by the content filters, a reply or the first post of a new thread.
+/// Moderators of the board approve(post) or discard it.
+#[derive(Queryable, SimpleObject, Clone)]
+pub struct HeldThreadPost {
+    #[graphql(skip)]
+    primary_key: i32,
+    pub uuid: Uuid,
+    pub board_id: Uuid,
+    /// Thread of the reply, None for new threads.
+    pub parent_thread_id: Option<Uuid>,
+    pub poster_user_id: String,
+    pub body_text: String,
+    /// Why the filters held it.
+    pub reason: String,
+    pub held_at: DateTime<Utc>,
+    /// Title of the new thread, None for replies.
+    pub thread_title: Option<String>,
+}
+
+impl HeldThreadPost {
+    /// Hold a reply to the thread.
+    pub fn hold(
+        db_pool: &DBPool,
+        board_uuid: &Uuid,
+        thread_uuid: &Uuid,
+        poster_user_id: &str,
+        post_body: &str,
+        reason: &str,
+    ) -> Result<Self> {
+        let new_held = NewHeldThreadPost {
+            uuid: &Uuid::new_v4(),
+            board_id: board_uuid,
+            parent_thread_id: Some(thread_uuid),
+            poster_user_id: poster_user_id,
+            body_text: post_body,
+            reason: reason,
+            held_at: &Utc::now(),
+            thread_title: None,
+        };
+        Ok(diesel::insert_into(held_threadposts::table)
+            .values(&new_held)
+            .get_result::<HeldThreadPost>(&db_pool.get()?)?)
+    }
+
+    /// Hold a new thread of the board with its first post.
+    pub fn hold_thread(
+        db_pool: &DBPool,
+        board_uuid: &Uuid,
+        poster_user_id: &str,
+        thread_title: &str,
+        first_post_text: &str,
+        reason: &str,
+    ) -> Result<Self> {
+        let new_held = NewHeldThreadPost {
+            uuid: &Uuid::new_v4(),
+            board_id: board_uuid,
+            parent_thread_id: None,
+            poster_user_id: poster_user_id,
+            body_text: first_post_text,
+            reason: reason,
+            held_at: &Utc::now(),
+            thread_title: Some(thread_title),
+        };
+        Ok(diesel::insert_into(held_threadposts::table)
+            .values(&new_held)
+            .get_result::<HeldThreadPost>(&db_pool.get()?)?)
+    }
+
+    pub fn select_by_uuid(db_pool: &DBPool, held_uuid: &Uuid) -> Result<Option<Self>> {
+        use crate::schema::held_threadposts::dsl::*;
+        Ok(held_threadposts
+            .filter(uuid.eq(held_uuid))
+            .first::<HeldThreadPost>(&db_pool.get()?)
+            .optional()?)
+    }
+
+    /// Held threadposts of the board, oldest first.
+    pub fn select_by_board(db_pool: &DBPool, board_uuid: &Uuid) -> Result<Vec<Self>> {
+        use crate::schema::held_threadposts::dsl::*;
+        Ok(held_threadposts
+            .filter(board_id.eq(board_uuid))
+            .order_by(held_at.asc())
+            .load::<HeldThreadPost>(&db_pool.get()?)?)
+    }
+
+    /// Text kept in the audit log when it's approved or discarded.
+    pub fn snapshot(&self) -> String {
+        let thread = match (&self.parent_thread_id, &self.thread_title) {
+            (Some(thread_uuid), _) => thread_uuid.to_string(),
+            (None, title) => format!("(new) {}", title.as_deref().unwrap_or_default()),
+        };
+        format!(
+            "poster: {}\nthread: {}\nheld: {}\n\n{}",
+            self.poster_user_id, thread, self.reason, self.body_text
+        )
+    }
+
+    /// Delete the held threadpost.
+    /// Fails if it's already gone, so two moderators can't both approve it.
+    pub fn remove_by_uuid(db_connection: &PgConnection, held_uuid: &Uuid) -> Result<()> {
+        use crate::schema::held_threadposts::dsl::*;
+        let removed =
+            diesel::delete(held_threadposts.filter(uuid.eq(held_uuid))).execute(db_connection)?;
+        if removed == 0 {
+            return Err(ErrorCode::NotFound.error("Invalid held threadpost uuid."));
+        }
+        Ok(())
+    }
+}
+
+/// diesel model
+#[derive(Insertable)]
+#[table_name = "held_threadposts"]
+struct NewHeldThreadPost<'a> {
+    pub uuid: &'a Uuid,
+    pub board_id: &'a Uuid,
+    pub parent_thread_id: Option<&'a Uuid>,
+    pub poster_user_id: &'a str,
+    pub body_text: &'a str,
+    pub reason: &'a str,
+    pub held_at: &'a DateTime<Utc>,
+    pub thread_title: Option<&'a str>,
+}
@@ -1,4 +1,5 @@
 use crate::diesel::associations::HasTable;
+use crate::pagination::KeysetPage;
 use crate::schema::logs;
 use crate::DBPool;
 use async_graphql::{Error, Result, SimpleObject};
@@ -39,13 +40,41 @@ impl Log {
         Ok(log)
     }
 
-    pub fn range(db_pool: &DBPool, start: i64, end: i64) -> Result<Vec<Log>> {
+    /// Cursor key of the log.
+    pub fn key(&self) -> i32 {
+        self.primary_key
+    }
+
+    /// Oldest first(or newest first), keyed by primary_key.
+    pub fn page(db_pool: &DBPool, newest_first: bool, page: &KeysetPage) -> Result<Vec<Log>> {
         use crate::schema::logs::dsl::*;
-        Ok(logs
-            .limit(end - start + 1)
-            .order_by(primary_key)
-            .offset(start)
-            .load::<Log>(&db_pool.get()?)?)
+        let mut query = logs.into_boxed();
+        if newest_first {
+            if let Some(key) = page.after {
+                query = query.filter(primary_key.lt(key));
+            }
+            if let Some(key) = page.before {
+                query = query.filter(primary_key.gt(key));
+            }
+            query = if page.backward {
+                query.order_by(primary_key)
+            } else {
+                query.order_by(primary_key.desc())
+            };
+        } else {
+            if let Some(key) = page.after {
+                query = query.filter(primary_key.gt(key));
+            }
+            if let Some(key) = page.before {
+                query = query.filter(primary_key.lt(key));
+            }
+            query = if page.backward {
+                query.order_by(primary_key.desc())
+            } else {
+                query.order_by(primary_key)
+            };
+        }
+        Ok(query.limit(page.limit).load::<Log>(&db_pool.get()?)?)
     }
 }
 
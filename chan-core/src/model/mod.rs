@@ -1,15 +1,41 @@
+mod ban;
 mod board;
+mod board_moderator;
+mod captcha;
+mod content_filter_rule;
+mod held_threadpost;
 mod log;
+mod moderation_action;
+mod notification;
+mod password_reset;
+mod permission;
 mod private_message;
+mod refresh_token;
 mod system_info;
 mod thread;
+mod thread_watch;
 mod threadpost;
 mod token;
 mod user;
+mod word_filter_rule;
+pub use ban::Ban;
 pub use board::Board;
+pub use board_moderator::BoardModerator;
+pub use captcha::{Captcha, CaptchaAnswer, CaptchaProvider, Challenge, ImageCaptcha, IssuedCaptcha};
+pub use content_filter_rule::{compile_regex, validate_rule_regex, ContentFilterRule, FilterAction};
+pub use held_threadpost::HeldThreadPost;
 pub use log::Log;
+pub use moderation_action::{ModerationAction, ModerationActionFilter, ModerationActionType};
+pub use notification::{Notification, NotificationKind};
+pub use password_reset::PasswordResetToken;
+pub use permission::{capabilities_of, has_capability, require, Capability, Scope};
+pub use refresh_token::RefreshToken;
 pub use system_info::{SystemInfo, SystemInfoContext};
 pub use thread::Thread;
+pub use thread_watch::ThreadWatch;
 pub use threadpost::ThreadPost;
-pub use token::{verify_token, TokenClaim};
-pub use user::{User, UserStatus, UserType};
+pub use token::{
+    verify_token, verify_token_claim, AuthTokens, ClientInfo, IssuedToken, TokenClaim, TokenConfig,
+};
+pub use user::{ContentRemovalPolicy, User, UserStatus, UserType, ANONYMOUS_USER_ID};
+pub use word_filter_rule::{apply_word_filters, WordFilterRule};
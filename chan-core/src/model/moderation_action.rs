@@ -0,0 +1,153 @@
+use crate::pagination::KeysetPage;
+use crate::schema::moderation_actions;
+use crate::DBPool;
+use async_graphql::{Result, SimpleObject};
+use chrono::{DateTime, Utc};
+use diesel::pg::Pg;
+use diesel::prelude::*;
+use diesel::PgConnection;
+use diesel::{Insertable, Queryable};
+use num_traits::{FromPrimitive, ToPrimitive};
+use uuid::Uuid;
+
+#[derive(FromPrimitive, PartialEq, Debug, ToPrimitive, Clone, Copy)]
+pub enum ModerationActionType {
+    RemoveThread = 1,
+    RemoveThreadpost = 2,
+    ChangeUserType = 3,
+    SuspendUser = 4,
+    BanUser = 5,
+    LiftBan = 6,
+    IssuePasswordResetToken = 7,
+    AssignBoardModerator = 8,
+    RevokeBoardModerator = 9,
+    AddContentFilterRule = 10,
+    RemoveContentFilterRule = 11,
+    ApproveHeldThreadpost = 12,
+    DiscardHeldThreadpost = 13,
+    AddWordFilterRule = 14,
+    RemoveWordFilterRule = 15,
+    LockThread = 16,
+    UnlockThread = 17,
+}
+
+impl ModerationActionType {
+    pub fn from_i32(action_type: i32) -> anyhow::Result<Self> {
+        let action_type = FromPrimitive::from_i32(action_type);
+        match action_type {
+            Some(t) => Ok(t),
+            None => Err(anyhow::Error::msg("Invalid moderation action type number")),
+        }
+    }
+}
+
+/// Audit log entry of a moderator action, only visible to admins.
+/// Unlike Log, this is not public and records who did what.
+#[derive(Queryable, SimpleObject, Clone)]
+pub struct ModerationAction {
+    #[graphql(skip)]
+    primary_key: i32,
+    pub uuid: Uuid,
+    pub actor_user_id: String,
+    /// ModerationActionType
+    pub action_type: i32,
+    /// UUID of the thread/threadpost or ID of the user.
+    pub target: String,
+    pub reason: Option<String>,
+    /// Removed content, kept for review.
+    pub content_snapshot: Option<String>,
+    pub created_at: DateTime<Utc>,
+}
+
+/// Conditions for listing moderation actions, None matches all.
+#[derive(Default, Debug, Clone)]
+pub struct ModerationActionFilter {
+    pub actor_user_id: Option<String>,
+    pub action_type: Option<ModerationActionType>,
+    pub target: Option<String>,
+}
+
+impl ModerationAction {
+    /// Append a new entry to the audit log.
+    /// Pass the connection of the transaction of the action, so the entry is written only with the action.
+    pub fn record(
+        db_connection: &PgConnection,
+        actor_user_id: &str,
+        action_type: ModerationActionType,
+        target: &str,
+        reason: Option<&str>,
+        content_snapshot: Option<&str>,
+    ) -> Result<Self> {
+        let new_action = NewModerationAction {
+            uuid: &Uuid::new_v4(),
+            actor_user_id: actor_user_id,
+            action_type: action_type.to_i32().unwrap(),
+            target: target,
+            reason: reason,
+            content_snapshot: content_snapshot,
+            created_at: &Utc::now(),
+        };
+        Ok(diesel::insert_into(moderation_actions::table)
+            .values(&new_action)
+            .get_result::<ModerationAction>(db_connection)?)
+    }
+
+    fn filtered_query<'a>(
+        filter: &'a ModerationActionFilter,
+    ) -> moderation_actions::BoxedQuery<'a, Pg> {
+        use crate::schema::moderation_actions::dsl;
+        let mut query = dsl::moderation_actions.into_boxed();
+        if let Some(actor) = &filter.actor_user_id {
+            query = query.filter(dsl::actor_user_id.eq(actor));
+        }
+        if let Some(t) = &filter.action_type {
+            query = query.filter(dsl::action_type.eq(t.to_i32().unwrap()));
+        }
+        if let Some(target) = &filter.target {
+            query = query.filter(dsl::target.eq(target));
+        }
+        query
+    }
+
+    /// Cursor key of the action.
+    pub fn key(&self) -> i32 {
+        self.primary_key
+    }
+
+    /// Newest first, keyed by primary_key.
+    pub fn page(
+        db_pool: &DBPool,
+        filter: &ModerationActionFilter,
+        page: &KeysetPage,
+    ) -> Result<Vec<ModerationAction>> {
+        use crate::schema::moderation_actions::dsl::*;
+        let mut query = ModerationAction::filtered_query(&filter);
+        if let Some(key) = page.after {
+            query = query.filter(primary_key.lt(key));
+        }
+        if let Some(key) = page.before {
+            query = query.filter(primary_key.gt(key));
+        }
+        query = if page.backward {
+            query.order_by(primary_key)
+        } else {
+            query.order_by(primary_key.desc())
+        };
+        Ok(query
+            .limit(page.limit)
+            .load::<ModerationAction>(&db_pool.get()?)?)
+    }
+}
+
+/// diesel model
+#[derive(Insertable)]
+#[table_name = "moderation_actions"]
+struct NewModerationAction<'a> {
+    pub uuid: &'a Uuid,
+    pub actor_user_id: &'a str,
+    pub action_type: i32,
+    pub target: &'a str,
+    pub reason: Option<&'a str>,
+    pub content_snapshot: Option<&'a str>,
+    pub created_at: &'a DateTime<Utc>,
+}
@@ -0,0 +1,272 @@
+use crate::error::ErrorCode;
+use crate::loader::{ThreadLoader, ThreadPostLoader};
+use crate::model::{Thread, ThreadPost, ANONYMOUS_USER_ID};
+use crate::pagination::KeysetPage;
+use crate::schema::notifications;
+use crate::DBPool;
+use async_graphql::dataloader::DataLoader;
+use async_graphql::{ComplexObject, Context, Result, SimpleObject};
+use chrono::{DateTime, Utc};
+use diesel::prelude::*;
+use diesel::PgConnection;
+use diesel::{Insertable, Queryable};
+use num_traits::{FromPrimitive, ToPrimitive};
+use std::collections::HashSet;
+use uuid::Uuid;
+
+/// References of a threadpost beyond this are ignored, so one threadpost can't notify everyone.
+const MAX_REFERENCES: usize = 16;
+
+#[derive(FromPrimitive, PartialEq, Debug, ToPrimitive, Clone, Copy)]
+pub enum NotificationKind {
+    /// A threadpost replied to a threadpost of the user with >>number.
+    Reply = 1,
+    /// A threadpost mentioned the user with @id.
+    Mention = 2,
+}
+
+impl NotificationKind {
+    pub fn from_i32(kind: i32) -> anyhow::Result<Self> {
+        let kind = FromPrimitive::from_i32(kind);
+        match kind {
+            Some(k) => Ok(k),
+            None => Err(anyhow::Error::msg("Invalid notification kind number")),
+        }
+    }
+}
+
+/// Notification of a reply to or a mention of the recipient.
+/// Only visible to the recipient.
+#[derive(Queryable, SimpleObject, Clone)]
+#[graphql(complex)]
+pub struct Notification {
+    #[graphql(skip)]
+    primary_key: i32,
+    pub uuid: Uuid,
+    pub recipient_user_id: String,
+    /// NotificationKind
+    pub kind: i32,
+    /// Poster of the threadpost.
+    pub actor_user_id: String,
+    pub thread_id: Uuid,
+    pub threadpost_id: Uuid,
+    pub created_at: DateTime<Utc>,
+    /// Null if unread.
+    pub read_at: Option<DateTime<Utc>>,
+}
+
+#[ComplexObject]
+impl Notification {
+    /// Threadpost replying to or mentioning the recipient.
+    async fn threadpost(&self, context: &Context<'_>) -> Result<Option<ThreadPost>> {
+        let loader = context.data::<DataLoader<ThreadPostLoader>>()?;
+        loader.load_one(self.threadpost_id).await
+    }
+
+    /// Thread of the threadpost.
+    async fn thread(&self, context: &Context<'_>) -> Result<Option<Thread>> {
+        let loader = context.data::<DataLoader<ThreadLoader>>()?;
+        loader.load_one(self.thread_id).await
+    }
+}
+
+/// Threadpost numbers(>>number) and user ids(@id) referenced by the body, without duplicates.
+fn parse_references(body: &str) -> (Vec<i32>, Vec<String>) {
+    let mut numbers = Vec::new();
+    let mut user_ids = Vec::new();
+    let chars: Vec<char> = body.chars().collect();
+    let mut i = 0;
+    while i < chars.len() && numbers.len() + user_ids.len() < MAX_REFERENCES {
+        if chars[i] == '>' && chars.get(i + 1) == Some(&'>') {
+            let digits: String = chars[i + 2..]
+                .iter()
+                .take_while(|c| c.is_ascii_digit())
+                .collect();
+            i += 2 + digits.len();
+            if let Ok(number) = digits.parse::<i32>() {
+                if !numbers.contains(&number) {
+                    numbers.push(number);
+                }
+            }
+        } else if chars[i] == '@' && (i == 0 || !chars[i - 1].is_alphanumeric()) {
+            //ids are lowercase alphanumeric, a preceding character means an email address
+            let id: String = chars[i + 1..]
+                .iter()
+                .take_while(|c| c.is_ascii_lowercase() || c.is_ascii_digit())
+                .collect();
+            i += 1 + id.len();
+            if !id.is_empty() && !user_ids.contains(&id) {
+                user_ids.push(id);
+            }
+        } else {
+            i += 1;
+        }
+    }
+    (numbers, user_ids)
+}
+
+impl Notification {
+    /// Notify posters of the threadposts the threadpost replies to, and users it mentions.
+    /// The poster and the anonymous user are never notified, a user is notified once per threadpost.
+    /// Returns the number of notifications.
+    /// Call it on the connection the threadpost is inserted with, the threadpost may be uncommitted.
+    pub fn notify_references(
+        db_connection: &PgConnection,
+        threadpost: &ThreadPost,
+    ) -> Result<usize> {
+        use crate::schema::{threadposts, users};
+        let (numbers, user_ids) = parse_references(&threadpost.body_text);
+        if numbers.is_empty() && user_ids.is_empty() {
+            return Ok(0);
+        }
+
+        let replied_user_ids = threadposts::table
+            .filter(threadposts::parent_thread_id.eq(&threadpost.parent_thread_id))
+            .filter(threadposts::number.eq_any(&numbers))
+            .select(threadposts::poster_user_id)
+            .load::<String>(db_connection)?;
+        let mentioned_user_ids = users::table
+            .filter(users::id.eq_any(&user_ids))
+            .select(users::id)
+            .load::<String>(db_connection)?;
+
+        let now = Utc::now();
+        let mut notified = HashSet::new();
+        notified.insert(threadpost.poster_user_id.as_str());
+        notified.insert(ANONYMOUS_USER_ID);
+        let mut new_notifications = Vec::new();
+        //replies first, a user both replied to and mentioned gets a reply notification
+        for (recipient, kind) in replied_user_ids
+            .iter()
+            .map(|id| (id, NotificationKind::Reply))
+            .chain(
+                mentioned_user_ids
+                    .iter()
+                    .map(|id| (id, NotificationKind::Mention)),
+            )
+        {
+            if !notified.insert(recipient.as_str()) {
+                continue;
+            }
+            new_notifications.push(NewNotification {
+                uuid: Uuid::new_v4(),
+                recipient_user_id: recipient,
+                kind: kind.to_i32().unwrap(),
+                actor_user_id: &threadpost.poster_user_id,
+                thread_id: &threadpost.parent_thread_id,
+                threadpost_id: &threadpost.uuid,
+                created_at: &now,
+            });
+        }
+        if new_notifications.is_empty() {
+            return Ok(0);
+        }
+        Ok(diesel::insert_into(notifications::table)
+            .values(&new_notifications)
+            .execute(db_connection)?)
+    }
+
+    pub fn count_unread(db_pool: &DBPool, user_id: &str) -> Result<i64> {
+        use crate::schema::notifications::dsl::*;
+        Ok(notifications
+            .filter(recipient_user_id.eq(user_id))
+            .filter(read_at.is_null())
+            .count()
+            .get_result(&db_pool.get()?)?)
+    }
+
+    /// Cursor key of the notification.
+    pub fn key(&self) -> i32 {
+        self.primary_key
+    }
+
+    /// Notifications of the user, newest first, keyed by primary_key.
+    pub fn page(
+        db_pool: &DBPool,
+        user_id: &str,
+        unread_only: bool,
+        page: &KeysetPage,
+    ) -> Result<Vec<Notification>> {
+        use crate::schema::notifications::dsl::*;
+        let mut query = notifications
+            .filter(recipient_user_id.eq(user_id))
+            .into_boxed();
+        if unread_only {
+            query = query.filter(read_at.is_null());
+        }
+        if let Some(key) = page.after {
+            query = query.filter(primary_key.lt(key));
+        }
+        if let Some(key) = page.before {
+            query = query.filter(primary_key.gt(key));
+        }
+        query = if page.backward {
+            query.order_by(primary_key)
+        } else {
+            query.order_by(primary_key.desc())
+        };
+        Ok(query
+            .limit(page.limit)
+            .load::<Notification>(&db_pool.get()?)?)
+    }
+
+    /// Mark unread notifications of the user read, all of them if notification_uuids is None.
+    /// Returns the number of marked notifications.
+    pub fn mark_read(
+        db_pool: &DBPool,
+        user_id: &str,
+        notification_uuids: Option<&[Uuid]>,
+    ) -> Result<usize> {
+        use crate::schema::notifications::dsl::*;
+        let query = notifications
+            .filter(recipient_user_id.eq(user_id))
+            .filter(read_at.is_null());
+        let marked = match notification_uuids {
+            Some(uuids) => {
+                if uuids.len() > crate::pagination::MAX_PAGE_SIZE {
+                    return Err(ErrorCode::Validation.error("Too many notifications."));
+                }
+                diesel::update(query.filter(uuid.eq_any(uuids)))
+                    .set(read_at.eq(Utc::now()))
+                    .execute(&db_pool.get()?)?
+            }
+            None => diesel::update(query)
+                .set(read_at.eq(Utc::now()))
+                .execute(&db_pool.get()?)?,
+        };
+        Ok(marked)
+    }
+}
+
+/// diesel model
+#[derive(Insertable)]
+#[table_name = "notifications"]
+struct NewNotification<'a> {
+    pub uuid: Uuid,
+    pub recipient_user_id: &'a str,
+    pub kind: i32,
+    pub actor_user_id: &'a str,
+    pub thread_id: &'a Uuid,
+    pub threadpost_id: &'a Uuid,
+    pub created_at: &'a DateTime<Utc>,
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn references() {
+        let (numbers, user_ids) =
+            parse_references(">>1 >>12 agreed\n>>1 @abc0 mail me at a@example.com @Abc >>x");
+        assert_eq!(numbers, vec![1, 12]);
+        assert_eq!(user_ids, vec!["abc0".to_string()]);
+
+        let (numbers, user_ids) = parse_references("no references > > @");
+        assert!(numbers.is_empty());
+        assert!(user_ids.is_empty());
+
+        let many: String = (1..100).map(|n| format!(">>{} ", n)).collect();
+        assert_eq!(parse_references(&many).0.len(), MAX_REFERENCES);
+    }
+}
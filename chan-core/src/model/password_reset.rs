@@ -0,0 +1,110 @@
+use crate::error::ErrorCode;
+use crate::model::token::{generate_random_token, hash_token};
+use crate::model::user::{check_password, hash_password};
+use crate::model::{IssuedToken, User};
+use crate::schema::password_reset_tokens;
+use crate::DBPool;
+use async_graphql::{Error, Result};
+use chrono::{DateTime, Duration, Utc};
+use diesel::prelude::*;
+use diesel::PgConnection;
+use diesel::{Insertable, Queryable};
+
+/// One-time password reset token issued by admins.
+/// Accounts have no email, so this is the only way to recover a forgotten password.
+#[derive(Queryable, Clone)]
+pub struct PasswordResetToken {
+    primary_key: i32,
+    pub token_hash: String,
+    pub user_id: String,
+    pub issuer_user_id: String,
+    pub issued_at: DateTime<Utc>,
+    pub expires_at: DateTime<Utc>,
+    pub used_at: Option<DateTime<Utc>>,
+}
+
+impl PasswordResetToken {
+    /// Issue a new reset token for the user.
+    /// Returns the raw token.(only the hash is stored)
+    pub fn issue(
+        db_connection: &PgConnection,
+        issuer_user_id: &str,
+        user_id: &str,
+    ) -> Result<String> {
+        if !User::exists(db_connection, user_id)? {
+            return Err(ErrorCode::NotFound.error("User does not exist"));
+        }
+
+        let token = generate_random_token()?;
+
+        let datetime_now = Utc::now();
+        let new_token = NewPasswordResetToken {
+            token_hash: &hash_token(&token),
+            user_id: user_id,
+            issuer_user_id: issuer_user_id,
+            issued_at: &datetime_now,
+            expires_at: &(datetime_now + Duration::hours(24)), //expires after 24 hours
+        };
+        diesel::insert_into(password_reset_tokens::table)
+            .values(&new_token)
+            .execute(db_connection)?;
+        Ok(token)
+    }
+
+    /// Reset the password with the token.
+    /// Revokes all tokens of the user.
+    pub fn reset_password(
+        db_pool: &DBPool,
+        user_id: &str,
+        token: &str,
+        new_password: &str,
+    ) -> Result<()> {
+        use crate::schema::password_reset_tokens::dsl;
+
+        //check before consuming the token
+        check_password(new_password)?;
+
+        let hashed_password = hash_password(new_password)?;
+        let datetime_now = Utc::now();
+        let db_connection = db_pool.get()?;
+        //the token is consumed only if the password is changed
+        db_connection.build_transaction().run::<_, Error, _>(|| {
+            //mark as used atomically, so the token can't be used twice
+            let consumed = diesel::update(
+                dsl::password_reset_tokens
+                    .filter(dsl::token_hash.eq(hash_token(token)))
+                    .filter(dsl::user_id.eq(user_id))
+                    .filter(dsl::used_at.is_null())
+                    .filter(dsl::expires_at.gt(datetime_now)),
+            )
+            .set(dsl::used_at.eq(Some(datetime_now)))
+            .execute(&db_connection)?;
+            if consumed != 1 {
+                debug_assert_eq!(consumed, 0);
+                return Err(ErrorCode::Validation.error("Invalid or expired reset token."));
+            }
+
+            use crate::schema::users;
+            diesel::update(users::table.filter(users::id.eq(user_id)))
+                .set((
+                    users::argon2_password.eq(hashed_password),
+                    users::tokens_valid_after.eq(Some(datetime_now)),
+                ))
+                .execute(&db_connection)?;
+            Ok(())
+        })?;
+        IssuedToken::revoke_all(&db_pool, user_id)?;
+        Ok(())
+    }
+}
+
+/// diesel model
+#[derive(Insertable)]
+#[table_name = "password_reset_tokens"]
+struct NewPasswordResetToken<'a> {
+    pub token_hash: &'a str,
+    pub user_id: &'a str,
+    pub issuer_user_id: &'a str,
+    pub issued_at: &'a DateTime<Utc>,
+    pub expires_at: &'a DateTime<Utc>,
+}
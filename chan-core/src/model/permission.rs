@@ -0,0 +1,140 @@
+use crate::error::ErrorCode;
+use crate::model::{BoardModerator, User, UserType};
+use crate::DBPool;
+use async_graphql::Result;
+use uuid::Uuid;
+
+/// Things a user may be allowed to do.
+#[derive(PartialEq, Debug, Clone, Copy)]
+pub enum Capability {
+    RemoveThread,
+    RemoveThreadpost,
+    /// Lock threads against new threadposts, and unlock them.
+    LockThread,
+    BanUser,
+    ChangeUserType,
+    IssuePasswordResetToken,
+    /// View and revoke sessions of other users.
+    ManageSessions,
+    ManageBoardModerators,
+    ViewAuditLog,
+    /// Banned words and regexes of boards.
+    ManageContentFilters,
+    /// Approve or discard threadposts held by the content filters.
+    ReviewHeldThreadposts,
+    /// Find-and-replace rules of boards.
+    ManageWordFilters,
+    /// Titles and threadposts as submitted, before the word filters.
+    ViewOriginalText,
+}
+
+/// Where the capability is used.
+#[derive(PartialEq, Debug, Clone, Copy)]
+pub enum Scope<'a> {
+    /// Whole site.
+    Global,
+    /// Threads and threadposts in the board.
+    Board(&'a Uuid),
+}
+
+const ADMIN_CAPABILITIES: &[Capability] = &[
+    Capability::RemoveThread,
+    Capability::RemoveThreadpost,
+    Capability::LockThread,
+    Capability::BanUser,
+    Capability::ChangeUserType,
+    Capability::IssuePasswordResetToken,
+    Capability::ManageSessions,
+    Capability::ManageBoardModerators,
+    Capability::ViewAuditLog,
+    Capability::ManageContentFilters,
+    Capability::ReviewHeldThreadposts,
+    Capability::ManageWordFilters,
+    Capability::ViewOriginalText,
+];
+
+/// Site-wide capabilities of moderators.
+/// Removing contents requires assignment to the board.
+const MODERATOR_CAPABILITIES: &[Capability] = &[Capability::BanUser];
+
+const NORMAL_CAPABILITIES: &[Capability] = &[];
+
+/// Capabilities of users assigned to a board, only in the board.
+const BOARD_MODERATOR_CAPABILITIES: &[Capability] = &[
+    Capability::RemoveThread,
+    Capability::RemoveThreadpost,
+    Capability::LockThread,
+    Capability::ManageContentFilters,
+    Capability::ReviewHeldThreadposts,
+    Capability::ManageWordFilters,
+    Capability::ViewOriginalText,
+];
+
+/// Site-wide capabilities of the user type.
+pub fn capabilities_of(user_type: &UserType) -> &'static [Capability] {
+    match user_type {
+        UserType::Admin => ADMIN_CAPABILITIES,
+        UserType::Moderator => MODERATOR_CAPABILITIES,
+        UserType::Normal => NORMAL_CAPABILITIES,
+    }
+}
+
+/// Returns Err unless the user has the capability in the scope.
+pub fn require(
+    db_pool: &DBPool,
+    user_id: &str,
+    capability: Capability,
+    scope: Scope,
+) -> Result<()> {
+    if !has_capability(db_pool, user_id, capability, scope)? {
+        return Err(ErrorCode::Forbidden.error("Not allowed."));
+    }
+    Ok(())
+}
+
+/// Whether the user has the capability in the scope.
+/// For showing extra fields to moderators, use require to guard actions.
+pub fn has_capability(
+    db_pool: &DBPool,
+    user_id: &str,
+    capability: Capability,
+    scope: Scope,
+) -> Result<bool> {
+    let user = match User::select_by_user_id(&db_pool, user_id)? {
+        Some(u) => u,
+        None => return Err(ErrorCode::NotFound.error("User does not exist")),
+    };
+    let user_type = UserType::from_i32(user.user_type)?;
+    if capabilities_of(&user_type).contains(&capability) {
+        return Ok(true);
+    }
+
+    if let Scope::Board(board_uuid) = scope {
+        if BOARD_MODERATOR_CAPABILITIES.contains(&capability)
+            && BoardModerator::is_moderator(&db_pool, board_uuid, user_id)?
+        {
+            return Ok(true);
+        }
+    }
+    Ok(false)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    #[test]
+    fn capabilities() {
+        let admin = capabilities_of(&UserType::Admin);
+        assert!(admin.contains(&Capability::RemoveThread));
+        assert!(admin.contains(&Capability::ManageBoardModerators));
+
+        let moderator = capabilities_of(&UserType::Moderator);
+        assert!(moderator.contains(&Capability::BanUser));
+        assert!(!moderator.contains(&Capability::RemoveThreadpost)); //only in assigned boards
+        assert!(!moderator.contains(&Capability::ViewAuditLog));
+        assert!(!moderator.contains(&Capability::ManageContentFilters)); //only in assigned boards
+        assert!(!moderator.contains(&Capability::ViewOriginalText)); //only in assigned boards
+
+        assert!(capabilities_of(&UserType::Normal).is_empty());
+    }
+}
@@ -0,0 +1,173 @@
+use crate::error::ErrorCode;
+use crate::model::token::{generate_random_token, hash_token, issue_auth_tokens};
+use crate::model::{AuthTokens, Ban, ClientInfo, IssuedToken, TokenConfig, User, UserStatus};
+use crate::schema::refresh_tokens;
+use crate::DBPool;
+use async_graphql::Result;
+use chrono::{DateTime, Duration, Utc};
+use diesel::prelude::*;
+use diesel::{Insertable, Queryable};
+use tracing::{event, Level};
+use uuid::Uuid;
+
+/// Reuse of a refresh token this soon after its rotation is rejected without revoking the other tokens.
+const REUSE_GRACE_SECONDS: i64 = 30;
+
+/// Long-lived, one-time token to get a new access token.
+/// Each refresh token is bound to the access token(session) issued with it.
+#[derive(Queryable, Clone)]
+pub struct RefreshToken {
+    primary_key: i32,
+    pub token_hash: String,
+    pub user_id: String,
+    pub access_token_uuid: Uuid,
+    pub issued_at: DateTime<Utc>,
+    pub expires_at: DateTime<Utc>,
+    pub used_at: Option<DateTime<Utc>>,
+    pub revoked_at: Option<DateTime<Utc>>,
+}
+
+impl RefreshToken {
+    /// Issue a new refresh token bound to the access token.
+    /// Returns the raw token.(only the hash is stored)
+    pub fn issue(
+        db_pool: &DBPool,
+        user_id: &str,
+        access_token_uuid: &Uuid,
+        lifetime: Duration,
+    ) -> Result<String> {
+        let token = generate_random_token()?;
+        let datetime_now = Utc::now();
+        let new_token = NewRefreshToken {
+            token_hash: &hash_token(&token),
+            user_id: user_id,
+            access_token_uuid: access_token_uuid,
+            issued_at: &datetime_now,
+            expires_at: &(datetime_now + lifetime),
+        };
+        diesel::insert_into(refresh_tokens::table)
+            .values(&new_token)
+            .execute(&db_pool.get()?)?;
+        Ok(token)
+    }
+
+    fn select_by_token(db_pool: &DBPool, token: &str) -> Result<Option<Self>> {
+        use crate::schema::refresh_tokens::dsl::*;
+        let v = refresh_tokens
+            .filter(token_hash.eq(hash_token(token)))
+            .limit(1)
+            .load::<RefreshToken>(&db_pool.get()?)?;
+        debug_assert!(v.len() <= 1);
+        Ok(v.into_iter().next())
+    }
+
+    /// Exchange the refresh token for a new access token and refresh token.(rotation)
+    /// The old access token is revoked, and the session keeps its client information.
+    /// Using an already used refresh token revokes all tokens of the user,
+    /// because it means the token was stolen.(except within REUSE_GRACE_SECONDS after the rotation)
+    pub fn exchange(
+        db_pool: &DBPool,
+        token: &str,
+        token_config: &TokenConfig,
+    ) -> Result<AuthTokens> {
+        let refresh_token = match RefreshToken::select_by_token(&db_pool, token)? {
+            Some(t) => t,
+            None => return Err(ErrorCode::Unauthenticated.error("Invalid refresh token.")),
+        };
+        if refresh_token.revoked_at.is_some() || refresh_token.expires_at < Utc::now() {
+            return Err(ErrorCode::Unauthenticated.error("Invalid refresh token."));
+        }
+
+        //mark as used atomically, so the token can't be used twice
+        let consumed = {
+            use crate::schema::refresh_tokens::dsl::*;
+            diesel::update(
+                refresh_tokens
+                    .filter(token_hash.eq(&refresh_token.token_hash))
+                    .filter(used_at.is_null()),
+            )
+            .set(used_at.eq(Some(Utc::now())))
+            .execute(&db_pool.get()?)?
+        };
+        if consumed != 1 {
+            //concurrent refreshes of one session(e.g. two tabs) reuse the token right after rotation
+            let used_at = RefreshToken::select_by_token(&db_pool, token)?.and_then(|t| t.used_at);
+            if let Some(used_at) = used_at {
+                if Utc::now() - used_at < Duration::seconds(REUSE_GRACE_SECONDS) {
+                    return Err(ErrorCode::Unauthenticated.error("Invalid refresh token."));
+                }
+            }
+            event!(
+                Level::WARN,
+                "Refresh token reuse detected, revoke all tokens of {}",
+                refresh_token.user_id
+            );
+            IssuedToken::revoke_all(&db_pool, &refresh_token.user_id)?;
+            return Err(ErrorCode::Unauthenticated.error("Invalid refresh token."));
+        }
+
+        //check user status
+        let user = match User::select_by_user_id(&db_pool, &refresh_token.user_id)? {
+            Some(u) => u,
+            None => return Err(ErrorCode::Unauthenticated.error("User does not exist")),
+        };
+        let status = Ban::effective_status(&db_pool, &user)?;
+        if status != UserStatus::Normal {
+            return Err(ErrorCode::Forbidden.error("Not allowed."));
+        }
+
+        //keep client information of the session
+        let client_info =
+            match IssuedToken::select_by_uuid(&db_pool, &refresh_token.access_token_uuid)? {
+                Some(old) => ClientInfo {
+                    user_agent: old.user_agent,
+                    ip_address: old.ip_address,
+                },
+                None => ClientInfo::default(),
+            };
+        IssuedToken::revoke(
+            &db_pool,
+            &refresh_token.user_id,
+            &refresh_token.access_token_uuid,
+        )?;
+
+        issue_auth_tokens(&db_pool, &user.id, &token_config, &client_info)
+    }
+
+    /// Revoke the refresh token bound to the access token.
+    pub fn revoke_by_access_token(db_pool: &DBPool, uuid: &Uuid) -> Result<()> {
+        use crate::schema::refresh_tokens::dsl::*;
+        diesel::update(
+            refresh_tokens
+                .filter(access_token_uuid.eq(uuid))
+                .filter(revoked_at.is_null()),
+        )
+        .set(revoked_at.eq(Some(Utc::now())))
+        .execute(&db_pool.get()?)?;
+        Ok(())
+    }
+
+    /// Revoke all refresh tokens of the user.
+    pub fn revoke_all(db_pool: &DBPool, owner_user_id: &str) -> Result<()> {
+        use crate::schema::refresh_tokens::dsl::*;
+        diesel::update(
+            refresh_tokens
+                .filter(user_id.eq(owner_user_id))
+                .filter(revoked_at.is_null()),
+        )
+        .set(revoked_at.eq(Some(Utc::now())))
+        .execute(&db_pool.get()?)?;
+        Ok(())
+    }
+}
+
+/// diesel model
+#[derive(Insertable)]
+#[table_name = "refresh_tokens"]
+struct NewRefreshToken<'a> {
+    pub token_hash: &'a str,
+    pub user_id: &'a str,
+    pub access_token_uuid: &'a Uuid,
+    pub issued_at: &'a DateTime<Utc>,
+    pub expires_at: &'a DateTime<Utc>,
+}
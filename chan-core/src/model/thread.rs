@@ -1,48 +1,214 @@
-use crate::model::{threadpost::NewThreadPost, ThreadPost};
+use crate::content_filter::{ContentFilterChain, FilterDecision, PostCandidate};
+use crate::error::ErrorCode;
+use crate::guard::AuthenticatedUser;
+use crate::loader::{BoardLoader, ThreadPostCountLoader, ThreadPostRange, ThreadPostsLoader};
+use crate::model::{
+    apply_word_filters, has_capability,
+    threadpost::{validate_body, NewThreadPost},
+    Board, Capability, HeldThreadPost, Notification, Scope, ThreadPost, WordFilterRule,
+};
+use crate::pagination::{list_complexity, KeysetPage};
 use crate::schema::threads;
-use crate::DBPool;
-use async_graphql::{Error, Result, SimpleObject};
+use crate::{run_blocking, DBPool};
+use async_graphql::dataloader::DataLoader;
+use async_graphql::{ComplexObject, Context, Error, Result, SimpleObject};
 use chrono::{DateTime, Utc};
 use diesel::prelude::*;
+use diesel::PgConnection;
 use diesel::{Insertable, Queryable};
 use tantivy::{doc, Index, IndexReader, IndexWriter, Term};
+use tracing::{event, Level};
 use uuid::Uuid;
 
-#[derive(Queryable, SimpleObject, Clone)]
+/// threads.title is VARCHAR(47)
+pub const MAX_TITLE_CHARS: usize = 47;
+
+/// Title must be 1 to MAX_TITLE_CHARS characters, checked before and after the word filters.
+fn validate_title(title: &str) -> Result<()> {
+    if title.trim().is_empty() || title.chars().count() > MAX_TITLE_CHARS {
+        return Err(ErrorCode::Validation.error(format!(
+            "Title must be 1 to {} characters.",
+            MAX_TITLE_CHARS
+        )));
+    }
+    Ok(())
+}
+
+#[derive(Queryable, QueryableByName, SimpleObject, Clone)]
+#[table_name = "threads"]
+#[graphql(complex)]
 pub struct Thread {
     #[graphql(skip)]
     primary_key: i32,
     pub uuid: Uuid,
     pub created_at: DateTime<Utc>,
     pub parent_board_id: Uuid,
+    /// After the word filters of the board.
     pub title: String,
     pub creator_user_id: String,
+    #[graphql(skip)]
+    pub original_title: Option<String>,
+    /// Locked threads reject new threadposts.
+    pub locked: bool,
+}
+
+#[ComplexObject]
+impl Thread {
+    /// Parent board of the thread.
+    async fn board(&self, context: &Context<'_>) -> Result<Option<Board>> {
+        let loader = context.data::<DataLoader<BoardLoader>>()?;
+        loader.load_one(self.parent_board_id).await
+    }
+
+    /// Threadposts ordered by number.
+    /// after is the number of the last threadpost already fetched.
+    #[graphql(complexity = "list_complexity(first.or(Some(100)), None, child_complexity)")]
+    async fn posts(
+        &self,
+        context: &Context<'_>,
+        first: Option<i32>,
+        after: Option<i32>,
+    ) -> Result<Vec<ThreadPost>> {
+        let first = first.unwrap_or(100);
+        if first < 0 || first > 1000 {
+            return Err(ErrorCode::Validation.error("Invalid range."));
+        }
+        let range = ThreadPostRange {
+            thread_id: self.uuid,
+            after: after.unwrap_or(0),
+            first: first,
+        };
+
+        let loader = context.data::<DataLoader<ThreadPostsLoader>>()?;
+        Ok(loader.load_one(range).await?.unwrap_or_default())
+    }
+
+    /// Number of threadposts in the thread.
+    async fn post_count(&self, context: &Context<'_>) -> Result<i64> {
+        let loader = context.data::<DataLoader<ThreadPostCountLoader>>()?;
+        Ok(loader.load_one(self.uuid).await?.unwrap_or(0))
+    }
+
+    /// Title as submitted, before the word filters. Null if no rule changed it.
+    /// Only for admin and moderators of the board, null for others.
+    async fn original_title(&self, context: &Context<'_>) -> Result<Option<String>> {
+        let original = match &self.original_title {
+            Some(t) => t.clone(),
+            None => return Ok(None),
+        };
+        let user_id = match context.data_opt::<AuthenticatedUser>() {
+            Some(user) => user.user_id.clone(),
+            None => return Ok(None),
+        };
+        let db_pool = context.data::<DBPool>()?.clone();
+        let board_uuid = self.parent_board_id;
+
+        run_blocking(move || {
+            if has_capability(
+                &db_pool,
+                &user_id,
+                Capability::ViewOriginalText,
+                Scope::Board(&board_uuid),
+            )? {
+                return Ok(Some(original));
+            }
+            Ok(None)
+        })
+        .await
+    }
 }
 
 impl Thread {
-    /// Create a new thread and insert it to DB.
-    /// Returns the created thread.
+    /// Create a new thread and insert it to DB, unless the content filters reject or hold it.
+    /// Returns the created thread, held threads are HeldForReview errors.
+    /// The title and the first post go through the content filters, then insert applies the word filters.
     pub fn create_new(
         db_pool: &DBPool,
         index_writer: &mut IndexWriter,
+        content_filters: &ContentFilterChain,
         creator_user_id: &str,
         thread_title: &str,
         parent_board_uuid: &Uuid,
         first_post_text: &str,
     ) -> Result<Self> {
+        validate_title(thread_title)?;
+        validate_body(first_post_text)?;
+        let candidate = PostCandidate {
+            poster_user_id: creator_user_id,
+            board_uuid: parent_board_uuid,
+            thread_title: Some(thread_title),
+            body: first_post_text,
+        };
+        match content_filters.run(&db_pool, &candidate)? {
+            FilterDecision::Allow => {}
+            FilterDecision::Reject(reason) => return Err(ErrorCode::Validation.error(reason)),
+            FilterDecision::Hold(reason) => {
+                HeldThreadPost::hold_thread(
+                    &db_pool,
+                    parent_board_uuid,
+                    creator_user_id,
+                    thread_title,
+                    first_post_text,
+                    &reason,
+                )?;
+                return Err(
+                    ErrorCode::HeldForReview.error("Your thread is held for review by moderators.")
+                );
+            }
+        }
+
+        let (created_thread, _) = Thread::insert(
+            db_pool,
+            &db_pool.get()?,
+            index_writer,
+            creator_user_id,
+            thread_title,
+            parent_board_uuid,
+            first_post_text,
+        )?;
+        Ok(created_thread)
+    }
+
+    /// Insert the thread and its first post with the word filters of the board applied.
+    /// The originals are kept for moderators, users mentioned in the first post are notified.
+    /// Runs in a transaction on db_connection, or a savepoint if it's already in one.
+    pub(crate) fn insert(
+        db_pool: &DBPool,
+        db_connection: &PgConnection,
+        index_writer: &mut IndexWriter,
+        creator_user_id: &str,
+        thread_title: &str,
+        parent_board_uuid: &Uuid,
+        first_post_text: &str,
+    ) -> Result<(Self, ThreadPost)> {
+        let word_filters = WordFilterRule::select_by_board(&db_pool, parent_board_uuid)?;
+        let filtered_title = apply_word_filters(&word_filters, thread_title)?;
+        let (thread_title, original_title) = match &filtered_title {
+            Some(filtered) => (filtered.as_str(), Some(thread_title)),
+            None => (thread_title, None),
+        };
+        let filtered_first_post = apply_word_filters(&word_filters, first_post_text)?;
+        let (first_post_text, original_first_post) = match &filtered_first_post {
+            Some(filtered) => (filtered.as_str(), Some(first_post_text)),
+            None => (first_post_text, None),
+        };
+        //the word filters may empty or lengthen them
+        validate_title(thread_title)?;
+        validate_body(first_post_text)?;
+
         let new_thread = NewThread {
             uuid: &Uuid::new_v4(),
             created_at: &Utc::now(),
             parent_board_id: parent_board_uuid,
             title: thread_title,
             creator_user_id: creator_user_id,
+            original_title: original_title,
         };
 
-        let db_connection = db_pool.get()?;
-        let created_thread = db_connection.build_transaction().run::<_, Error, _>(|| {
+        db_connection.transaction::<_, Error, _>(|| {
             let created_thread = diesel::insert_into(threads::table)
                 .values(&new_thread)
-                .get_result::<Thread>(&db_pool.get()?)?;
+                .get_result::<Thread>(db_connection)?;
             let index = index_writer.index();
             let schema = index.schema();
 
@@ -62,12 +228,26 @@ impl Thread {
                 poster_user_id: creator_user_id,
                 parent_thread_id: &created_thread.uuid,
                 body_text: first_post_text,
+                original_body_text: original_first_post,
             };
 
             use crate::schema::threadposts;
             let created_threadpost = diesel::insert_into(threadposts::table)
                 .values(&new_threadpost)
-                .get_result::<ThreadPost>(&db_pool.get()?)?;
+                .get_result::<ThreadPost>(db_connection)?;
+
+            //mentions in the first post, the threadpost is kept even if notifying fails
+            if let Err(e) = db_connection.transaction::<_, Error, _>(|| {
+                Notification::notify_references(db_connection, &created_threadpost)
+            }) {
+                event!(
+                    Level::ERROR,
+                    "Failed to notify references of {}: {}",
+                    created_threadpost.uuid,
+                    e.message
+                );
+            }
+
             let index = index_writer.index();
             let schema = index.schema();
 
@@ -81,10 +261,8 @@ impl Thread {
 
             index_writer.commit()?;
 
-            Ok(created_thread)
-        })?;
-
-        Ok(created_thread)
+            Ok((created_thread, created_threadpost))
+        })
     }
 
     pub fn select_by_uuid(db_pool: &DBPool, thread_uuid: &Uuid) -> Result<Option<Self>> {
@@ -102,14 +280,13 @@ impl Thread {
     }
 
     pub fn remove_by_uuid(
-        db_pool: &DBPool,
+        db_connection: &PgConnection,
         index_writer: &mut IndexWriter,
         thread_uuid: &Uuid,
     ) -> Result<()> {
         use crate::schema::threads::dsl::*;
-        let db_connection = db_pool.get()?;
-        db_connection.build_transaction().run::<_, Error, _>(|| {
-            diesel::delete(threads.filter(uuid.eq(thread_uuid))).execute(&db_connection)?;
+        db_connection.transaction::<_, Error, _>(|| {
+            diesel::delete(threads.filter(uuid.eq(thread_uuid))).execute(db_connection)?;
             let index = index_writer.index();
             let schema = index.schema();
             let uuid_field = schema.get_field("thread_uuid").unwrap();
@@ -121,6 +298,18 @@ impl Thread {
         Ok(())
     }
 
+    /// Lock or unlock the thread.
+    pub fn set_locked(db_connection: &PgConnection, thread_uuid: &Uuid, lock: bool) -> Result<()> {
+        use crate::schema::threads::dsl::*;
+        let updated = diesel::update(threads.filter(uuid.eq(thread_uuid)))
+            .set(locked.eq(lock))
+            .execute(db_connection)?;
+        if updated == 0 {
+            return Err(ErrorCode::NotFound.error("Invalid thread uuid."));
+        }
+        Ok(())
+    }
+
     pub fn count_threadposts(db_pool: &DBPool, thread_uuid: &Uuid) -> Result<u64> {
         use crate::schema::threadposts::dsl::*;
 
@@ -132,19 +321,34 @@ impl Thread {
         Ok(count as u64)
     }
 
-    /// ThreadPost range query
-    pub fn thread_post_range(
+    /// Cursor key of the thread.
+    pub fn key(&self) -> i32 {
+        self.primary_key
+    }
+
+    /// Threadposts of the thread, keyed by number.
+    pub fn thread_post_page(
         db_pool: &DBPool,
         thread_uuid: &Uuid,
-        start: i64,
-        end: i64,
+        page: &KeysetPage,
     ) -> Result<Vec<ThreadPost>> {
         use crate::schema::threadposts::dsl::*;
-        Ok(threadposts
+        let mut query = threadposts
             .filter(parent_thread_id.eq(thread_uuid))
-            .limit(end - start + 1)
-            .order_by(primary_key)
-            .offset(start)
+            .into_boxed();
+        if let Some(key) = page.after {
+            query = query.filter(number.gt(key));
+        }
+        if let Some(key) = page.before {
+            query = query.filter(number.lt(key));
+        }
+        query = if page.backward {
+            query.order_by(number.desc())
+        } else {
+            query.order_by(number)
+        };
+        Ok(query
+            .limit(page.limit)
             .load::<ThreadPost>(&db_pool.get()?)?)
     }
 }
@@ -158,4 +362,5 @@ struct NewThread<'a> {
     pub parent_board_id: &'a Uuid,
     pub title: &'a str,
     pub creator_user_id: &'a str,
+    pub original_title: Option<&'a str>,
 }
@@ -0,0 +1,168 @@
+use crate::error::ErrorCode;
+use crate::loader::{ThreadLoader, ThreadPostsAfter, ThreadPostsAfterCountLoader};
+use crate::model::Thread;
+use crate::schema::thread_watches;
+use crate::DBPool;
+use async_graphql::dataloader::DataLoader;
+use async_graphql::{ComplexObject, Context, Error, Result, SimpleObject};
+use chrono::{DateTime, Utc};
+use diesel::dsl::max;
+use diesel::prelude::*;
+use diesel::sql_types::{Integer, Text, Uuid as SqlUuid};
+use diesel::{Insertable, Queryable};
+use uuid::Uuid;
+
+const MAX_WATCHED_THREADS: i64 = 500;
+
+/// Thread watched by a user, with the read marker.
+/// Only visible to the user.
+#[derive(Queryable, SimpleObject, Clone)]
+#[graphql(complex)]
+pub struct ThreadWatch {
+    #[graphql(skip)]
+    primary_key: i32,
+    pub user_id: String,
+    pub thread_id: Uuid,
+    /// Number of the last threadpost the user has read.
+    pub last_read_number: i32,
+    pub watched_at: DateTime<Utc>,
+}
+
+#[ComplexObject]
+impl ThreadWatch {
+    /// Watched thread.
+    async fn thread(&self, context: &Context<'_>) -> Result<Option<Thread>> {
+        let loader = context.data::<DataLoader<ThreadLoader>>()?;
+        loader.load_one(self.thread_id).await
+    }
+
+    /// Number of threadposts after the last read one.
+    async fn new_post_count(&self, context: &Context<'_>) -> Result<i64> {
+        let loader = context.data::<DataLoader<ThreadPostsAfterCountLoader>>()?;
+        let key = ThreadPostsAfter {
+            thread_id: self.thread_id,
+            after: self.last_read_number,
+        };
+        Ok(loader.load_one(key).await?.unwrap_or(0))
+    }
+}
+
+impl ThreadWatch {
+    /// Start watching the thread, existing threadposts count as read.
+    /// Watching an already watched thread does nothing.
+    pub fn watch(db_pool: &DBPool, watcher_user_id: &str, thread_uuid: &Uuid) -> Result<()> {
+        use crate::schema::{threadposts, users};
+        if Thread::select_by_uuid(&db_pool, thread_uuid)?.is_none() {
+            return Err(ErrorCode::NotFound.error("Invalid thread uuid."));
+        }
+        let db_connection = db_pool.get()?;
+        db_connection.build_transaction().run::<_, Error, _>(|| {
+            //concurrent watches of the user wait here, so the count below stays valid until the insert
+            users::table
+                .filter(users::id.eq(watcher_user_id))
+                .select(users::id)
+                .for_update()
+                .first::<String>(&db_connection)?;
+            let already_watched: i64 = thread_watches::table
+                .filter(thread_watches::user_id.eq(watcher_user_id))
+                .filter(thread_watches::thread_id.eq(thread_uuid))
+                .count()
+                .get_result(&db_connection)?;
+            if already_watched > 0 {
+                return Ok(());
+            }
+            let watched: i64 = thread_watches::table
+                .filter(thread_watches::user_id.eq(watcher_user_id))
+                .count()
+                .get_result(&db_connection)?;
+            if watched >= MAX_WATCHED_THREADS {
+                return Err(ErrorCode::Validation.error(format!(
+                    "You can watch at most {} threads.",
+                    MAX_WATCHED_THREADS
+                )));
+            }
+
+            let last_number = threadposts::table
+                .filter(threadposts::parent_thread_id.eq(thread_uuid))
+                .select(max(threadposts::number))
+                .first::<Option<i32>>(&db_connection)?;
+            let new_watch = NewThreadWatch {
+                user_id: watcher_user_id,
+                thread_id: thread_uuid,
+                last_read_number: last_number.unwrap_or(0),
+                watched_at: &Utc::now(),
+            };
+            diesel::insert_into(thread_watches::table)
+                .values(&new_watch)
+                .execute(&db_connection)?;
+            Ok(())
+        })
+    }
+
+    pub fn unwatch(db_pool: &DBPool, watcher_user_id: &str, thread_uuid: &Uuid) -> Result<()> {
+        use crate::schema::thread_watches::dsl::*;
+        let removed = diesel::delete(
+            thread_watches
+                .filter(user_id.eq(watcher_user_id))
+                .filter(thread_id.eq(thread_uuid)),
+        )
+        .execute(&db_pool.get()?)?;
+        if removed == 0 {
+            return Err(ErrorCode::NotFound.error("You are not watching the thread."));
+        }
+        Ok(())
+    }
+
+    /// Threads watched by the user, newest watch first.
+    pub fn select_by_user(db_pool: &DBPool, watcher_user_id: &str) -> Result<Vec<Self>> {
+        use crate::schema::thread_watches::dsl::*;
+        Ok(thread_watches
+            .filter(user_id.eq(watcher_user_id))
+            .order_by(watched_at.desc())
+            .load::<ThreadWatch>(&db_pool.get()?)?)
+    }
+
+    /// Mark the threadposts numbered first_number to last_number read, as shown to the user.
+    /// The read marker moves forward to last_number only if no unread threadpost is before first_number,
+    /// so skipped threadposts stay unread. It never moves back.
+    /// Returns false if the user doesn't watch the thread.
+    pub fn mark_read(
+        db_pool: &DBPool,
+        watcher_user_id: &str,
+        thread_uuid: &Uuid,
+        first_number: i32,
+        last_number: i32,
+    ) -> Result<bool> {
+        use crate::schema::thread_watches::dsl::*;
+        let db_connection = db_pool.get()?;
+        diesel::sql_query(
+            "UPDATE thread_watches SET last_read_number = $3 \
+             WHERE user_id = $1 AND thread_id = $2 AND last_read_number < $3 \
+             AND NOT EXISTS (SELECT 1 FROM threadposts \
+             WHERE threadposts.parent_thread_id = thread_watches.thread_id \
+             AND threadposts.number > thread_watches.last_read_number \
+             AND threadposts.number < $4)",
+        )
+        .bind::<Text, _>(watcher_user_id)
+        .bind::<SqlUuid, _>(thread_uuid)
+        .bind::<Integer, _>(last_number)
+        .bind::<Integer, _>(first_number)
+        .execute(&db_connection)?;
+        let watched: i64 = thread_watches
+            .filter(user_id.eq(watcher_user_id))
+            .filter(thread_id.eq(thread_uuid))
+            .count()
+            .get_result(&db_connection)?;
+        Ok(watched > 0)
+    }
+}
+
+/// diesel model
+#[derive(Insertable)]
+#[table_name = "thread_watches"]
+struct NewThreadWatch<'a> {
+    pub user_id: &'a str,
+    pub thread_id: &'a Uuid,
+    pub last_read_number: i32,
+    pub watched_at: &'a DateTime<Utc>,
+}
@@ -1,13 +1,43 @@
+use crate::content_filter::{ContentFilterChain, FilterDecision, PostCandidate};
+use crate::error::ErrorCode;
+use crate::guard::AuthenticatedUser;
+use crate::loader::{ThreadLoader, UserLoader};
+use crate::model::{
+    apply_word_filters, has_capability, Capability, HeldThreadPost, Notification, Scope, Thread,
+    User, WordFilterRule,
+};
 use crate::schema::threadposts;
-use crate::DBPool;
-use async_graphql::{Error, Result, SimpleObject};
+use crate::{run_blocking, DBPool};
+use async_graphql::dataloader::DataLoader;
+use async_graphql::{ComplexObject, Context, Error, Result, SimpleObject};
 use chrono::{DateTime, Utc};
 use diesel::prelude::*;
+use diesel::PgConnection;
 use diesel::{Insertable, Queryable};
 use tantivy::{doc, Index, IndexReader, IndexWriter, Term};
+use tracing::{event, Level};
 use uuid::Uuid;
 
-#[derive(Queryable, SimpleObject, Clone)]
+/// threadposts.body_text is VARCHAR(4096)
+pub const MAX_BODY_CHARS: usize = 4096;
+
+/// Body must be 1 to MAX_BODY_CHARS characters, checked before and after the word filters.
+pub(crate) fn validate_body(body: &str) -> Result<()> {
+    if body.is_empty() {
+        return Err(ErrorCode::Validation.error("Empty post."));
+    }
+    if body.chars().count() > MAX_BODY_CHARS {
+        return Err(ErrorCode::Validation.error(format!(
+            "Post must be at most {} characters.",
+            MAX_BODY_CHARS
+        )));
+    }
+    Ok(())
+}
+
+#[derive(Queryable, QueryableByName, SimpleObject, Clone)]
+#[table_name = "threadposts"]
+#[graphql(complex)]
 pub struct ThreadPost {
     #[graphql(skip)]
     primary_key: i32,
@@ -16,7 +46,57 @@ pub struct ThreadPost {
     pub posted_at: DateTime<Utc>,
     pub poster_user_id: String,
     pub parent_thread_id: Uuid,
+    /// After the word filters of the board.
     pub body_text: String,
+    #[graphql(skip)]
+    pub original_body_text: Option<String>,
+}
+
+#[ComplexObject]
+impl ThreadPost {
+    /// Poster of the threadpost.
+    async fn poster(&self, context: &Context<'_>) -> Result<Option<User>> {
+        let loader = context.data::<DataLoader<UserLoader>>()?;
+        loader.load_one(self.poster_user_id.clone()).await
+    }
+
+    /// Parent thread of the threadpost.
+    async fn thread(&self, context: &Context<'_>) -> Result<Option<Thread>> {
+        let loader = context.data::<DataLoader<ThreadLoader>>()?;
+        loader.load_one(self.parent_thread_id).await
+    }
+
+    /// Body as submitted, before the word filters. Null if no rule changed it.
+    /// Only for admin and moderators of the board, null for others.
+    async fn original_body_text(&self, context: &Context<'_>) -> Result<Option<String>> {
+        let original = match &self.original_body_text {
+            Some(t) => t.clone(),
+            None => return Ok(None),
+        };
+        let user_id = match context.data_opt::<AuthenticatedUser>() {
+            Some(user) => user.user_id.clone(),
+            None => return Ok(None),
+        };
+        let db_pool = context.data::<DBPool>()?.clone();
+        let thread_uuid = self.parent_thread_id;
+
+        run_blocking(move || {
+            let thread = match Thread::select_by_uuid(&db_pool, &thread_uuid)? {
+                Some(t) => t,
+                None => return Ok(None),
+            };
+            if has_capability(
+                &db_pool,
+                &user_id,
+                Capability::ViewOriginalText,
+                Scope::Board(&thread.parent_board_id),
+            )? {
+                return Ok(Some(original));
+            }
+            Ok(None)
+        })
+        .await
+    }
 }
 
 impl ThreadPost {
@@ -29,47 +109,178 @@ impl ThreadPost {
             .load::<ThreadPost>(&db_pool.get()?)?;
         if threadpost_vec.len() != 1 {
             debug_assert_eq!(threadpost_vec.len(), 0);
-            return Err(Error::new("Invalid threadpost uuid."));
+            return Err(ErrorCode::NotFound.error("Invalid threadpost uuid."));
         }
         Ok(threadpost_vec[0].clone())
     }
 
-    /// Create a new threadpost and insert it to DB.
-    /// Returns the created threadpost.
+    /// Create a new threadpost and insert it to DB, unless the content filters reject or hold it.
+    /// Returns the created threadpost, held threadposts are HeldForReview errors.
     pub fn create_new(
         db_pool: &DBPool,
         index_writer: &mut IndexWriter,
+        content_filters: &ContentFilterChain,
         poster_user_id: &str,
         thread_uuid: &Uuid,
         post_body: &str,
     ) -> Result<Self> {
-        use crate::model::Thread;
+        validate_body(post_body)?;
+
+        let parent_thread = match Thread::select_by_uuid(&db_pool, thread_uuid)? {
+            Some(t) => t,
+            None => return Err(ErrorCode::NotFound.error("Invalid thread uuid.")),
+        };
+        //not held for review either
+        if parent_thread.locked {
+            return Err(ErrorCode::Forbidden.error("Thread is locked."));
+        }
+        let candidate = PostCandidate {
+            poster_user_id: poster_user_id,
+            board_uuid: &parent_thread.parent_board_id,
+            thread_title: None,
+            body: post_body,
+        };
+        match content_filters.run(&db_pool, &candidate)? {
+            FilterDecision::Allow => {}
+            FilterDecision::Reject(reason) => return Err(ErrorCode::Validation.error(reason)),
+            FilterDecision::Hold(reason) => {
+                HeldThreadPost::hold(
+                    &db_pool,
+                    &parent_thread.parent_board_id,
+                    thread_uuid,
+                    poster_user_id,
+                    post_body,
+                    &reason,
+                )?;
+                return Err(
+                    ErrorCode::HeldForReview.error("Your post is held for review by moderators.")
+                );
+            }
+        }
 
-        if post_body.len() == 0 {
-            return Err(Error::new("Empty post."));
+        ThreadPost::insert(
+            db_pool,
+            &db_pool.get()?,
+            index_writer,
+            poster_user_id,
+            &parent_thread.parent_board_id,
+            thread_uuid,
+            post_body,
+        )
+    }
+
+    /// Post the held threadpost approved by a moderator, without the content filters.
+    /// Held new threads are created with it as the first post.
+    /// The held threadpost is claimed and posted on db_connection, call it in a transaction,
+    /// so it's put back for another try if posting fails.(e.g. the thread is full)
+    pub fn approve_held(
+        db_pool: &DBPool,
+        db_connection: &PgConnection,
+        index_writer: &mut IndexWriter,
+        held: &HeldThreadPost,
+    ) -> Result<Self> {
+        //claim it first, so it's posted only once
+        HeldThreadPost::remove_by_uuid(db_connection, &held.uuid)?;
+        match (&held.parent_thread_id, &held.thread_title) {
+            (Some(thread_uuid), _) => ThreadPost::insert(
+                db_pool,
+                db_connection,
+                index_writer,
+                &held.poster_user_id,
+                &held.board_id,
+                thread_uuid,
+                &held.body_text,
+            ),
+            (None, Some(thread_title)) => {
+                let (_, first_post) = Thread::insert(
+                    db_pool,
+                    db_connection,
+                    index_writer,
+                    &held.poster_user_id,
+                    thread_title,
+                    &held.board_id,
+                    &held.body_text,
+                )?;
+                Ok(first_post)
+            }
+            (None, None) => Err(Error::new("Held threadpost without a thread.")),
         }
+    }
+
+    /// Insert the threadpost with the word filters of the board applied.
+    /// Users it replies to or mentions are notified.
+    /// Runs in a transaction on db_connection, or a savepoint if it's already in one.
+    fn insert(
+        db_pool: &DBPool,
+        db_connection: &PgConnection,
+        index_writer: &mut IndexWriter,
+        poster_user_id: &str,
+        board_uuid: &Uuid,
+        thread_uuid: &Uuid,
+        post_body: &str,
+    ) -> Result<Self> {
+        let word_filters = WordFilterRule::select_by_board(&db_pool, board_uuid)?;
+        let filtered_body = apply_word_filters(&word_filters, post_body)?;
+        //the original is kept only if it's changed
+        let (post_body, original_body) = match &filtered_body {
+            Some(filtered) => (filtered.as_str(), Some(post_body)),
+            None => (post_body, None),
+        };
+        //the word filters may empty or lengthen it
+        validate_body(post_body)?;
 
-        let db_connection = db_pool.get()?;
-        let created_threadpost = db_connection.build_transaction().run::<_, Error, _>(|| {
-            //must count threadposts in transaction
-            let threadpost_count = Thread::count_threadposts(&db_pool, thread_uuid)?;
+        db_connection.transaction::<_, Error, _>(|| {
+            use crate::schema::threads;
+            use diesel::dsl::{count_star, max};
+
+            //lock the thread, so concurrent posts to it are numbered one by one
+            let thread_locked = threads::table
+                .filter(threads::uuid.eq(thread_uuid))
+                .select(threads::locked)
+                .for_update()
+                .first::<bool>(db_connection)
+                .optional()?
+                .ok_or_else(|| ErrorCode::NotFound.error("Invalid thread uuid."))?;
+            //also checked here, it may be locked after create_new checked it
+            if thread_locked {
+                return Err(ErrorCode::Forbidden.error("Thread is locked."));
+            }
+            let (threadpost_count, last_number) = threadposts::table
+                .filter(threadposts::parent_thread_id.eq(thread_uuid))
+                .select((count_star(), max(threadposts::number)))
+                .first::<(i64, Option<i32>)>(db_connection)?;
 
             if threadpost_count >= 1000 {
-                return Err(Error::new("Thread is full."));
+                return Err(ErrorCode::Validation.error("Thread is full."));
             }
 
             let new_threadpost = NewThreadPost {
                 uuid: &Uuid::new_v4(),
-                number: (threadpost_count + 1).try_into()?,
+                //removed threadposts leave gaps, counting would reuse numbers
+                number: last_number.unwrap_or(0) + 1,
                 posted_at: &Utc::now(),
                 poster_user_id: poster_user_id,
                 parent_thread_id: thread_uuid,
                 body_text: post_body,
+                original_body_text: original_body,
             };
 
             let created_threadpost = diesel::insert_into(threadposts::table)
                 .values(&new_threadpost)
-                .get_result::<ThreadPost>(&db_pool.get()?)?;
+                .get_result::<ThreadPost>(db_connection)?;
+
+            //the threadpost is kept even if notifying fails
+            if let Err(e) = db_connection.transaction::<_, Error, _>(|| {
+                Notification::notify_references(db_connection, &created_threadpost)
+            }) {
+                event!(
+                    Level::ERROR,
+                    "Failed to notify references of {}: {}",
+                    created_threadpost.uuid,
+                    e.message
+                );
+            }
+
             let index = index_writer.index();
             let schema = index.schema();
 
@@ -82,20 +293,17 @@ impl ThreadPost {
             ))?;
             index_writer.commit()?;
             Ok(created_threadpost)
-        })?;
-
-        Ok(created_threadpost)
+        })
     }
 
     pub fn remove_by_uuid(
-        db_pool: &DBPool,
+        db_connection: &PgConnection,
         index_writer: &mut IndexWriter,
         threadpost_uuid: &Uuid,
     ) -> Result<()> {
         use crate::schema::threadposts::dsl::*;
-        let db_connection = db_pool.get()?;
-        db_connection.build_transaction().run::<_, Error, _>(|| {
-            diesel::delete(threadposts.filter(uuid.eq(threadpost_uuid))).execute(&db_connection)?;
+        db_connection.transaction::<_, Error, _>(|| {
+            diesel::delete(threadposts.filter(uuid.eq(threadpost_uuid))).execute(db_connection)?;
             let index = index_writer.index();
             let schema = index.schema();
             let uuid_field = schema.get_field("threadpost_uuid").unwrap();
@@ -118,4 +326,5 @@ pub struct NewThreadPost<'a> {
     pub poster_user_id: &'a str,
     pub parent_thread_id: &'a Uuid,
     pub body_text: &'a str,
+    pub original_body_text: Option<&'a str>,
 }
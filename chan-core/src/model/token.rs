@@ -1,13 +1,94 @@
-use crate::model::{User, UserStatus};
+use crate::error::ErrorCode;
+use crate::model::{Ban, RefreshToken, User, UserStatus};
+use crate::schema::issued_tokens;
 use crate::DBPool;
-use async_graphql::{Error, Result};
-use chrono::{DateTime, Utc};
-use jsonwebtoken::{decode, DecodingKey, Validation};
-use num_traits::FromPrimitive;
+use async_graphql::{Result, SimpleObject};
+use chrono::{DateTime, Duration, Utc};
+use diesel::prelude::*;
+use diesel::{Insertable, Queryable};
+use jsonwebtoken::{decode, encode, DecodingKey, EncodingKey, Header, Validation};
 use serde::{Deserialize, Serialize};
 use tracing::{event, Level};
 use uuid::Uuid;
 
+/// Signing key and lifetimes of tokens issued by login.
+#[derive(Debug, Clone)]
+pub struct TokenConfig {
+    pub secret_key: String,
+    pub access_token_lifetime: Duration,
+    pub refresh_token_lifetime: Duration,
+}
+
+impl Default for TokenConfig {
+    fn default() -> Self {
+        TokenConfig {
+            secret_key: String::new(),
+            access_token_lifetime: Duration::hours(1),
+            refresh_token_lifetime: Duration::days(30),
+        }
+    }
+}
+
+/// Tokens returned by login and refresh.
+#[derive(SimpleObject)]
+pub struct AuthTokens {
+    /// Short-lived JWT, send it with 'Token' header.
+    pub access_token: String,
+    /// Long-lived, one-time token to get a new access token.
+    pub refresh_token: String,
+}
+
+/// sha256 of the token in hex, tokens stored in DB are hashed by this.
+pub(crate) fn hash_token(token: &str) -> String {
+    openssl::sha::sha256(token.as_bytes())
+        .iter()
+        .map(|b| format!("{:02x}", b))
+        .collect()
+}
+
+/// Random 256 bits token in hex.
+pub(crate) fn generate_random_token() -> Result<String> {
+    let mut bytes = vec![0; 32];
+    openssl::rand::rand_bytes(&mut bytes)?;
+    Ok(bytes.iter().map(|b| format!("{:02x}", b)).collect())
+}
+
+/// Issue a new access token and refresh token for the user, and record them.
+/// Callers must authenticate the user.(e.g. by password)
+pub(crate) fn issue_auth_tokens(
+    db_pool: &DBPool,
+    user_id: &str,
+    token_config: &TokenConfig,
+    client_info: &ClientInfo,
+) -> Result<AuthTokens> {
+    let datetime_now = Utc::now();
+    let claim = TokenClaim {
+        issuer_user_id: user_id.to_owned(),
+        expiration_time: datetime_now + token_config.access_token_lifetime,
+        token_uuid: Uuid::new_v4(),
+        issued_at_time: datetime_now,
+    };
+    let access_token = encode(
+        &Header::default(),
+        &claim,
+        &EncodingKey::from_secret(token_config.secret_key.as_bytes()),
+    )?;
+
+    //record the token, so it can be revoked later
+    IssuedToken::record(&db_pool, &claim, &client_info)?;
+    let refresh_token = RefreshToken::issue(
+        &db_pool,
+        user_id,
+        &claim.token_uuid,
+        token_config.refresh_token_lifetime,
+    )?;
+
+    Ok(AuthTokens {
+        access_token: access_token,
+        refresh_token: refresh_token,
+    })
+}
+
 #[derive(Debug, Serialize, Deserialize)]
 pub struct TokenClaim {
     pub issuer_user_id: String,
@@ -36,9 +117,8 @@ impl TokenClaim {
             None => return Ok((false, None)), //user not found
         };
 
-        //check user status
-
-        let status: UserStatus = FromPrimitive::from_i32(user.user_status).unwrap();
+        //check user status(ended bans are lifted here)
+        let status = Ban::effective_status(&db_pool, &user)?;
         if status == UserStatus::Banned
             || status == UserStatus::Removed
             || status == UserStatus::Suspended
@@ -46,16 +126,176 @@ impl TokenClaim {
             return Ok((false, None));
         }
 
+        //check revocation(e.g. password change)
+        if let Some(valid_after) = user.tokens_valid_after {
+            if self.issued_at_time < valid_after {
+                return Ok((false, None));
+            }
+        }
+        let issued_token = match IssuedToken::select_by_uuid(&db_pool, &self.token_uuid)? {
+            Some(t) if t.revoked_at.is_none() => t,
+            _ => return Ok((false, None)), //not recorded or revoked
+        };
+        //most verifications don't write
+        if issued_token.needs_touch(datetime_now) {
+            IssuedToken::touch(&db_pool, &self.token_uuid)?;
+        }
+
         Ok((true, Some(user.id)))
     }
 }
 
+/// Client of a request.(captured at login)
+#[derive(Default, Debug, Clone)]
+pub struct ClientInfo {
+    pub user_agent: Option<String>,
+    pub ip_address: Option<String>,
+}
+
+/// Last use time of a session is only as precise as this.
+const TOUCH_INTERVAL_MINUTES: i64 = 5;
+
+/// Record of a token issued by login.(a session)
+/// A token is valid only while its record exists and is not revoked.
+#[derive(Queryable, SimpleObject, Clone)]
+pub struct IssuedToken {
+    #[graphql(skip)]
+    primary_key: i32,
+    pub token_uuid: Uuid,
+    pub user_id: String,
+    pub issued_at: DateTime<Utc>,
+    pub expires_at: DateTime<Utc>,
+    pub revoked_at: Option<DateTime<Utc>>,
+    pub last_used_at: Option<DateTime<Utc>>,
+    pub user_agent: Option<String>,
+    pub ip_address: Option<String>,
+}
+
+impl IssuedToken {
+    pub fn select_by_uuid(db_pool: &DBPool, uuid: &Uuid) -> Result<Option<Self>> {
+        use crate::schema::issued_tokens::dsl::*;
+        let v = issued_tokens
+            .filter(token_uuid.eq(uuid))
+            .limit(1)
+            .load::<IssuedToken>(&db_pool.get()?)?;
+        debug_assert!(v.len() <= 1);
+        Ok(v.into_iter().next())
+    }
+
+    /// Record a newly issued token.
+    pub fn record(db_pool: &DBPool, claim: &TokenClaim, client_info: &ClientInfo) -> Result<()> {
+        let new_token = NewIssuedToken {
+            token_uuid: &claim.token_uuid,
+            user_id: &claim.issuer_user_id,
+            issued_at: &claim.issued_at_time,
+            expires_at: &claim.expiration_time,
+            user_agent: client_info.user_agent.as_deref(),
+            ip_address: client_info.ip_address.as_deref(),
+        };
+        diesel::insert_into(issued_tokens::table)
+            .values(&new_token)
+            .execute(&db_pool.get()?)?;
+        Ok(())
+    }
+
+    /// Returns true if last use time of the token is older than TOUCH_INTERVAL_MINUTES.
+    pub fn needs_touch(&self, datetime_now: DateTime<Utc>) -> bool {
+        match self.last_used_at {
+            Some(last_used) => last_used < datetime_now - Duration::minutes(TOUCH_INTERVAL_MINUTES),
+            None => true,
+        }
+    }
+
+    /// Update last use time of the token.
+    /// To avoid a write on every request, it is updated at most once per TOUCH_INTERVAL_MINUTES.
+    pub fn touch(db_pool: &DBPool, uuid: &Uuid) -> Result<()> {
+        use crate::schema::issued_tokens::dsl::*;
+        let datetime_now = Utc::now();
+        //checked again in SQL, concurrent requests may have touched it
+        diesel::update(
+            issued_tokens.filter(token_uuid.eq(uuid)).filter(
+                last_used_at
+                    .is_null()
+                    .or(last_used_at.lt(datetime_now - Duration::minutes(TOUCH_INTERVAL_MINUTES))),
+            ),
+        )
+        .set(last_used_at.eq(Some(datetime_now)))
+        .execute(&db_pool.get()?)?;
+        Ok(())
+    }
+
+    /// Active(not revoked, not expired) tokens of the user.
+    /// Newest first.
+    pub fn active_tokens(db_pool: &DBPool, owner_user_id: &str) -> Result<Vec<Self>> {
+        use crate::schema::issued_tokens::dsl::*;
+        Ok(issued_tokens
+            .filter(user_id.eq(owner_user_id))
+            .filter(revoked_at.is_null())
+            .filter(expires_at.gt(Utc::now()))
+            .order_by(issued_at.desc())
+            .load::<IssuedToken>(&db_pool.get()?)?)
+    }
+
+    /// Revoke a token of the user.
+    /// Returns false if no such active token.
+    pub fn revoke(db_pool: &DBPool, owner_user_id: &str, uuid: &Uuid) -> Result<bool> {
+        use crate::schema::issued_tokens::dsl::*;
+        let revoked = diesel::update(
+            issued_tokens
+                .filter(token_uuid.eq(uuid))
+                .filter(user_id.eq(owner_user_id))
+                .filter(revoked_at.is_null()),
+        )
+        .set(revoked_at.eq(Some(Utc::now())))
+        .execute(&db_pool.get()?)?;
+        //the refresh token of the session must not be able to revive it
+        RefreshToken::revoke_by_access_token(&db_pool, uuid)?;
+        Ok(revoked == 1)
+    }
+
+    /// Revoke all tokens of the user.(log out everywhere)
+    /// Returns the number of revoked tokens.
+    pub fn revoke_all(db_pool: &DBPool, owner_user_id: &str) -> Result<usize> {
+        use crate::schema::issued_tokens::dsl::*;
+        let revoked = diesel::update(
+            issued_tokens
+                .filter(user_id.eq(owner_user_id))
+                .filter(revoked_at.is_null()),
+        )
+        .set(revoked_at.eq(Some(Utc::now())))
+        .execute(&db_pool.get()?)?;
+        RefreshToken::revoke_all(&db_pool, owner_user_id)?;
+        Ok(revoked)
+    }
+}
+
+/// diesel model
+#[derive(Insertable)]
+#[table_name = "issued_tokens"]
+struct NewIssuedToken<'a> {
+    pub token_uuid: &'a Uuid,
+    pub user_id: &'a str,
+    pub issued_at: &'a DateTime<Utc>,
+    pub expires_at: &'a DateTime<Utc>,
+    pub user_agent: Option<&'a str>,
+    pub ip_address: Option<&'a str>,
+}
+
 /// Verify token
 /// Returns Ok(user_id) if valid.
 /// Returns Err if token is invalid or not allowed.
 /// GraphQL handler which uses this function should propagate the result by 'verify_token(...)?;'
-pub fn verify_token(db_pool: &DBPool, token: &str) -> Result<String> {
-    let secret_key = std::env::var("JWT_SECRET_KEY")?;
+pub fn verify_token(db_pool: &DBPool, token_config: &TokenConfig, token: &str) -> Result<String> {
+    Ok(verify_token_claim(&db_pool, &token_config, &token)?.issuer_user_id)
+}
+
+/// Verify token
+/// Same as verify_token, but returns the whole claim.(e.g. to revoke the token itself)
+pub fn verify_token_claim(
+    db_pool: &DBPool,
+    token_config: &TokenConfig,
+    token: &str,
+) -> Result<TokenClaim> {
     let mut validation = Validation::default();
     validation.required_spec_claims.remove("exp");
     validation.validate_exp = false; //We use our own validation
@@ -63,17 +303,21 @@ pub fn verify_token(db_pool: &DBPool, token: &str) -> Result<String> {
     //verify token
     let token = decode::<TokenClaim>(
         &token,
-        &DecodingKey::from_secret(secret_key.as_bytes()),
+        &DecodingKey::from_secret(token_config.secret_key.as_bytes()),
         &validation,
     )?;
+    //tell clients to refresh the token
+    if token.claims.expiration_time < Utc::now() {
+        return Err(ErrorCode::Unauthenticated.error("Token expired."));
+    }
     let (is_valid, user_uuid) = token.claims.verify(&db_pool)?;
     if !is_valid {
         assert!(user_uuid.is_none());
-        return Err(Error::new("Invalid token."));
+        return Err(ErrorCode::Unauthenticated.error("Invalid token."));
     }
     assert!(user_uuid.is_some());
 
     event!(Level::DEBUG, "verify_token OK!");
 
-    Ok(user_uuid.unwrap())
+    Ok(token.claims)
 }
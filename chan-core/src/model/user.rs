@@ -1,13 +1,16 @@
-use crate::model::TokenClaim;
+use crate::error::ErrorCode;
+use crate::model::token::issue_auth_tokens;
+use crate::model::{AuthTokens, Ban, ClientInfo, IssuedToken, TokenConfig};
 use crate::schema::users;
 use crate::DBPool;
 use argon2::Config;
 use async_graphql::{Error, Result, SimpleObject};
-use chrono::{DateTime, Duration, Utc};
+use chrono::{DateTime, Utc};
 use diesel::prelude::*;
+use diesel::PgConnection;
 use diesel::{Insertable, Queryable};
-use jsonwebtoken::{encode, EncodingKey, Header};
 use num_traits::{FromPrimitive, ToPrimitive};
+use tantivy::{IndexWriter, Term};
 use uuid::Uuid;
 
 #[derive(FromPrimitive, PartialEq, Debug, ToPrimitive)]
@@ -46,6 +49,19 @@ impl UserStatus {
     }
 }
 
+/// Placeholder account which owns contents of removed accounts.
+/// Created by migration, nobody can login as this account.
+pub const ANONYMOUS_USER_ID: &str = "anonymous";
+
+/// What to do with threadposts of an account which is removed by its owner.
+#[derive(PartialEq, Debug, Clone, Copy)]
+pub enum ContentRemovalPolicy {
+    /// Keep threadposts, but reassign them to the anonymous account.
+    Anonymize,
+    /// Delete threadposts from DB and search index.
+    Delete,
+}
+
 #[derive(Queryable, SimpleObject, Clone)]
 pub struct User {
     #[graphql(skip)]
@@ -56,6 +72,36 @@ pub struct User {
     pub argon2_password: String,
     pub user_type: i32,
     pub user_status: i32,
+    /// Tokens issued before this time are rejected.
+    #[graphql(skip)]
+    pub tokens_valid_after: Option<DateTime<Utc>>,
+}
+
+/// Check password length.
+pub(crate) fn check_password(password: &str) -> Result<()> {
+    if password.len() < 16 {
+        return Err(ErrorCode::Validation.error("Password is too short.(at least 16)"));
+    }
+    Ok(())
+}
+
+/// Check password length and hash it.
+pub(crate) fn hash_password(password: &str) -> Result<String> {
+    let argon2_config = Config::default();
+
+    //check password length
+    check_password(password)?;
+
+    //hash password
+    let mut salt = vec![0; 128];
+    openssl::rand::rand_bytes(&mut salt).unwrap();
+    let hashed_password =
+        argon2::hash_encoded(&password.as_bytes(), &salt, &argon2_config).unwrap();
+    let matches = argon2::verify_encoded(&hashed_password, &password.as_bytes()).unwrap();
+    if !matches {
+        return Err(Error::new("This should not happen."));
+    }
+    Ok(hashed_password)
 }
 
 impl User {
@@ -69,7 +115,7 @@ impl User {
             .load::<User>(&db_pool.get()?)?;
         if user_vec.len() != 1 {
             debug_assert_eq!(user_vec.len(), 0);
-            return Err(Error::new("Invalid user uuid."));
+            return Err(ErrorCode::NotFound.error("Invalid user uuid."));
         }
         Ok(user_vec[0].clone())
     }
@@ -90,25 +136,20 @@ impl User {
         }
     }
 
+    /// Same as select_by_user_id(..).is_some(), on the connection.(e.g. in a transaction)
+    pub fn exists(db_connection: &PgConnection, user_id: &str) -> Result<bool> {
+        use crate::schema::users::dsl::*;
+        let count: i64 = users
+            .filter(id.eq(user_id))
+            .count()
+            .get_result(db_connection)?;
+        Ok(count > 0)
+    }
+
     /// Create a new user and insert to DB.(Registration)
     /// Returns the created user.
     pub fn create_new(db_pool: &DBPool, user_type: UserType, password: &str) -> Result<Self> {
-        let argon2_config = Config::default();
-
-        //check password length
-        if password.len() < 16 {
-            return Err(Error::new("Password is too short.(at least 16)"));
-        }
-
-        //hash password
-        let mut salt = vec![0; 128];
-        openssl::rand::rand_bytes(&mut salt).unwrap();
-        let hashed_password =
-            argon2::hash_encoded(&password.as_bytes(), &salt, &argon2_config).unwrap();
-        let matches = argon2::verify_encoded(&hashed_password, &password.as_bytes()).unwrap();
-        if !matches {
-            return Err(Error::new("This should not happen."));
-        }
+        let hashed_password = hash_password(password)?;
         let mut user_id;
 
         //todo better rand
@@ -134,7 +175,13 @@ impl User {
         Ok(created_user)
     }
 
-    pub fn login(db_pool: &DBPool, user_id: &str, password: &str) -> Result<String> {
+    pub fn login(
+        db_pool: &DBPool,
+        user_id: &str,
+        password: &str,
+        token_config: &TokenConfig,
+        client_info: &ClientInfo,
+    ) -> Result<AuthTokens> {
         use crate::schema::users::dsl::*;
         //select user by user id
         let v = users
@@ -142,38 +189,133 @@ impl User {
             .limit(1)
             .load::<User>(&db_pool.get()?)?;
         if v.len() == 0 {
-            return Err(Error::new("Invalid user id."));
+            return Err(ErrorCode::Unauthenticated.error("Invalid user id."));
         }
         let user = &v[0];
         //check password
-        let matches = argon2::verify_encoded(&user.argon2_password, &password.as_bytes()).unwrap();
-        if !matches {
-            return Err(Error::new("Invalid password."));
+        if !user.verify_password(password) {
+            return Err(ErrorCode::Unauthenticated.error("Invalid password."));
+        }
+        //check account status(ended bans are lifted here)
+        let status = Ban::effective_status(&db_pool, &user)?;
+        if status == UserStatus::Banned || status == UserStatus::Suspended {
+            //tell the user why
+            return match Ban::select_active(&db_pool, &user.id)? {
+                Some(ban) => Err(ErrorCode::Forbidden.error(ban.describe())),
+                None => Err(ErrorCode::Forbidden.error("Not allowed.")),
+            };
         }
-        //check account status
-        let status: UserStatus = FromPrimitive::from_i32(user.user_status).unwrap();
-        //TODO: what about suspended user, for now block
-        if status == UserStatus::Banned
-            || status == UserStatus::Removed
-            || status == UserStatus::Suspended
-        {
-            return Err(Error::new("Not allowed."));
+        if status == UserStatus::Removed {
+            return Err(ErrorCode::Forbidden.error("Not allowed."));
         }
 
-        //generate token
-        let secret_key = std::env::var("JWT_SECRET_KEY")?;
-        let datetime_now = Utc::now();
-        let token = encode(
-            &Header::default(),
-            &TokenClaim {
-                issuer_user_id: user.id.to_owned(),
-                expiration_time: datetime_now + Duration::hours(48), //expires after 48 hours(for now)
-                token_uuid: Uuid::new_v4(),
-                issued_at_time: datetime_now,
-            },
-            &EncodingKey::from_secret(secret_key.as_bytes()),
-        )?;
-        Ok(token)
+        //generate tokens
+        issue_auth_tokens(&db_pool, &user.id, &token_config, &client_info)
+    }
+
+    /// Returns true if the password matches.
+    /// Accounts without valid hash(e.g. the anonymous account) never match.
+    pub fn verify_password(&self, password: &str) -> bool {
+        argon2::verify_encoded(&self.argon2_password, &password.as_bytes()).unwrap_or(false)
+    }
+
+    /// Change the password of the user.(requires the current password)
+    /// All tokens issued before the change are revoked.
+    pub fn change_password(
+        db_pool: &DBPool,
+        user_id: &str,
+        old_password: &str,
+        new_password: &str,
+    ) -> Result<()> {
+        let user = match User::select_by_user_id(&db_pool, user_id)? {
+            Some(u) => u,
+            None => return Err(ErrorCode::NotFound.error("User does not exist")),
+        };
+        if !user.verify_password(old_password) {
+            return Err(ErrorCode::Validation.error("Invalid password."));
+        }
+        User::set_password(&db_pool, user_id, new_password)
+    }
+
+    /// Overwrite the password without checking the current one and revoke all tokens.
+    /// Callers must authorize the change.(e.g. by a password reset token)
+    pub fn set_password(db_pool: &DBPool, user_id: &str, new_password: &str) -> Result<()> {
+        use crate::schema::users::dsl::*;
+        let hashed_password = hash_password(new_password)?;
+        diesel::update(users.filter(id.eq(user_id)))
+            .set((
+                argon2_password.eq(hashed_password),
+                tokens_valid_after.eq(Some(Utc::now())),
+            ))
+            .execute(&db_pool.get()?)?;
+        IssuedToken::revoke_all(&db_pool, user_id)?;
+        Ok(())
+    }
+
+    /// Remove the account by its owner.(requires password)
+    /// The user record is kept with UserStatus::Removed, so tokens of the user are rejected by verify_token.
+    /// Threadposts and threads are handled based on the policy.
+    pub fn remove_account(
+        db_pool: &DBPool,
+        index_writer: &mut IndexWriter,
+        user_id: &str,
+        password: &str,
+        policy: ContentRemovalPolicy,
+    ) -> Result<()> {
+        let user = match User::select_by_user_id(&db_pool, user_id)? {
+            Some(u) => u,
+            None => return Err(ErrorCode::NotFound.error("User does not exist")),
+        };
+        if !user.verify_password(password) {
+            return Err(ErrorCode::Validation.error("Invalid password."));
+        }
+
+        let db_connection = db_pool.get()?;
+        db_connection.build_transaction().run::<_, Error, _>(|| {
+            use crate::schema::threadposts;
+            use crate::schema::threads;
+
+            match policy {
+                ContentRemovalPolicy::Anonymize => {
+                    diesel::update(
+                        threadposts::table.filter(threadposts::poster_user_id.eq(user_id)),
+                    )
+                    .set(threadposts::poster_user_id.eq(ANONYMOUS_USER_ID))
+                    .execute(&db_connection)?;
+                }
+                ContentRemovalPolicy::Delete => {
+                    let removed_uuids = diesel::delete(
+                        threadposts::table.filter(threadposts::poster_user_id.eq(user_id)),
+                    )
+                    .returning(threadposts::uuid)
+                    .get_results::<Uuid>(&db_connection)?;
+
+                    let index = index_writer.index();
+                    let schema = index.schema();
+                    let uuid_field = schema.get_field("threadpost_uuid").unwrap();
+                    for removed_uuid in &removed_uuids {
+                        let term = Term::from_field_bytes(uuid_field, removed_uuid.as_bytes());
+                        index_writer.delete_term(term);
+                    }
+                }
+            }
+
+            //threads also contain other users' posts, so always anonymize them
+            diesel::update(threads::table.filter(threads::creator_user_id.eq(user_id)))
+                .set(threads::creator_user_id.eq(ANONYMOUS_USER_ID))
+                .execute(&db_connection)?;
+
+            diesel::update(users::table.filter(users::id.eq(user_id)))
+                .set(users::user_status.eq(UserStatus::Removed.to_i32().unwrap()))
+                .execute(&db_connection)?;
+
+            if policy == ContentRemovalPolicy::Delete {
+                index_writer.commit()?;
+            }
+            Ok(())
+        })?;
+        IssuedToken::revoke_all(&db_pool, user_id)?;
+        Ok(())
     }
 
     pub fn change_status(db_pool: &DBPool, user_id: &str, status: UserStatus) -> Result<()> {
@@ -186,12 +328,16 @@ impl User {
         Ok(())
     }
 
-    pub fn change_type(db_pool: &DBPool, user_id: &str, new_type: UserType) -> Result<()> {
+    pub fn change_type(
+        db_connection: &PgConnection,
+        user_id: &str,
+        new_type: UserType,
+    ) -> Result<()> {
         assert!(new_type.to_i32().is_some());
         use crate::schema::users::dsl::*;
         diesel::update(users.filter(id.eq(user_id)))
             .set(user_type.eq(new_type.to_i32().unwrap()))
-            .execute(&db_pool.get()?)?;
+            .execute(db_connection)?;
         Ok(())
     }
 }
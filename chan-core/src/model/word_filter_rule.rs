@@ -0,0 +1,189 @@
+use crate::error::ErrorCode;
+use crate::model::{compile_regex, validate_rule_regex, Board};
+use crate::schema::word_filter_rules;
+use crate::DBPool;
+use async_graphql::{Result, SimpleObject};
+use chrono::{DateTime, Utc};
+use diesel::prelude::*;
+use diesel::PgConnection;
+use diesel::{Insertable, Queryable};
+use regex::NoExpand;
+use uuid::Uuid;
+
+const MAX_PATTERN_LENGTH: usize = 256;
+const MAX_REPLACEMENT_LENGTH: usize = 256;
+
+/// Find-and-replace rule of a board.
+/// Applied to titles and threadposts on submission, the original text is kept for moderators.
+#[derive(Queryable, SimpleObject, Clone)]
+pub struct WordFilterRule {
+    #[graphql(skip)]
+    primary_key: i32,
+    pub uuid: Uuid,
+    pub board_id: Uuid,
+    /// Word matched case-insensitively, or a regex if is_regex.
+    pub pattern: String,
+    pub is_regex: bool,
+    /// Replaces every match. $1, $name etc. expand to the groups of regexes.
+    pub replacement: String,
+    pub creator_user_id: String,
+    pub created_at: DateTime<Utc>,
+}
+
+impl WordFilterRule {
+    /// Add a rule to the board.
+    pub fn create_new(
+        db_connection: &PgConnection,
+        creator_user_id: &str,
+        board_uuid: &Uuid,
+        pattern: &str,
+        is_regex: bool,
+        replacement: &str,
+    ) -> Result<Self> {
+        if pattern.trim().is_empty() || pattern.len() > MAX_PATTERN_LENGTH {
+            return Err(ErrorCode::Validation.error(format!(
+                "Pattern must be 1 to {} bytes.",
+                MAX_PATTERN_LENGTH
+            )));
+        }
+        if replacement.len() > MAX_REPLACEMENT_LENGTH {
+            return Err(ErrorCode::Validation.error(format!(
+                "Replacement must be at most {} bytes.",
+                MAX_REPLACEMENT_LENGTH
+            )));
+        }
+        if is_regex {
+            validate_rule_regex(pattern)?;
+        }
+        if !Board::exists(db_connection, board_uuid)? {
+            return Err(ErrorCode::NotFound.error("Invalid board uuid."));
+        }
+
+        let new_rule = NewWordFilterRule {
+            uuid: &Uuid::new_v4(),
+            board_id: board_uuid,
+            pattern: pattern,
+            is_regex: is_regex,
+            replacement: replacement,
+            creator_user_id: creator_user_id,
+            created_at: &Utc::now(),
+        };
+        Ok(diesel::insert_into(word_filter_rules::table)
+            .values(&new_rule)
+            .get_result::<WordFilterRule>(db_connection)?)
+    }
+
+    pub fn select_by_uuid(db_pool: &DBPool, rule_uuid: &Uuid) -> Result<Option<Self>> {
+        use crate::schema::word_filter_rules::dsl::*;
+        Ok(word_filter_rules
+            .filter(uuid.eq(rule_uuid))
+            .first::<WordFilterRule>(&db_pool.get()?)
+            .optional()?)
+    }
+
+    /// Rules of the board, oldest first.(applied in this order)
+    pub fn select_by_board(db_pool: &DBPool, board_uuid: &Uuid) -> Result<Vec<Self>> {
+        use crate::schema::word_filter_rules::dsl::*;
+        Ok(word_filter_rules
+            .filter(board_id.eq(board_uuid))
+            .order_by(created_at.asc())
+            .load::<WordFilterRule>(&db_pool.get()?)?)
+    }
+
+    pub fn remove_by_uuid(db_connection: &PgConnection, rule_uuid: &Uuid) -> Result<()> {
+        use crate::schema::word_filter_rules::dsl::*;
+        let removed =
+            diesel::delete(word_filter_rules.filter(uuid.eq(rule_uuid))).execute(db_connection)?;
+        if removed == 0 {
+            return Err(ErrorCode::NotFound.error("Invalid rule uuid."));
+        }
+        Ok(())
+    }
+
+    /// Replace every match of the rule in the text.
+    pub fn apply(&self, text: &str) -> Result<String> {
+        if self.is_regex {
+            let regex = compile_regex(&self.pattern)?;
+            Ok(regex
+                .replace_all(text, self.replacement.as_str())
+                .into_owned())
+        } else {
+            let regex = compile_regex(&regex::escape(&self.pattern))?;
+            Ok(regex
+                .replace_all(text, NoExpand(&self.replacement))
+                .into_owned())
+        }
+    }
+}
+
+/// Apply the rules in order, each to the output of the previous one.
+/// Returns None if no rule changed the text.
+pub fn apply_word_filters(rules: &[WordFilterRule], text: &str) -> Result<Option<String>> {
+    let mut filtered = text.to_string();
+    for rule in rules {
+        filtered = rule.apply(&filtered)?;
+    }
+    if filtered == text {
+        return Ok(None);
+    }
+    Ok(Some(filtered))
+}
+
+/// diesel model
+#[derive(Insertable)]
+#[table_name = "word_filter_rules"]
+struct NewWordFilterRule<'a> {
+    pub uuid: &'a Uuid,
+    pub board_id: &'a Uuid,
+    pub pattern: &'a str,
+    pub is_regex: bool,
+    pub replacement: &'a str,
+    pub creator_user_id: &'a str,
+    pub created_at: &'a DateTime<Utc>,
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn rule(pattern: &str, is_regex: bool, replacement: &str) -> WordFilterRule {
+        WordFilterRule {
+            primary_key: 0,
+            uuid: Uuid::new_v4(),
+            board_id: Uuid::new_v4(),
+            pattern: pattern.to_string(),
+            is_regex: is_regex,
+            replacement: replacement.to_string(),
+            creator_user_id: "admin".to_string(),
+            created_at: Utc::now(),
+        }
+    }
+
+    #[test]
+    fn word_filters() {
+        let rules = vec![
+            rule("tbh", false, "$1 desu"), //literal, $1 is not expanded
+            rule(r"(\w+)coin", true, "${1}bux"),
+        ];
+        assert_eq!(
+            apply_word_filters(&rules, "TBH buy smartcoin").unwrap(),
+            Some("$1 desu buy smartbux".to_string())
+        );
+        assert_eq!(apply_word_filters(&rules, "nothing here").unwrap(), None);
+        //the literal pattern is not a regex
+        assert_eq!(
+            apply_word_filters(&[rule("a.b", false, "x")], "acb a.b").unwrap(),
+            Some("acb x".to_string())
+        );
+    }
+
+    #[test]
+    fn empty_matching_patterns() {
+        for pattern in ["a*", "^", "$", r"\b", "(?:)", "x|"] {
+            assert!(validate_rule_regex(pattern).is_err(), "{}", pattern);
+        }
+        for pattern in ["a+", r"\bcoin\b", "(?i)spam"] {
+            assert!(validate_rule_regex(pattern).is_ok(), "{}", pattern);
+        }
+    }
+}
@@ -0,0 +1,135 @@
+//! Keyset pagination for Relay connections.
+//! Cursors are keys of rows(e.g. primary_key, threadpost number), not offsets,
+//! so pages don't shift when new rows are inserted and deep pages stay fast.
+use crate::error::ErrorCode;
+use crate::run_blocking;
+use async_graphql::connection::{query, Connection, CursorType, Edge, EmptyFields};
+use async_graphql::{Error, OutputType, Result};
+
+/// Number of items when neither first nor last is given.
+pub const DEFAULT_PAGE_SIZE: usize = 50;
+pub const MAX_PAGE_SIZE: usize = 1000;
+
+/// Complexity of a list field, estimated from the number of requested items.
+/// DEFAULT_PAGE_SIZE items are assumed when neither first nor last is given.(or the list is not paginated)
+pub fn list_complexity(first: Option<i32>, last: Option<i32>, child_complexity: usize) -> usize {
+    let count = match first.or(last) {
+        Some(n) => std::cmp::min(std::cmp::max(n, 0) as usize, MAX_PAGE_SIZE),
+        None => DEFAULT_PAGE_SIZE,
+    };
+    count.saturating_mul(child_complexity).saturating_add(1)
+}
+
+/// Key of a row, encoded as a decimal string.
+pub struct KeyCursor(pub i32);
+
+impl CursorType for KeyCursor {
+    type Error = std::num::ParseIntError;
+
+    fn decode_cursor(s: &str) -> std::result::Result<Self, Self::Error> {
+        Ok(KeyCursor(s.parse()?))
+    }
+
+    fn encode_cursor(&self) -> String {
+        self.0.to_string()
+    }
+}
+
+/// Range of a page, passed to models.
+/// after/before are keys in the order of the list.(e.g. newest first lists compare keys reversed)
+#[derive(Clone, Copy)]
+pub struct KeysetPage {
+    pub after: Option<i32>,
+    pub before: Option<i32>,
+    /// Fetch one more row than the page size to know whether there are more rows.
+    pub limit: i64,
+    /// Fetch from the end of the range.(when last is given)
+    /// Models return rows in the reversed order.
+    pub backward: bool,
+}
+
+pub type KeysetConnection<T> = Connection<KeyCursor, T, EmptyFields, EmptyFields>;
+
+/// Build a connection with the rows fetched by the model.
+/// fetch runs on the blocking thread pool.
+/// It's called again with a one-row page to know whether rows exist on the other side of the cursor.
+pub async fn keyset_connection<T, F>(
+    after: Option<String>,
+    before: Option<String>,
+    first: Option<i32>,
+    last: Option<i32>,
+    key: fn(&T) -> i32,
+    fetch: F,
+) -> Result<KeysetConnection<T>>
+where
+    T: OutputType + Send + 'static,
+    F: Fn(&KeysetPage) -> Result<Vec<T>> + Send + 'static,
+{
+    query(
+        after,
+        before,
+        first,
+        last,
+        |after: Option<KeyCursor>, before: Option<KeyCursor>, first, last| async move {
+            let backward = first.is_none() && last.is_some();
+            let page_size = first.or(last).unwrap_or(DEFAULT_PAGE_SIZE);
+            if page_size > MAX_PAGE_SIZE {
+                return Err(ErrorCode::Validation.error("Too many items requested."));
+            }
+            let page = KeysetPage {
+                after: after.map(|c| c.0),
+                before: before.map(|c| c.0),
+                limit: page_size as i64 + 1,
+                backward: backward,
+            };
+
+            let (rows, has_previous_page, has_next_page) = run_blocking(move || {
+                let mut rows = fetch(&page)?;
+                let has_more = rows.len() > page_size;
+                rows.truncate(page_size);
+                if backward {
+                    rows.reverse();
+                }
+                let (has_previous_page, has_next_page) = if backward {
+                    let has_next_page = match (rows.last(), page.before) {
+                        (Some(row), Some(_)) => !fetch(&KeysetPage {
+                            after: Some(key(row)),
+                            before: None,
+                            limit: 1,
+                            backward: false,
+                        })?
+                        .is_empty(),
+                        //the cursor is a row the client has seen
+                        (None, before) => before.is_some(),
+                        (Some(_), None) => false,
+                    };
+                    (has_more, has_next_page)
+                } else {
+                    let has_previous_page = match (rows.first(), page.after) {
+                        (Some(row), Some(_)) => !fetch(&KeysetPage {
+                            after: None,
+                            before: Some(key(row)),
+                            limit: 1,
+                            backward: true,
+                        })?
+                        .is_empty(),
+                        //the cursor is a row the client has seen
+                        (None, after) => after.is_some(),
+                        (Some(_), None) => false,
+                    };
+                    (has_previous_page, has_more)
+                };
+                Ok((rows, has_previous_page, has_next_page))
+            })
+            .await?;
+
+            let mut connection = Connection::new(has_previous_page, has_next_page);
+            connection.append(
+                rows.into_iter()
+                    .map(|row| Edge::new(KeyCursor(key(&row)), row)),
+            );
+            Ok::<_, Error>(connection)
+        },
+    )
+    .await
+}
@@ -0,0 +1,183 @@
+//! Automatic Persisted Queries.
+//! Clients send sha256 of the query document in extensions.persistedQuery instead of the document.
+//! Unknown hashes return PersistedQueryNotFound, then the client retries with both the hash and the document.
+use async_graphql::extensions::{
+    Extension, ExtensionContext, ExtensionFactory, NextPrepareRequest,
+};
+use async_graphql::{from_value, Request, ServerError, ServerResult};
+use serde::Deserialize;
+use std::collections::{BTreeMap, HashMap};
+use std::sync::{Arc, Mutex};
+
+#[derive(Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct PersistedQuery {
+    version: i32,
+    sha256_hash: String,
+}
+
+/// sha256 of the query document in hex, used as the key of persisted queries.
+pub fn query_hash(query: &str) -> String {
+    openssl::sha::sha256(query.as_bytes())
+        .iter()
+        .map(|b| format!("{:02x}", b))
+        .collect()
+}
+
+/// Stored queries by hash, the least recently used one is evicted once full.
+struct QueryCache {
+    capacity: usize,
+    /// hash -> (query, last use)
+    queries: HashMap<String, (String, u64)>,
+    /// last use -> hash, oldest first
+    uses: BTreeMap<u64, String>,
+    clock: u64,
+}
+
+impl QueryCache {
+    fn new(capacity: usize) -> Self {
+        QueryCache {
+            capacity: capacity,
+            queries: HashMap::new(),
+            uses: BTreeMap::new(),
+            clock: 0,
+        }
+    }
+
+    fn tick(&mut self) -> u64 {
+        self.clock += 1;
+        self.clock
+    }
+
+    fn get(&mut self, hash: &str) -> Option<String> {
+        let now = self.tick();
+        let (query, last_use) = self.queries.get_mut(hash)?;
+        self.uses.remove(last_use);
+        self.uses.insert(now, hash.to_string());
+        *last_use = now;
+        Some(query.clone())
+    }
+
+    fn insert(&mut self, hash: String, query: String) {
+        if self.capacity == 0 {
+            return;
+        }
+        let now = self.tick();
+        if let Some((_, last_use)) = self.queries.remove(&hash) {
+            self.uses.remove(&last_use);
+        }
+        while self.queries.len() >= self.capacity {
+            let oldest = match self.uses.keys().next() {
+                Some(oldest) => *oldest,
+                None => break,
+            };
+            if let Some(hash) = self.uses.remove(&oldest) {
+                self.queries.remove(&hash);
+            }
+        }
+        self.uses.insert(now, hash.clone());
+        self.queries.insert(hash, (query, now));
+    }
+}
+
+/// Schema extension, shares the stored queries between requests.
+pub struct PersistedQueries {
+    queries: Arc<Mutex<QueryCache>>,
+}
+
+impl PersistedQueries {
+    /// Stores up to capacity queries, evicting the least recently used ones.(0 stores nothing)
+    pub fn new(capacity: usize) -> Self {
+        PersistedQueries {
+            queries: Arc::new(Mutex::new(QueryCache::new(capacity))),
+        }
+    }
+}
+
+impl ExtensionFactory for PersistedQueries {
+    fn create(&self) -> Arc<dyn Extension> {
+        Arc::new(PersistedQueriesExtension {
+            queries: self.queries.clone(),
+        })
+    }
+}
+
+struct PersistedQueriesExtension {
+    queries: Arc<Mutex<QueryCache>>,
+}
+
+#[async_graphql::async_trait::async_trait]
+impl Extension for PersistedQueriesExtension {
+    async fn prepare_request(
+        &self,
+        ctx: &ExtensionContext<'_>,
+        mut request: Request,
+        next: NextPrepareRequest<'_>,
+    ) -> ServerResult<Request> {
+        if let Some(value) = request.extensions.remove("persistedQuery") {
+            let persisted_query = from_value::<PersistedQuery>(value)
+                .map_err(|_| ServerError::new("Invalid persisted query.", None))?;
+            if persisted_query.version != 1 {
+                return Err(ServerError::new(
+                    "Unsupported persisted query version.",
+                    None,
+                ));
+            }
+            let hash = persisted_query.sha256_hash.to_lowercase();
+
+            if request.query.is_empty() {
+                let query = self.queries.lock().unwrap().get(&hash);
+                match query {
+                    Some(query) => request.query = query,
+                    None => return Err(ServerError::new("PersistedQueryNotFound", None)),
+                }
+            } else {
+                //never store a document under a hash of another document
+                if query_hash(&request.query) != hash {
+                    return Err(ServerError::new(
+                        "Persisted query hash does not match the query.",
+                        None,
+                    ));
+                }
+                self.queries
+                    .lock()
+                    .unwrap()
+                    .insert(hash, request.query.clone());
+            }
+        }
+        next.run(ctx, request).await
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    #[test]
+    fn hash() {
+        assert_eq!(
+            query_hash(""),
+            "e3b0c44298fc1c149afbf4c8996fb92427ae41e4649b934ca495991b7852b855"
+        );
+        assert_ne!(
+            query_hash("{ boards { edges { cursor } } }"),
+            query_hash("")
+        );
+    }
+
+    #[test]
+    fn evict_least_recently_used() {
+        let mut cache = QueryCache::new(2);
+        cache.insert("a".to_string(), "{ a }".to_string());
+        cache.insert("b".to_string(), "{ b }".to_string());
+        assert_eq!(cache.get("a"), Some("{ a }".to_string()));
+        cache.insert("c".to_string(), "{ c }".to_string());
+        assert_eq!(cache.get("b"), None);
+        assert_eq!(cache.get("a"), Some("{ a }".to_string()));
+        assert_eq!(cache.get("c"), Some("{ c }".to_string()));
+        assert_eq!(cache.queries.len(), cache.uses.len());
+
+        let mut cache = QueryCache::new(0);
+        cache.insert("a".to_string(), "{ a }".to_string());
+        assert_eq!(cache.get("a"), None);
+    }
+}
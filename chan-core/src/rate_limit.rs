@@ -0,0 +1,230 @@
+//! Backends of the IP rate limiter.(actix-extensible-rate-limit)
+use crate::handler::client_ip_address;
+use crate::schema::rate_limit_windows;
+use crate::{run_blocking, DBPool};
+use actix_extensible_rate_limit::backend::memory::InMemoryBackend;
+use actix_extensible_rate_limit::backend::{Backend, SimpleInput, SimpleOutput};
+use actix_web::dev::ServiceRequest;
+use actix_web::error::ErrorInternalServerError;
+use actix_web::ResponseError;
+use async_trait::async_trait;
+use chrono::{DateTime, Utc};
+use diesel::prelude::*;
+use diesel::sql_types::{BigInt, Text};
+use diesel::PgConnection;
+use std::future::{ready, Ready};
+use std::time::{Duration, Instant};
+use tracing::{event, Level};
+
+/// Input function of the rate limiter, keyed by client_ip_address.
+/// X-Forwarded-For is honoured only from server.trusted_proxies, like everywhere else.
+pub fn client_ip_input(
+    interval: Duration,
+    max_requests: u64,
+) -> impl Fn(&ServiceRequest) -> Ready<Result<SimpleInput, actix_web::Error>> + 'static {
+    move |service_request| {
+        let input = match client_ip_address(service_request.request()) {
+            Some(ip_address) => Ok(SimpleInput {
+                interval: interval,
+                max_requests: max_requests,
+                key: ip_address,
+            }),
+            None => Err(ErrorInternalServerError("Unknown client IP address.")),
+        };
+        ready(input)
+    }
+}
+
+/// Counting failed, the request is answered with 500.
+#[derive(Debug)]
+pub struct RateLimitBackendError(String);
+
+impl std::fmt::Display for RateLimitBackendError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "Rate limiter failed: {}", self.0)
+    }
+}
+
+impl ResponseError for RateLimitBackendError {}
+
+/// Window a request was counted in.
+/// Rolling back only touches the same window, a new one may have started since.
+pub struct WindowToken {
+    key: String,
+    window_ends_at: DateTime<Utc>,
+}
+
+#[derive(QueryableByName)]
+#[table_name = "rate_limit_windows"]
+struct RateLimitWindow {
+    count: i64,
+    window_ends_at: DateTime<Utc>,
+}
+
+/// Count a request of the key, a new window starts if the last one has ended.
+/// Single statement, so concurrent requests of the key are never lost.
+fn increment(
+    conn: &PgConnection,
+    window_key: &str,
+    interval: Duration,
+) -> QueryResult<RateLimitWindow> {
+    diesel::sql_query(
+        "INSERT INTO rate_limit_windows (key, count, window_ends_at) \
+         VALUES ($1, 1, now() + $2 * interval '1 millisecond') \
+         ON CONFLICT (key) DO UPDATE SET \
+         count = CASE WHEN rate_limit_windows.window_ends_at <= now() \
+         THEN 1 ELSE rate_limit_windows.count + 1 END, \
+         window_ends_at = CASE WHEN rate_limit_windows.window_ends_at <= now() \
+         THEN EXCLUDED.window_ends_at ELSE rate_limit_windows.window_ends_at END \
+         RETURNING count, window_ends_at",
+    )
+    .bind::<Text, _>(window_key)
+    .bind::<BigInt, _>(interval.as_millis() as i64)
+    .get_result(conn)
+}
+
+/// Counters in the rate_limit_windows table.
+/// They survive restarts and are shared by every process using the database.(e.g. behind a load balancer)
+#[derive(Clone)]
+pub struct DatabaseBackend {
+    db_pool: DBPool,
+}
+
+impl DatabaseBackend {
+    pub fn new(db_pool: DBPool) -> Self {
+        DatabaseBackend { db_pool: db_pool }
+    }
+
+    /// Delete the windows already ended.
+    /// Only keeps the table small, ended windows are reset by the next request anyway.
+    pub async fn delete_ended(&self) -> async_graphql::Result<usize> {
+        let db_pool = self.db_pool.clone();
+        run_blocking(move || {
+            use crate::schema::rate_limit_windows::dsl::*;
+            let deleted = diesel::delete(rate_limit_windows.filter(window_ends_at.le(Utc::now())))
+                .execute(&db_pool.get()?)?;
+            Ok(deleted)
+        })
+        .await
+    }
+
+    /// Run delete_ended every interval in the background.
+    pub fn spawn_cleanup(&self, interval: Duration) {
+        let backend = self.clone();
+        tokio::spawn(async move {
+            let mut ticker = tokio::time::interval(interval);
+            loop {
+                ticker.tick().await;
+                if let Err(e) = backend.delete_ended().await {
+                    event!(Level::ERROR, "Failed to delete rate limit windows: {:?}", e);
+                }
+            }
+        });
+    }
+}
+
+#[async_trait(?Send)]
+impl Backend<SimpleInput> for DatabaseBackend {
+    type Output = SimpleOutput;
+    type RollbackToken = WindowToken;
+    type Error = RateLimitBackendError;
+
+    async fn request(
+        &self,
+        input: SimpleInput,
+    ) -> Result<(bool, Self::Output, Self::RollbackToken), Self::Error> {
+        let db_pool = self.db_pool.clone();
+        let window_key = input.key.clone();
+        let interval = input.interval;
+        let window = run_blocking(move || Ok(increment(&db_pool.get()?, &window_key, interval)?))
+            .await
+            .map_err(|e| RateLimitBackendError(e.message))?;
+
+        let count = window.count as u64;
+        let reset_in = (window.window_ends_at - Utc::now())
+            .to_std()
+            .unwrap_or_default();
+        let output = SimpleOutput {
+            limit: input.max_requests,
+            remaining: input.max_requests.saturating_sub(count),
+            reset: Instant::now() + reset_in,
+        };
+        let token = WindowToken {
+            key: input.key,
+            window_ends_at: window.window_ends_at,
+        };
+        Ok((count <= input.max_requests, output, token))
+    }
+
+    async fn rollback(&self, token: Self::RollbackToken) -> Result<(), Self::Error> {
+        let db_pool = self.db_pool.clone();
+        run_blocking(move || {
+            use crate::schema::rate_limit_windows::dsl::*;
+            diesel::update(
+                rate_limit_windows
+                    .filter(key.eq(&token.key))
+                    .filter(window_ends_at.eq(token.window_ends_at))
+                    .filter(count.gt(0)),
+            )
+            .set(count.eq(count - 1))
+            .execute(&db_pool.get()?)?;
+            Ok(())
+        })
+        .await
+        .map_err(|e| RateLimitBackendError(e.message))
+    }
+}
+
+pub enum RateLimitRollbackToken {
+    Memory(String),
+    Database(WindowToken),
+}
+
+/// Backend selected by rate_limit.backend of the config.
+#[derive(Clone)]
+pub enum RateLimitBackend {
+    /// Counters of this process only, reset on restart.
+    Memory(InMemoryBackend),
+    Database(DatabaseBackend),
+}
+
+#[async_trait(?Send)]
+impl Backend<SimpleInput> for RateLimitBackend {
+    type Output = SimpleOutput;
+    type RollbackToken = RateLimitRollbackToken;
+    type Error = RateLimitBackendError;
+
+    async fn request(
+        &self,
+        input: SimpleInput,
+    ) -> Result<(bool, Self::Output, Self::RollbackToken), Self::Error> {
+        match self {
+            RateLimitBackend::Memory(backend) => {
+                let (allowed, output, token) = backend
+                    .request(input)
+                    .await
+                    .map_err(|e| RateLimitBackendError(e.to_string()))?;
+                Ok((allowed, output, RateLimitRollbackToken::Memory(token)))
+            }
+            RateLimitBackend::Database(backend) => {
+                let (allowed, output, token) = backend.request(input).await?;
+                Ok((allowed, output, RateLimitRollbackToken::Database(token)))
+            }
+        }
+    }
+
+    async fn rollback(&self, token: Self::RollbackToken) -> Result<(), Self::Error> {
+        match (self, token) {
+            (RateLimitBackend::Memory(backend), RateLimitRollbackToken::Memory(token)) => backend
+                .rollback(token)
+                .await
+                .map_err(|e| RateLimitBackendError(e.to_string())),
+            (RateLimitBackend::Database(backend), RateLimitRollbackToken::Database(token)) => {
+                backend.rollback(token).await
+            }
+            _ => Err(RateLimitBackendError(
+                "Rollback token of another backend.".to_string(),
+            )),
+        }
+    }
+}
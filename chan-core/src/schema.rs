@@ -1,3 +1,28 @@
+table! {
+    bans (primary_key) {
+        primary_key -> Int4,
+        uuid -> Uuid,
+        user_id -> Text,
+        issuer_user_id -> Text,
+        user_status -> Int4,
+        reason -> Text,
+        starts_at -> Timestamptz,
+        ends_at -> Nullable<Timestamptz>,
+        lifted_at -> Nullable<Timestamptz>,
+        lifter_user_id -> Nullable<Text>,
+    }
+}
+
+table! {
+    board_moderators (primary_key) {
+        primary_key -> Int4,
+        board_id -> Uuid,
+        user_id -> Text,
+        assigner_user_id -> Text,
+        assigned_at -> Timestamptz,
+    }
+}
+
 table! {
     boards (primary_key) {
         primary_key -> Int4,
@@ -8,6 +33,58 @@ table! {
     }
 }
 
+table! {
+    captchas (primary_key) {
+        primary_key -> Int4,
+        uuid -> Uuid,
+        question -> Text,
+        answer -> Text,
+        issued_at -> Timestamptz,
+        expires_at -> Timestamptz,
+    }
+}
+
+table! {
+    content_filter_rules (primary_key) {
+        primary_key -> Int4,
+        uuid -> Uuid,
+        board_id -> Uuid,
+        pattern -> Text,
+        is_regex -> Bool,
+        action -> Int4,
+        creator_user_id -> Text,
+        created_at -> Timestamptz,
+    }
+}
+
+table! {
+    held_threadposts (primary_key) {
+        primary_key -> Int4,
+        uuid -> Uuid,
+        board_id -> Uuid,
+        parent_thread_id -> Nullable<Uuid>,
+        poster_user_id -> Text,
+        body_text -> Varchar,
+        reason -> Text,
+        held_at -> Timestamptz,
+        thread_title -> Nullable<Text>,
+    }
+}
+
+table! {
+    issued_tokens (primary_key) {
+        primary_key -> Int4,
+        token_uuid -> Uuid,
+        user_id -> Text,
+        issued_at -> Timestamptz,
+        expires_at -> Timestamptz,
+        revoked_at -> Nullable<Timestamptz>,
+        last_used_at -> Nullable<Timestamptz>,
+        user_agent -> Nullable<Text>,
+        ip_address -> Nullable<Text>,
+    }
+}
+
 table! {
     logs (primary_key) {
         primary_key -> Int4,
@@ -18,6 +95,76 @@ table! {
     }
 }
 
+table! {
+    moderation_actions (primary_key) {
+        primary_key -> Int4,
+        uuid -> Uuid,
+        actor_user_id -> Text,
+        action_type -> Int4,
+        target -> Text,
+        reason -> Nullable<Text>,
+        content_snapshot -> Nullable<Text>,
+        created_at -> Timestamptz,
+    }
+}
+
+table! {
+    notifications (primary_key) {
+        primary_key -> Int4,
+        uuid -> Uuid,
+        recipient_user_id -> Text,
+        kind -> Int4,
+        actor_user_id -> Text,
+        thread_id -> Uuid,
+        threadpost_id -> Uuid,
+        created_at -> Timestamptz,
+        read_at -> Nullable<Timestamptz>,
+    }
+}
+
+table! {
+    password_reset_tokens (primary_key) {
+        primary_key -> Int4,
+        token_hash -> Text,
+        user_id -> Text,
+        issuer_user_id -> Text,
+        issued_at -> Timestamptz,
+        expires_at -> Timestamptz,
+        used_at -> Nullable<Timestamptz>,
+    }
+}
+
+table! {
+    rate_limit_windows (key) {
+        key -> Text,
+        count -> Int8,
+        window_ends_at -> Timestamptz,
+    }
+}
+
+table! {
+    refresh_tokens (primary_key) {
+        primary_key -> Int4,
+        token_hash -> Text,
+        user_id -> Text,
+        access_token_uuid -> Uuid,
+        issued_at -> Timestamptz,
+        expires_at -> Timestamptz,
+        used_at -> Nullable<Timestamptz>,
+        revoked_at -> Nullable<Timestamptz>,
+    }
+}
+
+table! {
+    thread_watches (primary_key) {
+        primary_key -> Int4,
+        user_id -> Text,
+        thread_id -> Uuid,
+        last_read_number -> Int4,
+        watched_at -> Timestamptz,
+    }
+}
+
 table! {
     threadposts (primary_key) {
         primary_key -> Int4,
@@ -27,6 +174,7 @@ table! {
         poster_user_id -> Text,
         parent_thread_id -> Uuid,
         body_text -> Varchar,
+        original_body_text -> Nullable<Text>,
     }
 }
 
@@ -38,6 +186,8 @@ table! {
         parent_board_id -> Uuid,
         title -> Varchar,
         creator_user_id -> Text,
+        original_title -> Nullable<Text>,
+        locked -> Bool,
     }
 }
 
@@ -49,13 +199,40 @@ table! {
         argon2_password -> Text,
         user_type -> Int4,
         user_status -> Int4,
+        tokens_valid_after -> Nullable<Timestamptz>,
+    }
+}
+
+table! {
+    word_filter_rules (primary_key) {
+        primary_key -> Int4,
+        uuid -> Uuid,
+        board_id -> Uuid,
+        pattern -> Text,
+        is_regex -> Bool,
+        replacement -> Text,
+        creator_user_id -> Text,
+        created_at -> Timestamptz,
     }
 }
 
 allow_tables_to_appear_in_same_query!(
+    bans,
+    board_moderators,
     boards,
+    captchas,
+    content_filter_rules,
+    held_threadposts,
+    issued_tokens,
     logs,
+    moderation_actions,
+    notifications,
+    password_reset_tokens,
+    rate_limit_windows,
+    refresh_tokens,
+    thread_watches,
     threadposts,
     threads,
     users,
+    word_filter_rules,
 );
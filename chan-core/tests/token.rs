@@ -1,5 +1,5 @@
 use anyhow::Result;
-use chan_core::model::{verify_token, User, UserType};
+use chan_core::model::{verify_token, ClientInfo, TokenConfig, User, UserType};
 use diesel::r2d2::{ConnectionManager, Pool};
 use diesel::PgConnection;
 use dotenv::dotenv;
@@ -29,11 +29,21 @@ fn user_token_test() -> Result<()> {
     assert!(result.is_ok());
     let user = result.unwrap();
 
-    let result = User::login(&db_pool, &user_id, &user_pass);
+    let token_config = TokenConfig {
+        secret_key: std::env::var("JWT_SECRET_KEY")?,
+        ..TokenConfig::default()
+    };
+    let result = User::login(
+        &db_pool,
+        &user_id,
+        &user_pass,
+        &token_config,
+        &ClientInfo::default(),
+    );
     assert!(result.is_ok());
-    let token = result.unwrap();
+    let token = result.unwrap().access_token;
 
-    let result = verify_token(&db_pool, &token);
+    let result = verify_token(&db_pool, &token_config, &token);
     if result.is_err() {
         event!(Level::ERROR, "{:?}", result);
     }
@@ -22,6 +22,14 @@ pub struct RegisterAccount;
 )]
 pub struct Login;
 
+#[derive(GraphQLQuery)]
+#[graphql(
+    schema_path = "schema.graphql",
+    query_path = "query/refresh_token.graphql",
+    response_derives = "Debug"
+)]
+pub struct RefreshToken;
+
 #[derive(GraphQLQuery)]
 #[graphql(
     schema_path = "schema.graphql",
@@ -133,5 +141,189 @@ pub struct GetSystemInfo;
     response_derives = "Debug"
 )]
 pub struct ChangeUserType;
+
+#[derive(GraphQLQuery)]
+#[graphql(
+    schema_path = "schema.graphql",
+    query_path = "query/delete_my_account.graphql",
+    response_derives = "Debug"
+)]
+pub struct DeleteMyAccount;
+
+#[derive(GraphQLQuery)]
+#[graphql(
+    schema_path = "schema.graphql",
+    query_path = "query/change_password.graphql",
+    response_derives = "Debug"
+)]
+pub struct ChangePassword;
+
+#[derive(GraphQLQuery)]
+#[graphql(
+    schema_path = "schema.graphql",
+    query_path = "query/issue_password_reset_token.graphql",
+    response_derives = "Debug"
+)]
+pub struct IssuePasswordResetToken;
+
+#[derive(GraphQLQuery)]
+#[graphql(
+    schema_path = "schema.graphql",
+    query_path = "query/reset_password.graphql",
+    response_derives = "Debug"
+)]
+pub struct ResetPassword;
+
+#[derive(GraphQLQuery)]
+#[graphql(
+    schema_path = "schema.graphql",
+    query_path = "query/logout.graphql",
+    response_derives = "Debug"
+)]
+pub struct Logout;
+
+#[derive(GraphQLQuery)]
+#[graphql(
+    schema_path = "schema.graphql",
+    query_path = "query/logout_everywhere.graphql",
+    response_derives = "Debug"
+)]
+pub struct LogoutEverywhere;
+
+#[derive(GraphQLQuery)]
+#[graphql(
+    schema_path = "schema.graphql",
+    query_path = "query/sessions.graphql",
+    response_derives = "Debug"
+)]
+pub struct Sessions;
+
+#[derive(GraphQLQuery)]
+#[graphql(
+    schema_path = "schema.graphql",
+    query_path = "query/revoke_session.graphql",
+    response_derives = "Debug"
+)]
+pub struct RevokeSession;
+
+#[derive(GraphQLQuery)]
+#[graphql(
+    schema_path = "schema.graphql",
+    query_path = "query/suspend_user.graphql",
+    response_derives = "Debug"
+)]
+pub struct SuspendUser;
+
+#[derive(GraphQLQuery)]
+#[graphql(
+    schema_path = "schema.graphql",
+    query_path = "query/ban_user.graphql",
+    response_derives = "Debug"
+)]
+pub struct BanUser;
+
+#[derive(GraphQLQuery)]
+#[graphql(
+    schema_path = "schema.graphql",
+    query_path = "query/lift_ban.graphql",
+    response_derives = "Debug"
+)]
+pub struct LiftBan;
+
+#[derive(GraphQLQuery)]
+#[graphql(
+    schema_path = "schema.graphql",
+    query_path = "query/moderation_actions.graphql",
+    response_derives = "Debug"
+)]
+pub struct ModerationActions;
+
+#[derive(GraphQLQuery)]
+#[graphql(
+    schema_path = "schema.graphql",
+    query_path = "query/assign_board_moderator.graphql",
+    response_derives = "Debug"
+)]
+pub struct AssignBoardModerator;
+
+#[derive(GraphQLQuery)]
+#[graphql(
+    schema_path = "schema.graphql",
+    query_path = "query/revoke_board_moderator.graphql",
+    response_derives = "Debug"
+)]
+pub struct RevokeBoardModerator;
+
+#[derive(GraphQLQuery)]
+#[graphql(
+    schema_path = "schema.graphql",
+    query_path = "query/issue_captcha.graphql",
+    response_derives = "Debug"
+)]
+pub struct IssueCaptcha;
+
+#[derive(GraphQLQuery)]
+#[graphql(
+    schema_path = "schema.graphql",
+    query_path = "query/captcha_required_for_registration.graphql",
+    response_derives = "Debug"
+)]
+pub struct CaptchaRequiredForRegistration;
+
+#[derive(GraphQLQuery)]
+#[graphql(
+    schema_path = "schema.graphql",
+    query_path = "query/unread_notification_count.graphql",
+    response_derives = "Debug"
+)]
+pub struct UnreadNotificationCount;
+
+#[derive(GraphQLQuery)]
+#[graphql(
+    schema_path = "schema.graphql",
+    query_path = "query/notifications.graphql",
+    response_derives = "Debug"
+)]
+pub struct Notifications;
+
+#[derive(GraphQLQuery)]
+#[graphql(
+    schema_path = "schema.graphql",
+    query_path = "query/mark_notifications_read.graphql",
+    response_derives = "Debug"
+)]
+pub struct MarkNotificationsRead;
+
+#[derive(GraphQLQuery)]
+#[graphql(
+    schema_path = "schema.graphql",
+    query_path = "query/watched_threads.graphql",
+    response_derives = "Debug"
+)]
+pub struct WatchedThreads;
+
+#[derive(GraphQLQuery)]
+#[graphql(
+    schema_path = "schema.graphql",
+    query_path = "query/watch_thread.graphql",
+    response_derives = "Debug"
+)]
+pub struct WatchThread;
+
+#[derive(GraphQLQuery)]
+#[graphql(
+    schema_path = "schema.graphql",
+    query_path = "query/unwatch_thread.graphql",
+    response_derives = "Debug"
+)]
+pub struct UnwatchThread;
+
+#[derive(GraphQLQuery)]
+#[graphql(
+    schema_path = "schema.graphql",
+    query_path = "query/mark_thread_read.graphql",
+    response_derives = "Debug"
+)]
+pub struct MarkThreadRead;
 #[cfg(test)]
 mod tests {}
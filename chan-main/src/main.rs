@@ -11,14 +11,23 @@ use actix_web::{
 use actix_web_flash_messages::storage::CookieMessageStore;
 use actix_web_flash_messages::FlashMessagesFramework;
 use async_graphql::{EmptySubscription, Schema};
+use chan_core::config::{Config, RateLimitBackendType};
+use chan_core::content_filter::ContentFilterChain;
+use chan_core::flood::FloodControl;
 use chan_core::graphql::{MutationRoot, QueryRoot};
 use chan_core::handler::{index, index_playground};
-use chan_core::model::SystemInfoContext;
+use chan_core::loader::register_loaders;
+use chan_core::model::{CaptchaProvider, ContentRemovalPolicy, ImageCaptcha, SystemInfoContext};
+use chan_core::persisted_query::PersistedQueries;
+use chan_core::rate_limit::{client_ip_input, DatabaseBackend, RateLimitBackend};
+use chan_web::executor::GraphQLExecutor;
+use chan_web::middleware::RotatingIdentityPolicy;
 use diesel::r2d2::{ConnectionManager, Pool};
 use diesel::PgConnection;
 use dotenv::dotenv;
 use reqwest::Client;
 use std::env;
+use std::path::Path;
 use std::sync::Arc;
 use tokio::sync::RwLock;
 use tracing::{event, span, Level};
@@ -38,49 +47,81 @@ async fn main() -> anyhow::Result<()> {
 
     let _ = span.enter();
 
+    //config file, CHAN_CONFIG or ./chan.toml
+    let config_path = env::var("CHAN_CONFIG").unwrap_or_else(|_| "chan.toml".to_string());
+    let config = Config::load(Path::new(&config_path))?;
+    let cookie_keys = config.cookie_keys()?;
+
     //diesel
-    let database_url = env::var("DATABASE_URL").expect("DATABASE_URL must be set");
     let db_pool = Pool::builder()
-        .max_size(20)
-        .build(ConnectionManager::<PgConnection>::new(database_url))
-        .unwrap();
+        .max_size(config.database.pool_max_size)
+        .build(ConnectionManager::<PgConnection>::new(&config.database.url))?;
 
-    let cd = std::env::current_dir()?.join("tantivy");
     //init tantivy
-    let tantivy_index = chan_core::search_engine::init_tantivy(&cd).unwrap(); //todo from config instead of cd
-    let index_writer = tantivy_index.writer(100_000_000)?;
+    let tantivy_index = chan_core::search_engine::init_tantivy(&config.search.index_dir)?;
+    let index_writer = tantivy_index.writer(config.search.writer_heap_bytes)?;
     let index_reader = tantivy_index.reader()?;
 
     //system info
-    let system_info_context = SystemInfoContext::new(std::time::Duration::from_secs(10));
+    let system_info_context = SystemInfoContext::new(config.system_info_refresh_interval());
+
+    //token signing key and lifetimes
+    let token_config = config.token_config();
+
+    //query depth and complexity
+    let query_limits = config.graphql;
 
     //graphql
-    let schema = Schema::build(QueryRoot, MutationRoot, EmptySubscription)
+    let schema_builder = Schema::build(QueryRoot, MutationRoot, EmptySubscription)
+        .limit_depth(query_limits.max_depth)
+        .limit_complexity(query_limits.max_complexity)
+        .extension(PersistedQueries::new(query_limits.persisted_query_capacity))
         .data(Arc::new(RwLock::new(system_info_context)))
         .data(db_pool.clone())
         .data(tantivy_index.clone())
         .data(Arc::new(RwLock::new(index_writer)))
         .data(index_reader)
-        .finish();
+        .data(ContentRemovalPolicy::Anonymize)
+        .data(token_config.clone())
+        .data(FloodControl::new(config.flood.clone()))
+        .data(Arc::new(ImageCaptcha) as Arc<dyn CaptchaProvider>)
+        .data(Arc::new(ContentFilterChain::from_config(
+            &config.content_filter,
+        )))
+        .data(config.clone());
+    let schema = register_loaders(schema_builder, &db_pool).finish();
+
+    //chan-web runs operations in process, unless graphql_url points to a separate API server
+    let graphql_executor = match &config.server.graphql_url {
+        Some(url) => GraphQLExecutor::http(Client::new(), url)?,
+        None => GraphQLExecutor::in_process(schema.clone(), db_pool.clone(), token_config.clone()),
+    };
 
     //flash message related
-    let key = Key::generate();
-    let message_store = CookieMessageStore::builder(key).build();
+    let message_store = CookieMessageStore::builder(Key::derive_from(&cookie_keys.current)).build();
 
     let flash_fw = FlashMessagesFramework::builder(message_store)
         .minimum_level(actix_web_flash_messages::Level::Debug)
         .build();
 
     //rate limiting
-    use actix_extensible_rate_limit::{
-        backend::memory::InMemoryBackend, backend::SimpleInputFunctionBuilder, RateLimiter,
+    use actix_extensible_rate_limit::{backend::memory::InMemoryBackend, RateLimiter};
+    let rate_limit_interval = config.rate_limit_interval();
+    let backend = match config.rate_limit.backend {
+        RateLimitBackendType::Memory => {
+            RateLimitBackend::Memory(InMemoryBackend::builder().build())
+        }
+        RateLimitBackendType::Database => {
+            let database_backend = DatabaseBackend::new(db_pool.clone());
+            database_backend.spawn_cleanup(rate_limit_interval);
+            RateLimitBackend::Database(database_backend)
+        }
     };
-    let backend = InMemoryBackend::builder().build();
+    let rate_limit_max_requests = config.rate_limit.max_requests;
+    let bind_address = config.server.bind_address.clone();
 
     let server = HttpServer::new(move || {
-        let input = SimpleInputFunctionBuilder::new(std::time::Duration::from_secs(60), 60)
-            .real_ip_key()
-            .build();
+        let input = client_ip_input(rate_limit_interval, rate_limit_max_requests);
         let rate_limit_middleware = RateLimiter::builder(backend.clone(), input)
             .add_headers()
             .build();
@@ -88,19 +129,24 @@ async fn main() -> anyhow::Result<()> {
         App::new()
             .wrap(rate_limit_middleware)
             .wrap(chan_web::middleware::LoginCheck)
+            .wrap(chan_web::middleware::CsrfCheck)
+            .wrap(chan_web::middleware::NotificationBadge)
+            //session cookies only keep preferences(e.g. theme), they are reset when the key is rotated
             .wrap(
-                CookieSession::signed(&[0; 32])
+                CookieSession::signed(&cookie_keys.current)
                     .name("session-cookie")
                     .secure(true),
             )
-            .wrap(IdentityService::new(
-                CookieIdentityPolicy::new(&[0; 32]) // <- create cookie identity policy
-                    .name("auth-cookie")
-                    .secure(true),
-            ))
+            .wrap(IdentityService::new(RotatingIdentityPolicy::new(
+                &cookie_keys.all(),
+                |policy| policy.name("auth-cookie").secure(true),
+            )))
             .wrap(flash_fw.clone())
             .app_data(Data::new(schema.clone()))
-            .app_data(Data::new(Client::new()))
+            .app_data(Data::new(db_pool.clone()))
+            .app_data(Data::new(token_config.clone()))
+            .app_data(Data::new(config.clone()))
+            .app_data(Data::new(graphql_executor.clone()))
             .wrap(middleware::Compress::default())
             .wrap(middleware::Logger::default())
             .service(web::resource("/graphql").guard(guard::Post()).to(index))
@@ -114,12 +160,15 @@ async fn main() -> anyhow::Result<()> {
             .service(chan_web::routes::register)
             .service(chan_web::routes::registration_handler)
             .service(chan_web::routes::logout_handler)
+            .service(chan_web::routes::logout_everywhere_handler)
             .service(chan_web::routes::login)
             .service(chan_web::routes::login_handler)
             .service(chan_web::routes::search)
             .service(chan_web::routes::search_handler)
             .service(chan_web::routes::board::view::board_view)
             .service(chan_web::routes::board::list::board_list)
+            .service(chan_web::routes::board::moderator::board_moderator_assignment_handler)
+            .service(chan_web::routes::board::moderator::board_moderator_revocation_handler)
             .service(chan_web::routes::thread::view::thread_view_range)
             .service(chan_web::routes::thread::view::thread_view_range_post)
             .service(chan_web::routes::thread::creation::thread_creation_handler)
@@ -129,10 +178,26 @@ async fn main() -> anyhow::Result<()> {
             .service(chan_web::routes::threadpost::removal::threadpost_removal_handler)
             .service(chan_web::routes::user::view::user_view)
             .service(chan_web::routes::user::change::user_type_change_handler)
+            .service(chan_web::routes::user::deletion::account_deletion)
+            .service(chan_web::routes::user::deletion::account_deletion_handler)
+            .service(chan_web::routes::user::password::password_change)
+            .service(chan_web::routes::user::password::password_change_handler)
+            .service(chan_web::routes::user::password::password_reset)
+            .service(chan_web::routes::user::password::password_reset_handler)
+            .service(chan_web::routes::user::password::password_reset_issue_handler)
+            .service(chan_web::routes::user::session::session_revocation_handler)
+            .service(chan_web::routes::user::ban::user_suspension_handler)
+            .service(chan_web::routes::user::ban::user_ban_handler)
+            .service(chan_web::routes::user::ban::ban_lift_handler)
             .service(chan_web::routes::rules::rules)
             .service(chan_web::routes::manage::manage)
+            .service(chan_web::routes::audit::audit)
+            .service(chan_web::routes::notification::notifications)
+            .service(chan_web::routes::notification::notifications_read_handler)
+            .service(chan_web::routes::watched::watched)
+            .service(chan_web::routes::watched::thread_watch_handler)
+            .service(chan_web::routes::watched::thread_unwatch_handler)
             .service(chan_web::routes::log::log_view)
-            .service(chan_web::routes::log::log_view_range)
             .service(chan_web::routes::system_info::system_info)
             .service(
                 web::resource(["/redirect/{base}", "/redirect/{base}/{v}"])
@@ -142,6 +207,6 @@ async fn main() -> anyhow::Result<()> {
             .service(Files::new("/css", "static/css").show_files_listing())
     });
 
-    server.bind("127.0.0.1:8080")?.run().await?;
+    server.bind(&bind_address)?.run().await?;
     Ok(())
 }
@@ -1 +1,3 @@
 pub const THEME_SESSION_KEY: &str = "theme";
+pub const CSRF_TOKEN_SESSION_KEY: &str = "csrf_token";
+pub const UNREAD_NOTIFICATIONS_SESSION_KEY: &str = "unread_notifications";
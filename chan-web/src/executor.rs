@@ -0,0 +1,183 @@
+//! Runs typed chan_graphql_client operations.
+//! In process against the shared schema by default, or over HTTP when the web front end is split from the API.
+use crate::utility::GraphQLResponseError;
+use anyhow::anyhow;
+use chan_core::error::ErrorCode;
+use chan_core::graphql::{ChanSchema, TokenString};
+use chan_core::handler::authenticate_request;
+use chan_core::model::{ClientInfo, TokenConfig};
+use chan_core::persisted_query::query_hash;
+use chan_core::DBPool;
+use chan_graphql_client::GraphQLQuery;
+use graphql_client::{QueryBody, Response};
+use reqwest::Url;
+use serde::Serialize;
+
+/// Where chan-web sends GraphQL operations.
+#[derive(Clone)]
+pub enum GraphQLExecutor {
+    /// Execute on the schema of this process, without HTTP round trips.
+    InProcess {
+        schema: ChanSchema,
+        db_pool: DBPool,
+        token_config: TokenConfig,
+    },
+    /// Post to the /graphql endpoint of a GraphQL server.
+    Http { client: reqwest::Client, url: Url },
+}
+
+impl GraphQLExecutor {
+    pub fn in_process(schema: ChanSchema, db_pool: DBPool, token_config: TokenConfig) -> Self {
+        GraphQLExecutor::InProcess {
+            schema: schema,
+            db_pool: db_pool,
+            token_config: token_config,
+        }
+    }
+
+    pub fn http(client: reqwest::Client, url: &str) -> anyhow::Result<Self> {
+        Ok(GraphQLExecutor::Http {
+            client: client,
+            url: Url::parse(url)?,
+        })
+    }
+
+    /// Run the operation as the owner of the token.
+    /// client_info is the user of chan-web.(e.g. sessions made by login)
+    pub async fn execute<Q: GraphQLQuery>(
+        &self,
+        body: &QueryBody<Q::Variables>,
+        token_string: Option<&str>,
+        client_info: Option<&ClientInfo>,
+    ) -> anyhow::Result<Response<Q::ResponseData>> {
+        match self {
+            GraphQLExecutor::InProcess {
+                schema,
+                db_pool,
+                token_config,
+            } => {
+                let variables =
+                    async_graphql::Variables::from_json(serde_json::to_value(&body.variables)?);
+                let request = async_graphql::Request::new(body.query)
+                    .operation_name(body.operation_name)
+                    .variables(variables);
+                let request = authenticate_request(
+                    db_pool,
+                    token_config,
+                    request,
+                    token_string.map(|s| TokenString(s.to_owned())),
+                    client_info.cloned().unwrap_or_default(),
+                )
+                .await;
+                let response = schema.execute(request).await;
+                Ok(serde_json::from_value(serde_json::to_value(&response)?)?)
+            }
+            GraphQLExecutor::Http { client, url } => {
+                let mut headers = Vec::new();
+                if let Some(client_info) = client_info {
+                    if let Some(user_agent) = &client_info.user_agent {
+                        headers.push(("User-Agent", user_agent.to_owned()));
+                    }
+                    if let Some(ip_address) = &client_info.ip_address {
+                        headers.push(("X-Forwarded-For", ip_address.to_owned()));
+                    }
+                }
+                post_graphql_body::<Q>(client, url.clone(), body, token_string, &headers).await
+            }
+        }
+    }
+}
+
+/// Request body of Automatic Persisted Queries.
+/// query is omitted on the first try, the server knows the query by the hash.
+#[derive(Serialize)]
+#[serde(rename_all = "camelCase")]
+struct PersistedQueryBody<'a, V> {
+    #[serde(skip_serializing_if = "Option::is_none")]
+    query: Option<&'a str>,
+    operation_name: &'a str,
+    variables: &'a V,
+    extensions: serde_json::Value,
+}
+
+fn is_persisted_query_not_found<T>(response: &Response<T>) -> bool {
+    match &response.errors {
+        Some(errors) => errors.iter().any(|e| e.message == "PersistedQueryNotFound"),
+        None => false,
+    }
+}
+
+/// Post the query by its hash, and with the document only if the server doesn't know the hash yet.
+async fn post_graphql_body<Q: GraphQLQuery>(
+    client: &reqwest::Client,
+    url: Url,
+    body: &QueryBody<Q::Variables>,
+    token_string: Option<&str>,
+    headers: &[(&str, String)],
+) -> anyhow::Result<Response<Q::ResponseData>> {
+    let mut persisted_body = PersistedQueryBody {
+        query: None,
+        operation_name: body.operation_name,
+        variables: &body.variables,
+        extensions: serde_json::json!({
+            "persistedQuery": {
+                "version": 1,
+                "sha256Hash": query_hash(body.query),
+            }
+        }),
+    };
+    let response = post_persisted_query_body::<Q, _>(
+        client,
+        url.clone(),
+        &persisted_body,
+        token_string,
+        headers,
+    )
+    .await?;
+    if !is_persisted_query_not_found(&response) {
+        return Ok(response);
+    }
+    persisted_body.query = Some(body.query);
+    post_persisted_query_body::<Q, _>(client, url, &persisted_body, token_string, headers).await
+}
+
+async fn post_persisted_query_body<Q: GraphQLQuery, V: Serialize>(
+    client: &reqwest::Client,
+    url: Url,
+    body: &PersistedQueryBody<'_, V>,
+    token_string: Option<&str>,
+    headers: &[(&str, String)],
+) -> anyhow::Result<Response<Q::ResponseData>> {
+    let mut request_builder = client.post(url);
+    if let Some(token_string) = token_string {
+        request_builder = request_builder.header("Token", token_string);
+    }
+    for (key, value) in headers {
+        request_builder = request_builder.header(*key, value);
+    }
+    let reqwest_response = match request_builder.json(body).send().await {
+        Ok(response) => response,
+        Err(e) => {
+            return Err(anyhow!(format!("GraphQL returned an error: {:?}", e)));
+        }
+    };
+    //rejected by the rate limiter before reaching the GraphQL server
+    if reqwest_response.status() == reqwest::StatusCode::TOO_MANY_REQUESTS {
+        let retry_after = reqwest_response
+            .headers()
+            .get(reqwest::header::RETRY_AFTER)
+            .and_then(|value| value.to_str().ok())
+            .and_then(|s| s.parse().ok());
+        return Err(GraphQLResponseError {
+            code: Some(ErrorCode::RateLimited),
+            message: "Too many requests.".to_string(),
+            retry_after: retry_after,
+        }
+        .into());
+    }
+
+    match reqwest_response.json().await {
+        Ok(response) => Ok(response),
+        Err(e) => Err(anyhow!(format!("GraphQL returned an error: {:?}", e))),
+    }
+}
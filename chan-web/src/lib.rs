@@ -1,4 +1,5 @@
 mod constant;
+pub mod executor;
 pub mod middleware;
 mod route;
 mod utility;
@@ -0,0 +1,119 @@
+use crate::constant::CSRF_TOKEN_SESSION_KEY;
+use crate::utility::error_page;
+use actix_session::Session;
+use actix_web::body::EitherBody;
+use actix_web::dev::{self, Payload, ServiceRequest, ServiceResponse};
+use actix_web::dev::{Service, Transform};
+use actix_web::http::{Method, StatusCode};
+use actix_web::web::{Bytes, BytesMut};
+use actix_web::{Error, FromRequest};
+use futures::future::LocalBoxFuture;
+use futures::StreamExt;
+use serde::Deserialize;
+use std::future::{ready, Ready};
+use std::rc::Rc;
+use tracing::{event, Level};
+
+/// Forms larger than this are rejected without reading the rest.
+const MAX_FORM_BYTES: usize = 256 * 1024;
+
+#[derive(Deserialize)]
+struct CsrfField {
+    csrf_token: String,
+}
+
+fn constant_time_eq(a: &[u8], b: &[u8]) -> bool {
+    a.len() == b.len() && a.iter().zip(b).fold(0, |acc, (x, y)| acc | (x ^ y)) == 0
+}
+
+/// Rejects POST requests whose csrf_token form field doesn't match the token of the session.
+/// /graphql is excluded, it's authenticated by the Token header, not by cookies.
+pub struct CsrfCheck;
+
+impl<S: 'static, B> Transform<S, ServiceRequest> for CsrfCheck
+where
+    S: Service<ServiceRequest, Response = ServiceResponse<B>, Error = Error>,
+    S::Future: 'static,
+    B: 'static,
+{
+    type Response = ServiceResponse<EitherBody<B>>;
+    type Error = Error;
+    type InitError = ();
+    type Transform = CsrfCheckMiddleware<S>;
+    type Future = Ready<Result<Self::Transform, Self::InitError>>;
+
+    fn new_transform(&self, service: S) -> Self::Future {
+        ready(Ok(CsrfCheckMiddleware {
+            service: Rc::new(service),
+        }))
+    }
+}
+
+pub struct CsrfCheckMiddleware<S> {
+    service: Rc<S>,
+}
+
+impl<S, B> Service<ServiceRequest> for CsrfCheckMiddleware<S>
+where
+    S: Service<ServiceRequest, Response = ServiceResponse<B>, Error = Error> + 'static,
+    S::Future: 'static,
+    B: 'static,
+{
+    type Response = ServiceResponse<EitherBody<B>>;
+    type Error = Error;
+    type Future = LocalBoxFuture<'static, Result<Self::Response, Self::Error>>;
+
+    dev::forward_ready!(service);
+
+    fn call(&self, mut req: ServiceRequest) -> Self::Future {
+        let svc = self.service.clone();
+
+        Box::pin(async move {
+            if req.method() != Method::POST || req.path() == "/graphql" {
+                let res = svc.call(req).await?;
+                return Ok(res.map_into_left_body());
+            }
+
+            let session = Session::extract(req.request()).await?;
+            let reject = |req: ServiceRequest, status: StatusCode, message: &str| {
+                let response = error_page(&session, status, message).map_into_right_body();
+                Ok(req.into_response(response))
+            };
+
+            //read the form, then put it back for the handler
+            let mut payload = req.take_payload();
+            let mut body = BytesMut::new();
+            while let Some(chunk) = payload.next().await {
+                let chunk = chunk?;
+                if body.len() + chunk.len() > MAX_FORM_BYTES {
+                    return reject(req, StatusCode::PAYLOAD_TOO_LARGE, "Too large form.");
+                }
+                body.extend_from_slice(&chunk);
+            }
+            let body: Bytes = body.freeze();
+
+            let expected = session.get::<String>(CSRF_TOKEN_SESSION_KEY).ok().flatten();
+            let sent = serde_urlencoded::from_bytes::<CsrfField>(&body)
+                .ok()
+                .map(|field| field.csrf_token);
+            let is_valid = match (expected, sent) {
+                (Some(expected), Some(sent)) => {
+                    constant_time_eq(expected.as_bytes(), sent.as_bytes())
+                }
+                _ => false,
+            };
+            if !is_valid {
+                event!(Level::DEBUG, "CSRF check failed: {}", req.path());
+                return reject(
+                    req,
+                    StatusCode::FORBIDDEN,
+                    "Invalid form token. Please reload the page and try again.",
+                );
+            }
+
+            req.set_payload(Payload::from(body));
+            let res = svc.call(req).await?;
+            Ok(res.map_into_left_body())
+        })
+    }
+}
@@ -0,0 +1,76 @@
+use actix_identity::{CookieIdentityPolicy, IdentityPolicy};
+use actix_web::dev::{ServiceRequest, ServiceResponse};
+use actix_web::{Error, HttpMessage};
+use futures::FutureExt;
+use std::future::{ready, Ready};
+
+/// The identity was read from a cookie of a previous key.
+struct PreviousKeyCookie;
+
+/// Identity cookie policy with key rotation.
+/// New cookies use the first key, cookies of the other keys are still accepted and re-issued with the first key.
+pub struct RotatingIdentityPolicy {
+    policies: Vec<CookieIdentityPolicy>,
+}
+
+impl RotatingIdentityPolicy {
+    /// keys must not be empty, the first key is the current one.
+    /// configure is applied to the policy of every key.(e.g. cookie name)
+    pub fn new<F>(keys: &[Vec<u8>], configure: F) -> Self
+    where
+        F: Fn(CookieIdentityPolicy) -> CookieIdentityPolicy,
+    {
+        assert!(!keys.is_empty());
+        RotatingIdentityPolicy {
+            policies: keys
+                .iter()
+                .map(|key| configure(CookieIdentityPolicy::new(key)))
+                .collect(),
+        }
+    }
+}
+
+impl IdentityPolicy for RotatingIdentityPolicy {
+    type Future = Ready<Result<Option<String>, Error>>;
+    type ResponseFuture = Ready<Result<(), Error>>;
+
+    fn from_request(&self, request: &mut ServiceRequest) -> Self::Future {
+        for (i, policy) in self.policies.iter().enumerate() {
+            //cookie policies don't await anything
+            let identity = match policy.from_request(request).now_or_never() {
+                Some(Ok(identity)) => identity,
+                Some(Err(e)) => return ready(Err(e)),
+                None => unreachable!(),
+            };
+            if identity.is_some() {
+                if i != 0 {
+                    request.extensions_mut().insert(PreviousKeyCookie);
+                }
+                return ready(Ok(identity));
+            }
+        }
+        ready(Ok(None))
+    }
+
+    fn to_response<B>(
+        &self,
+        identity: Option<String>,
+        changed: bool,
+        response: &mut ServiceResponse<B>,
+    ) -> Self::ResponseFuture {
+        let rotated = response
+            .request()
+            .extensions()
+            .get::<PreviousKeyCookie>()
+            .is_some();
+        //re-issue the cookie with the current key
+        let changed = changed || (rotated && identity.is_some());
+        match self.policies[0]
+            .to_response(identity, changed, response)
+            .now_or_never()
+        {
+            Some(result) => ready(result),
+            None => unreachable!(),
+        }
+    }
+}
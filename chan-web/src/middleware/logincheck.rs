@@ -76,6 +76,7 @@ where
             if is_logged_in
                 || req.path() == "/login"
                 || req.path() == "/register"
+                || req.path() == "/password_reset"
                 || req.path() == "/"
                 || req.path() == "/graphql"
             {
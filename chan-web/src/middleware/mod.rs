@@ -1,2 +1,8 @@
 mod logincheck;
 pub use logincheck::LoginCheck;
+mod identity;
+pub use identity::RotatingIdentityPolicy;
+mod csrf;
+pub use csrf::CsrfCheck;
+mod notification;
+pub use notification::NotificationBadge;
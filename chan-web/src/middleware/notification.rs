@@ -0,0 +1,100 @@
+use crate::constant::UNREAD_NOTIFICATIONS_SESSION_KEY;
+use crate::executor::GraphQLExecutor;
+use crate::utility::execute_with_token;
+use actix_identity::Identity;
+use actix_session::Session;
+use actix_web::dev::{self, ServiceRequest, ServiceResponse};
+use actix_web::dev::{Service, Transform};
+use actix_web::http::Method;
+use actix_web::{web, Error, FromRequest};
+use chan_graphql_client::UnreadNotificationCount;
+use futures::future::LocalBoxFuture;
+use std::future::{ready, Ready};
+use std::rc::Rc;
+use tracing::{event, Level};
+
+/// Saves the number of unread notifications to the session before pages are rendered.
+/// Templates read it with utility::unread_notifications for the badge.
+pub struct NotificationBadge;
+
+impl<S: 'static, B> Transform<S, ServiceRequest> for NotificationBadge
+where
+    S: Service<ServiceRequest, Response = ServiceResponse<B>, Error = Error>,
+    S::Future: 'static,
+    B: 'static,
+{
+    type Response = ServiceResponse<B>;
+    type Error = Error;
+    type InitError = ();
+    type Transform = NotificationBadgeMiddleware<S>;
+    type Future = Ready<Result<Self::Transform, Self::InitError>>;
+
+    fn new_transform(&self, service: S) -> Self::Future {
+        ready(Ok(NotificationBadgeMiddleware {
+            service: Rc::new(service),
+        }))
+    }
+}
+
+pub struct NotificationBadgeMiddleware<S> {
+    service: Rc<S>,
+}
+
+/// Fetch the count with the token of the user, failures keep the previous count.
+async fn refresh_unread_notifications(req: &ServiceRequest) -> Result<(), Error> {
+    let session = Session::extract(req.request()).await?;
+    let id = Identity::extract(req.request()).await?;
+    if id.identity().is_none() {
+        session.remove(UNREAD_NOTIFICATIONS_SESSION_KEY);
+        return Ok(());
+    }
+    let executor = match req.app_data::<web::Data<GraphQLExecutor>>() {
+        Some(executor) => executor.clone(),
+        None => return Ok(()),
+    };
+    match execute_with_token::<UnreadNotificationCount>(
+        &executor,
+        chan_graphql_client::unread_notification_count::Variables {},
+        &id,
+    )
+    .await
+    {
+        Ok(data) => session.insert(
+            UNREAD_NOTIFICATIONS_SESSION_KEY,
+            data.unread_notification_count,
+        )?,
+        Err(e) => event!(Level::DEBUG, "Failed to count notifications: {}", e),
+    }
+    Ok(())
+}
+
+impl<S, B> Service<ServiceRequest> for NotificationBadgeMiddleware<S>
+where
+    S: Service<ServiceRequest, Response = ServiceResponse<B>, Error = Error> + 'static,
+    S::Future: 'static,
+    B: 'static,
+{
+    type Response = ServiceResponse<B>;
+    type Error = Error;
+    type Future = LocalBoxFuture<'static, Result<Self::Response, Self::Error>>;
+
+    dev::forward_ready!(service);
+
+    fn call(&self, req: ServiceRequest) -> Self::Future {
+        let svc = self.service.clone();
+
+        Box::pin(async move {
+            //only pages, not forms(they redirect to a page), static files or the API
+            let path = req.path();
+            let is_page = !(path == "/graphql"
+                || path.starts_with("/css/")
+                || path.starts_with("/backgrounds/"));
+            if req.method() == Method::GET && is_page {
+                if let Err(e) = refresh_unread_notifications(&req).await {
+                    event!(Level::DEBUG, "Failed to refresh notifications: {}", e);
+                }
+            }
+            svc.call(req).await
+        })
+    }
+}
@@ -0,0 +1,131 @@
+use crate::executor::GraphQLExecutor;
+use crate::utility::csrf_token;
+use crate::utility::error_response;
+use crate::utility::execute_with_token;
+use crate::utility::extract_theme_from_session;
+use crate::utility::unread_notifications;
+use actix_identity::Identity;
+use actix_session::Session;
+use actix_web::{get, web, HttpResponse, Responder};
+use actix_web_flash_messages::IncomingFlashMessages;
+use askama_actix::{Template, TemplateToResponse};
+use chan_core::model::ModerationActionType;
+use chan_graphql_client::ModerationActions;
+use serde::{Deserialize, Serialize};
+
+/// Number of actions per page.
+const PAGE_SIZE: i64 = 50;
+
+struct ModerationActionInfo {
+    created_at: chrono::DateTime<chrono::Utc>,
+    actor_user_id: String,
+    action_type: String,
+    target: String,
+    reason: String,
+    content_snapshot: String,
+}
+
+#[derive(Template)]
+#[template(path = "audit.html")]
+struct AuditTemplate {
+    theme: String,
+    csrf_token: String,
+    unread_notifications: i64,
+    flash_messages: IncomingFlashMessages,
+    actor_user_id: String,
+    action_type: String,
+    target: String,
+    actions: Vec<ModerationActionInfo>,
+    /// Cursor of the next page, empty if there is no next page.
+    next_cursor: String,
+}
+
+/// Filters, empty string matches all.
+#[derive(Serialize, Deserialize)]
+pub struct AuditParams {
+    pub actor_user_id: Option<String>,
+    pub action_type: Option<String>,
+    pub target: Option<String>,
+    pub after: Option<String>,
+}
+
+fn non_empty(s: &Option<String>) -> Option<String> {
+    match s.as_deref().map(|s| s.trim()) {
+        Some("") | None => None,
+        Some(s) => Some(s.to_owned()),
+    }
+}
+
+/// Only for admin
+#[get("/manage/audit")]
+pub async fn audit(
+    session: Session,
+    params: web::Query<AuditParams>,
+    executor: web::Data<GraphQLExecutor>,
+    flash_messages: IncomingFlashMessages,
+    id: Identity,
+) -> impl Responder {
+    assert!(id.identity().is_some()); //protected route
+
+    let action_type = match non_empty(&params.action_type) {
+        Some(t) => match t.parse::<i64>() {
+            Ok(t) => Some(t),
+            Err(_) => return HttpResponse::BadRequest().body("Invalid action type."),
+        },
+        None => None,
+    };
+    let variables = chan_graphql_client::moderation_actions::Variables {
+        actor_user_id: non_empty(&params.actor_user_id),
+        action_type: action_type,
+        target: non_empty(&params.target),
+        after: non_empty(&params.after),
+        first: Some(PAGE_SIZE),
+    };
+
+    let result = execute_with_token::<ModerationActions>(&executor, variables, &id).await;
+
+    if result.is_err() {
+        debug_assert!(result.as_ref().err().is_some());
+        let error = result.as_ref().err().unwrap();
+        return error_response(&session, error);
+    }
+    let data = result.unwrap();
+
+    let mut actions = Vec::new();
+    for edge in data.moderation_actions.edges.unwrap_or_default() {
+        let action = match edge {
+            Some(e) => e.node,
+            None => continue,
+        };
+        actions.push(ModerationActionInfo {
+            created_at: action.created_at,
+            actor_user_id: action.actor_user_id,
+            action_type: match ModerationActionType::from_i32(action.action_type as i32) {
+                Ok(t) => format!("{:?}", t),
+                Err(_) => action.action_type.to_string(),
+            },
+            target: action.target,
+            reason: action.reason.unwrap_or_default(),
+            content_snapshot: action.content_snapshot.unwrap_or_default(),
+        });
+    }
+    let page_info = data.moderation_actions.page_info;
+    let next_cursor = if page_info.has_next_page {
+        page_info.end_cursor.unwrap_or_default()
+    } else {
+        String::new()
+    };
+
+    AuditTemplate {
+        theme: extract_theme_from_session(&session),
+        csrf_token: csrf_token(&session),
+        unread_notifications: unread_notifications(&session),
+        flash_messages: flash_messages,
+        actor_user_id: non_empty(&params.actor_user_id).unwrap_or_default(),
+        action_type: non_empty(&params.action_type).unwrap_or_default(),
+        target: non_empty(&params.target).unwrap_or_default(),
+        actions: actions,
+        next_cursor: next_cursor,
+    }
+    .to_response()
+}
@@ -1,8 +1,11 @@
+use crate::executor::GraphQLExecutor;
+use crate::utility::csrf_token;
+use crate::utility::error_response;
+use crate::utility::execute_with_token;
 use crate::utility::extract_theme_from_session;
-use crate::utility::post_graphql_with_token_ex;
+use crate::utility::unread_notifications;
 use actix_identity::{CookieIdentityPolicy, Identity, IdentityService};
 use actix_session::Session;
-use actix_web::dev::ConnectionInfo;
 use actix_web::guard::Connect;
 use actix_web::{get, post, web, App, HttpRequest, HttpResponse, HttpServer, Responder};
 use actix_web_flash_messages::{FlashMessage, IncomingFlashMessages};
@@ -17,6 +20,8 @@ use tracing::{event, Id, Level};
 #[template(path = "board_creation.html")]
 struct BoardCreationTemplate {
     theme: String,
+    csrf_token: String,
+    unread_notifications: i64,
     flash_messages: IncomingFlashMessages,
 }
 
@@ -27,6 +32,8 @@ pub async fn board_creation(
 ) -> impl Responder {
     BoardCreationTemplate {
         theme: extract_theme_from_session(&session),
+        csrf_token: csrf_token(&session),
+        unread_notifications: unread_notifications(&session),
         flash_messages: flash_messages,
     }
     .to_response()
@@ -40,9 +47,9 @@ pub struct BoardCreationParams {
 
 #[post("/board_creation")]
 pub async fn board_creation_handler(
+    session: Session,
     params: web::Form<BoardCreationParams>,
-    reqwest_client: web::Data<Client>,
-    connection_info: ConnectionInfo,
+    executor: web::Data<GraphQLExecutor>,
     id: Identity,
 ) -> impl Responder {
     assert!(id.identity().is_some());
@@ -52,24 +59,12 @@ pub async fn board_creation_handler(
         board_description: params.description.to_owned(),
     };
 
-    let graphql_url = format!(
-        "{}://{}/graphql",
-        connection_info.scheme(),
-        connection_info.host()
-    );
-
-    let result = post_graphql_with_token_ex::<CreateBoard, _>(
-        &reqwest_client,
-        &graphql_url,
-        variables,
-        &id.identity().unwrap(),
-    )
-    .await;
+    let result = execute_with_token::<CreateBoard>(&executor, variables, &id).await;
 
     if result.is_err() {
         debug_assert!(result.as_ref().err().is_some());
         let error = result.as_ref().err().unwrap();
-        return HttpResponse::InternalServerError().body(error.to_string());
+        return error_response(&session, error);
     }
     let data = result.unwrap();
 
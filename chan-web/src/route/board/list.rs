@@ -1,9 +1,12 @@
+use crate::executor::GraphQLExecutor;
 use crate::routes::board::BoardInfo;
+use crate::utility::csrf_token;
+use crate::utility::error_response;
+use crate::utility::execute_with_token;
 use crate::utility::extract_theme_from_session;
-use crate::utility::post_graphql_with_token_ex;
+use crate::utility::unread_notifications;
 use actix_identity::{CookieIdentityPolicy, Identity, IdentityService};
 use actix_session::Session;
-use actix_web::dev::ConnectionInfo;
 use actix_web::guard::Connect;
 use actix_web::{get, post, web, App, HttpRequest, HttpResponse, HttpServer, Responder};
 use actix_web_flash_messages::{FlashMessage, IncomingFlashMessages};
@@ -18,44 +21,45 @@ use tracing::{event, Id, Level};
 #[template(path = "board_list.html")]
 struct BoardListTemplate {
     theme: String,
+    csrf_token: String,
+    unread_notifications: i64,
     flash_messages: IncomingFlashMessages,
     board_infos: Vec<BoardInfo>,
+    /// Cursor of the next page, empty if there is no next page.
+    next_cursor: String,
+}
+
+/// Number of boards per page.
+const PAGE_SIZE: i64 = 100;
+
+#[derive(Serialize, Deserialize)]
+pub struct BoardListParams {
+    /// Cursor of the last board in the previous page.
+    pub after: Option<String>,
 }
 
 #[get("/board_list")]
 pub async fn board_list(
     session: Session,
-    reqwest_client: web::Data<Client>,
-    connection_info: ConnectionInfo,
+    params: web::Query<BoardListParams>,
+    executor: web::Data<GraphQLExecutor>,
     flash_messages: IncomingFlashMessages,
     id: Identity,
 ) -> impl Responder {
     assert!(id.identity().is_some()); //protected route
 
-    let graphql_url = format!(
-        "{}://{}/graphql",
-        connection_info.scheme(),
-        connection_info.host()
-    );
-
     let variables = chan_graphql_client::boards::Variables {
-        after: None,
+        after: params.after.clone(),
         before: None,
-        first: Some(0),
+        first: Some(PAGE_SIZE),
         last: None,
     };
-    let result = post_graphql_with_token_ex::<Boards, _>(
-        &reqwest_client,
-        graphql_url,
-        variables,
-        &id.identity().unwrap(),
-    )
-    .await;
+    let result = execute_with_token::<Boards>(&executor, variables, &id).await;
 
     if result.is_err() {
         debug_assert!(result.as_ref().err().is_some());
         let error = result.as_ref().err().unwrap();
-        return HttpResponse::InternalServerError().body(error.to_string());
+        return error_response(&session, error);
     }
     let data = result.unwrap();
 
@@ -74,10 +78,19 @@ pub async fn board_list(
         }
     }
 
+    let next_cursor = if data.boards.page_info.has_next_page {
+        data.boards.page_info.end_cursor.unwrap_or_default()
+    } else {
+        String::new()
+    };
+
     BoardListTemplate {
         theme: extract_theme_from_session(&session),
+        csrf_token: csrf_token(&session),
+        unread_notifications: unread_notifications(&session),
         flash_messages: flash_messages,
         board_infos: board_infos,
+        next_cursor: next_cursor,
     }
     .to_response()
 }
@@ -7,4 +7,5 @@ struct BoardInfo {
 }
 
 pub mod list;
+pub mod moderator;
 pub mod view;
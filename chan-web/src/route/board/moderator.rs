@@ -0,0 +1,84 @@
+use crate::executor::GraphQLExecutor;
+use crate::utility::error_response;
+use crate::utility::execute_with_token;
+use actix_identity::Identity;
+use actix_session::Session;
+use actix_web::{post, web, HttpResponse, Responder};
+use actix_web_flash_messages::FlashMessage;
+use chan_graphql_client::{AssignBoardModerator, RevokeBoardModerator};
+use serde::{Deserialize, Serialize};
+use uuid::Uuid;
+
+#[derive(Serialize, Deserialize)]
+pub struct BoardModeratorParams {
+    pub board_id: Uuid,
+    pub user_id: String,
+}
+
+/// Only for admin
+#[post("/board_moderator_assignment")]
+pub async fn board_moderator_assignment_handler(
+    session: Session,
+    params: web::Form<BoardModeratorParams>,
+    executor: web::Data<GraphQLExecutor>,
+    id: Identity,
+) -> impl Responder {
+    assert!(id.identity().is_some()); //protected route
+
+    let variables = chan_graphql_client::assign_board_moderator::Variables {
+        board_id: params.board_id,
+        user_id: params.user_id.to_owned(),
+    };
+
+    let result = execute_with_token::<AssignBoardModerator>(&executor, variables, &id).await;
+
+    if result.is_err() {
+        debug_assert!(result.as_ref().err().is_some());
+        let error = result.as_ref().err().unwrap();
+        return error_response(&session, error);
+    }
+
+    FlashMessage::success(format!(
+        "Assigned {} as a moderator of {}.",
+        params.user_id, params.board_id
+    ))
+    .send();
+
+    HttpResponse::Found()
+        .append_header((actix_web::http::header::LOCATION, "/manage"))
+        .finish()
+}
+
+/// Only for admin
+#[post("/board_moderator_revocation")]
+pub async fn board_moderator_revocation_handler(
+    session: Session,
+    params: web::Form<BoardModeratorParams>,
+    executor: web::Data<GraphQLExecutor>,
+    id: Identity,
+) -> impl Responder {
+    assert!(id.identity().is_some()); //protected route
+
+    let variables = chan_graphql_client::revoke_board_moderator::Variables {
+        board_id: params.board_id,
+        user_id: params.user_id.to_owned(),
+    };
+
+    let result = execute_with_token::<RevokeBoardModerator>(&executor, variables, &id).await;
+
+    if result.is_err() {
+        debug_assert!(result.as_ref().err().is_some());
+        let error = result.as_ref().err().unwrap();
+        return error_response(&session, error);
+    }
+
+    FlashMessage::success(format!(
+        "Removed {} from moderators of {}.",
+        params.user_id, params.board_id
+    ))
+    .send();
+
+    HttpResponse::Found()
+        .append_header((actix_web::http::header::LOCATION, "/manage"))
+        .finish()
+}
@@ -1,11 +1,18 @@
+use crate::executor::GraphQLExecutor;
 use crate::routes::board::BoardInfo;
 use crate::routes::thread::ThreadInfo;
+use crate::utility::captcha_challenge;
+use crate::utility::csrf_token;
+use crate::utility::error_page;
+use crate::utility::error_response;
+use crate::utility::execute_with_token;
 use crate::utility::extract_theme_from_session;
-use crate::utility::post_graphql_with_token_ex;
+use crate::utility::unread_notifications;
+use crate::utility::CaptchaChallenge;
 use actix_identity::{CookieIdentityPolicy, Identity, IdentityService};
 use actix_session::Session;
-use actix_web::dev::ConnectionInfo;
 use actix_web::guard::Connect;
+use actix_web::http::StatusCode;
 use actix_web::{get, post, web, App, HttpRequest, HttpResponse, HttpServer, Responder};
 use actix_web_flash_messages::{FlashMessage, IncomingFlashMessages};
 use askama_actix::{Template, TemplateToResponse};
@@ -20,35 +27,48 @@ use uuid::Uuid;
 #[template(path = "board/view.html")]
 struct BoardViewTemplate {
     theme: String,
+    csrf_token: String,
+    unread_notifications: i64,
     flash_messages: IncomingFlashMessages,
     board_info: BoardInfo,
     child_thread_infos: Vec<ThreadInfo>,
+    /// Cursor of the next page, empty if there is no next page.
+    next_cursor: String,
+    /// CAPTCHA of the thread creation form.
+    captcha: CaptchaChallenge,
+}
+
+/// Number of threads per page.
+const PAGE_SIZE: i64 = 100;
+
+#[derive(Serialize, Deserialize)]
+pub struct BoardViewParams {
+    /// Cursor of the last thread in the previous page.
+    pub after: Option<String>,
 }
 
 #[get("/board/{board_id}")]
 pub async fn board_view(
     session: Session,
-    reqwest_client: web::Data<Client>,
-    connection_info: ConnectionInfo,
+    params: web::Query<BoardViewParams>,
+    executor: web::Data<GraphQLExecutor>,
     flash_messages: IncomingFlashMessages,
     board_id: web::Path<String>,
     id: Identity,
+    request: HttpRequest,
 ) -> impl Responder {
     use std::str::FromStr;
 
     assert!(id.identity().is_some()); //protected route
 
-    let graphql_url = format!(
-        "{}://{}/graphql",
-        connection_info.scheme(),
-        connection_info.host()
-    );
-
     let board_uuid = match Uuid::from_str(&board_id) {
         Ok(uuid) => uuid,
         Err(e) => {
-            FlashMessage::success("Login success!").send();
-            return HttpResponse::InternalServerError().body(format!("Invalid id: {}", board_id));
+            return error_page(
+                &session,
+                StatusCode::NOT_FOUND,
+                &format!("Invalid id: {}", board_id),
+            );
         }
     };
 
@@ -57,17 +77,11 @@ pub async fn board_view(
         board_id: board_uuid,
     };
 
-    let result = post_graphql_with_token_ex::<BoardById, _>(
-        &reqwest_client,
-        &graphql_url,
-        variables,
-        &id.identity().unwrap(),
-    )
-    .await;
+    let result = execute_with_token::<BoardById>(&executor, variables, &id).await;
     if result.is_err() {
         debug_assert!(result.as_ref().err().is_some());
         let error = result.as_ref().err().unwrap();
-        return HttpResponse::InternalServerError().body(error.to_string());
+        return error_response(&session, error);
     }
     let data = result.unwrap();
 
@@ -77,33 +91,47 @@ pub async fn board_view(
     //get child thread infos
     let variables = chan_graphql_client::child_threads_by_board_id::Variables {
         board_id: board_uuid,
+        after: params.after.clone(),
+        first: Some(PAGE_SIZE),
     };
 
-    let result = post_graphql_with_token_ex::<ChildThreadsByBoardId, _>(
-        &reqwest_client,
-        &graphql_url,
-        variables,
-        &id.identity().unwrap(),
-    )
-    .await;
+    let result = execute_with_token::<ChildThreadsByBoardId>(&executor, variables, &id).await;
     if result.is_err() {
         debug_assert!(result.as_ref().err().is_some());
         let error = result.as_ref().err().unwrap();
-        return HttpResponse::InternalServerError().body(error.to_string());
+        return error_response(&session, error);
     }
     let data = result.unwrap();
 
     let mut child_thread_infos = Vec::new();
-    for thread in &data.threads {
+    for thread in data.threads.edges.iter().flatten().flatten() {
         child_thread_infos.push(ThreadInfo {
-            title: thread.title.to_owned(),
-            uuid: thread.uuid,
-            created_at: thread.created_at,
-            creator_user_id: thread.creator_user_id.to_owned(),
+            title: thread.node.title.to_owned(),
+            uuid: thread.node.uuid,
+            created_at: thread.node.created_at,
+            creator_user_id: thread.node.creator_user_id.to_owned(),
         });
     }
+    let next_cursor = if data.threads.page_info.has_next_page {
+        data.threads.page_info.end_cursor.unwrap_or_default()
+    } else {
+        String::new()
+    };
+
+    let captcha = match captcha_challenge(
+        &executor,
+        board.captcha_required_for_thread_creation,
+        &request,
+    )
+    .await
+    {
+        Ok(captcha) => captcha,
+        Err(e) => return error_response(&session, &e),
+    };
     BoardViewTemplate {
         theme: extract_theme_from_session(&session),
+        csrf_token: csrf_token(&session),
+        unread_notifications: unread_notifications(&session),
         flash_messages: flash_messages,
         board_info: BoardInfo {
             name: board.name,
@@ -112,6 +140,8 @@ pub async fn board_view(
             created_at: board.created_at,
         },
         child_thread_infos: child_thread_infos,
+        next_cursor: next_cursor,
+        captcha: captcha,
     }
     .to_response()
 }
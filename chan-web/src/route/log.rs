@@ -1,8 +1,11 @@
+use crate::executor::GraphQLExecutor;
+use crate::utility::csrf_token;
+use crate::utility::error_response;
+use crate::utility::execute_with_token;
 use crate::utility::extract_theme_from_session;
-use crate::utility::post_graphql_with_token_ex;
+use crate::utility::unread_notifications;
 use actix_identity::{CookieIdentityPolicy, Identity, IdentityService};
 use actix_session::Session;
-use actix_web::dev::ConnectionInfo;
 use actix_web::guard::Connect;
 use actix_web::{get, post, web, App, HttpRequest, HttpResponse, HttpServer, Responder};
 use actix_web_flash_messages::{FlashMessage, IncomingFlashMessages};
@@ -24,64 +27,52 @@ struct LogInfo<'a> {
 #[template(path = "log.html")]
 struct LogTemplate<'a> {
     theme: String,
+    csrf_token: String,
+    unread_notifications: i64,
     flash_messages: IncomingFlashMessages,
     logs: Vec<LogInfo<'a>>,
+    /// Cursor of the next page, empty if there is no next page.
+    next_cursor: String,
 }
 
-#[get("/log")]
-pub async fn log_view(
-    session: Session,
-    reqwest_client: web::Data<Client>,
-    connection_info: ConnectionInfo,
-    flash_messages: IncomingFlashMessages,
-    id: Identity,
-) -> impl Responder {
-    assert!(id.identity().is_some()); //protected route
-                                      //redirect
-    HttpResponse::Found()
-        .append_header((actix_web::http::header::LOCATION, "/log/1/10"))
-        .finish()
+/// Number of logs per page.
+const PAGE_SIZE: i64 = 50;
+
+#[derive(Serialize, Deserialize)]
+pub struct LogParams {
+    /// Cursor of the last log in the previous page.
+    pub after: Option<String>,
 }
 
-#[get("/log/{l}/{r}")]
-pub async fn log_view_range(
+/// Newest first.
+#[get("/log")]
+pub async fn log_view(
     session: Session,
-    reqwest_client: web::Data<Client>,
-    connection_info: ConnectionInfo,
+    params: web::Query<LogParams>,
+    executor: web::Data<GraphQLExecutor>,
     flash_messages: IncomingFlashMessages,
     id: Identity,
-    path: web::Path<(u32, u32)>,
 ) -> impl Responder {
     assert!(id.identity().is_some()); //protected route
 
-    let graphql_url = format!(
-        "{}://{}/graphql",
-        connection_info.scheme(),
-        connection_info.host()
-    );
-
     let variables = chan_graphql_client::logs_range::Variables {
-        start: Some((path.0 - 1).into()),
-        end: Some((path.1 - 1).into()),
+        after: params.after.clone(),
+        first: Some(PAGE_SIZE),
+        newest_first: Some(true),
     };
-    let result = post_graphql_with_token_ex::<LogsRange, _>(
-        &reqwest_client,
-        graphql_url,
-        variables,
-        &id.identity().unwrap(),
-    )
-    .await;
+    let result = execute_with_token::<LogsRange>(&executor, variables, &id).await;
 
     if result.is_err() {
         debug_assert!(result.as_ref().err().is_some());
         let error = result.as_ref().err().unwrap();
-        return HttpResponse::InternalServerError().body(error.to_string());
+        return error_response(&session, error);
     }
     let data = result.unwrap();
 
-    event!(Level::DEBUG, "{} logs", data.logs.len());
+    let edges = data.logs.edges.unwrap_or_default();
+    event!(Level::DEBUG, "{} logs", edges.len());
     let mut log_infos = Vec::new();
-    for log in &data.logs {
+    for log in edges.iter().flatten().map(|edge| &edge.node) {
         log_infos.push(LogInfo {
             timestamp: &log.timestamp,
             message: &log.message,
@@ -95,11 +86,19 @@ pub async fn log_view_range(
             },
         });
     }
+    let next_cursor = if data.logs.page_info.has_next_page {
+        data.logs.page_info.end_cursor.unwrap_or_default()
+    } else {
+        String::new()
+    };
 
     LogTemplate {
         theme: extract_theme_from_session(&session),
+        csrf_token: csrf_token(&session),
+        unread_notifications: unread_notifications(&session),
         flash_messages: flash_messages,
         logs: log_infos,
+        next_cursor: next_cursor,
     }
     .to_response()
 }
@@ -1,9 +1,14 @@
+use crate::executor::GraphQLExecutor;
+use crate::utility::csrf_token;
+use crate::utility::error_page;
+use crate::utility::error_response;
 use crate::utility::extract_theme_from_session;
-use crate::utility::post_graphql_with_token_ex;
+use crate::utility::unread_notifications;
+use crate::utility::{execute_as_client, AuthIdentity};
 use actix_identity::{CookieIdentityPolicy, Identity, IdentityService};
 use actix_session::Session;
-use actix_web::dev::ConnectionInfo;
 use actix_web::guard::Connect;
+use actix_web::http::StatusCode;
 use actix_web::{get, post, web, App, HttpRequest, HttpResponse, HttpServer, Responder};
 use actix_web_flash_messages::{FlashMessage, IncomingFlashMessages};
 use askama_actix::{Template, TemplateToResponse};
@@ -17,6 +22,8 @@ use tracing::{event, Id, Level};
 #[template(path = "login.html")]
 struct LoginTemplate {
     theme: String,
+    csrf_token: String,
+    unread_notifications: i64,
     flash_messages: IncomingFlashMessages,
 }
 
@@ -24,6 +31,8 @@ struct LoginTemplate {
 pub async fn login(session: Session, flash_messages: IncomingFlashMessages) -> impl Responder {
     LoginTemplate {
         theme: extract_theme_from_session(&session),
+        csrf_token: csrf_token(&session),
+        unread_notifications: unread_notifications(&session),
         flash_messages: flash_messages,
     }
     .to_response()
@@ -37,13 +46,14 @@ pub struct LoginFormParams {
 
 #[post("/login")]
 pub async fn login_handler(
+    session: Session,
     params: web::Form<LoginFormParams>,
-    reqwest_client: web::Data<Client>,
-    connection_info: ConnectionInfo,
+    executor: web::Data<GraphQLExecutor>,
     id: Identity,
+    request: HttpRequest,
 ) -> impl Responder {
     if id.identity().is_some() {
-        return HttpResponse::InternalServerError().body("Already logged in.");
+        return error_page(&session, StatusCode::BAD_REQUEST, "Already logged in.");
     }
 
     let variables = chan_graphql_client::login::Variables {
@@ -51,29 +61,22 @@ pub async fn login_handler(
         password: params.password.to_owned(),
     };
 
-    let graphql_url = format!(
-        "{}://{}/graphql",
-        connection_info.scheme(),
-        connection_info.host()
-    );
-
-    let result = post_graphql_with_token_ex::<Login, _>(
-        &reqwest_client,
-        graphql_url,
-        variables,
-        "dummytoken", /*No need to use token */
-    )
-    .await;
+    let result = execute_as_client::<Login>(&executor, variables, &request).await;
 
     if result.is_err() {
         debug_assert!(result.as_ref().err().is_some());
         let error = result.as_ref().err().unwrap();
-        return HttpResponse::InternalServerError().body(error.to_string());
+        return error_response(&session, error);
     }
 
     let data = result.unwrap();
 
-    id.remember(data.login); //save token to cookie
+    //save tokens to cookie
+    AuthIdentity {
+        access_token: data.login.access_token,
+        refresh_token: Some(data.login.refresh_token),
+    }
+    .remember(&id);
 
     FlashMessage::success("Login success!").send();
 
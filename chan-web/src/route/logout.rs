@@ -1,20 +1,26 @@
-use crate::utility::extract_theme_from_session;
-use actix_identity::{CookieIdentityPolicy, Identity, IdentityService};
+use crate::executor::GraphQLExecutor;
+use crate::utility::error_response;
+use crate::utility::execute_with_token;
+use actix_identity::Identity;
 use actix_session::Session;
-use actix_web::dev::ConnectionInfo;
-use actix_web::guard::Connect;
-use actix_web::{get, post, web, App, HttpRequest, HttpResponse, HttpServer, Responder};
-use actix_web_flash_messages::{FlashMessage, IncomingFlashMessages};
-use askama_actix::{Template, TemplateToResponse};
-use chan_graphql_client::{GraphQLQuery, Login, Response};
-use reqwest::{Client, RequestBuilder};
-use serde::{Deserialize, Serialize};
-use std::error::Error;
-use tracing::{event, Id, Level};
-
-#[get("/logout")]
-pub async fn logout_handler(id: Identity) -> impl Responder {
+use actix_web::{post, web, HttpResponse, Responder};
+use actix_web_flash_messages::FlashMessage;
+use chan_graphql_client::{Logout, LogoutEverywhere};
+use tracing::{event, Level};
+
+#[post("/logout")]
+pub async fn logout_handler(executor: web::Data<GraphQLExecutor>, id: Identity) -> impl Responder {
     assert!(id.identity().is_some());
+
+    //revoke the token on server side
+    let result =
+        execute_with_token::<Logout>(&executor, chan_graphql_client::logout::Variables {}, &id)
+            .await;
+    if let Err(e) = result {
+        //the token may be already invalid, forget it anyway
+        event!(Level::DEBUG, "Failed to revoke the token: {}", e);
+    }
+
     id.forget();
 
     FlashMessage::success("Logged out.").send();
@@ -24,3 +30,39 @@ pub async fn logout_handler(id: Identity) -> impl Responder {
         .append_header((actix_web::http::header::LOCATION, "/"))
         .finish()
 }
+
+#[post("/logout_everywhere")]
+pub async fn logout_everywhere_handler(
+    session: Session,
+    executor: web::Data<GraphQLExecutor>,
+    id: Identity,
+) -> impl Responder {
+    assert!(id.identity().is_some());
+
+    let result = execute_with_token::<LogoutEverywhere>(
+        &executor,
+        chan_graphql_client::logout_everywhere::Variables {},
+        &id,
+    )
+    .await;
+
+    if result.is_err() {
+        debug_assert!(result.as_ref().err().is_some());
+        let error = result.as_ref().err().unwrap();
+        return error_response(&session, error);
+    }
+    let data = result.unwrap();
+
+    id.forget();
+
+    FlashMessage::success(format!(
+        "Logged out from {} sessions.",
+        data.logout_everywhere
+    ))
+    .send();
+
+    //redirect
+    HttpResponse::Found()
+        .append_header((actix_web::http::header::LOCATION, "/"))
+        .finish()
+}
@@ -1,4 +1,6 @@
+use crate::utility::csrf_token;
 use crate::utility::extract_theme_from_session;
+use crate::utility::unread_notifications;
 use actix_session::Session;
 use actix_web::{get,   Responder};
 use actix_web_flash_messages::{ IncomingFlashMessages};
@@ -9,6 +11,8 @@ use askama_actix::{Template, TemplateToResponse};
 #[template(path = "manage.html")]
 struct ManageTemplate {
     theme: String,
+    csrf_token: String,
+    unread_notifications: i64,
     flash_messages: IncomingFlashMessages,
 }
 
@@ -16,6 +20,8 @@ struct ManageTemplate {
 pub async fn manage(session: Session, flash_messages: IncomingFlashMessages) -> impl Responder {
     ManageTemplate {
         theme: extract_theme_from_session(&session),
+        csrf_token: csrf_token(&session),
+        unread_notifications: unread_notifications(&session),
         flash_messages: flash_messages,
     }
     .to_response()
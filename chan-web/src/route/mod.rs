@@ -17,4 +17,7 @@ pub use register::*;
 pub use root::*;
 pub use search::*;
 pub mod manage;
+pub mod audit;
+pub mod notification;
+pub mod watched;
 pub mod system_info;
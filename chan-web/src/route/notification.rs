@@ -0,0 +1,163 @@
+use crate::executor::GraphQLExecutor;
+use crate::utility::csrf_token;
+use crate::utility::error_response;
+use crate::utility::execute_with_token;
+use crate::utility::extract_theme_from_session;
+use crate::utility::unread_notifications;
+use actix_identity::Identity;
+use actix_session::Session;
+use actix_web::{get, post, web, HttpResponse, Responder};
+use actix_web_flash_messages::{FlashMessage, IncomingFlashMessages};
+use askama_actix::{Template, TemplateToResponse};
+use chan_core::model::NotificationKind;
+use chan_graphql_client::{MarkNotificationsRead, Notifications};
+use serde::{Deserialize, Serialize};
+use uuid::Uuid;
+
+/// Number of notifications per page.
+const PAGE_SIZE: i64 = 50;
+
+struct NotificationInfo {
+    uuid: Uuid,
+    created_at: chrono::DateTime<chrono::Utc>,
+    /// "replied to" or "mentioned"
+    verb: &'static str,
+    actor_user_id: String,
+    thread_uuid: Uuid,
+    thread_title: String,
+    threadpost_number: i64,
+    body_text: String,
+    is_unread: bool,
+}
+
+#[derive(Template)]
+#[template(path = "notifications.html")]
+struct NotificationsTemplate {
+    theme: String,
+    csrf_token: String,
+    unread_notifications: i64,
+    flash_messages: IncomingFlashMessages,
+    notifications: Vec<NotificationInfo>,
+    /// Cursor of the next page, empty if there is no next page.
+    next_cursor: String,
+}
+
+#[derive(Serialize, Deserialize)]
+pub struct NotificationsParams {
+    pub after: Option<String>,
+}
+
+#[get("/notifications")]
+pub async fn notifications(
+    session: Session,
+    params: web::Query<NotificationsParams>,
+    executor: web::Data<GraphQLExecutor>,
+    flash_messages: IncomingFlashMessages,
+    id: Identity,
+) -> impl Responder {
+    assert!(id.identity().is_some()); //protected route
+
+    let variables = chan_graphql_client::notifications::Variables {
+        unread_only: None,
+        after: params.after.clone().filter(|s| !s.is_empty()),
+        first: Some(PAGE_SIZE),
+    };
+    let result = execute_with_token::<Notifications>(&executor, variables, &id).await;
+
+    if result.is_err() {
+        debug_assert!(result.as_ref().err().is_some());
+        let error = result.as_ref().err().unwrap();
+        return error_response(&session, error);
+    }
+    let data = result.unwrap();
+
+    let mut notification_infos = Vec::new();
+    for edge in data.notifications.edges.unwrap_or_default() {
+        let notification = match edge {
+            Some(e) => e.node,
+            None => continue,
+        };
+        //the threadpost is gone if it was removed after the notification
+        let threadpost = match notification.threadpost {
+            Some(t) => t,
+            None => continue,
+        };
+        notification_infos.push(NotificationInfo {
+            uuid: notification.uuid,
+            created_at: notification.created_at,
+            verb: match NotificationKind::from_i32(notification.kind as i32) {
+                Ok(NotificationKind::Reply) => "replied to",
+                _ => "mentioned",
+            },
+            actor_user_id: notification.actor_user_id,
+            thread_uuid: notification.thread_id,
+            thread_title: notification.thread.map(|t| t.title).unwrap_or_default(),
+            threadpost_number: threadpost.number,
+            body_text: threadpost.body_text,
+            is_unread: notification.read_at.is_none(),
+        });
+    }
+    let page_info = data.notifications.page_info;
+    let next_cursor = if page_info.has_next_page {
+        page_info.end_cursor.unwrap_or_default()
+    } else {
+        String::new()
+    };
+
+    NotificationsTemplate {
+        theme: extract_theme_from_session(&session),
+        csrf_token: csrf_token(&session),
+        unread_notifications: unread_notifications(&session),
+        flash_messages: flash_messages,
+        notifications: notification_infos,
+        next_cursor: next_cursor,
+    }
+    .to_response()
+}
+
+/// notification_uuid is empty to mark all read.
+#[derive(Serialize, Deserialize)]
+pub struct NotificationsReadFormParams {
+    pub notification_uuid: String,
+}
+
+#[post("/notifications/read")]
+pub async fn notifications_read_handler(
+    session: Session,
+    executor: web::Data<GraphQLExecutor>,
+    form: web::Form<NotificationsReadFormParams>,
+    id: Identity,
+) -> impl Responder {
+    assert!(id.identity().is_some()); //protected route
+
+    let notification_uuids = if form.notification_uuid.is_empty() {
+        None
+    } else {
+        match Uuid::parse_str(&form.notification_uuid) {
+            Ok(uuid) => Some(vec![uuid]),
+            Err(_) => return HttpResponse::BadRequest().body("Invalid notification uuid."),
+        }
+    };
+    let variables = chan_graphql_client::mark_notifications_read::Variables {
+        notification_uuids: notification_uuids,
+    };
+    let result = execute_with_token::<MarkNotificationsRead>(&executor, variables, &id).await;
+
+    if result.is_err() {
+        debug_assert!(result.as_ref().err().is_some());
+        let error = result.as_ref().err().unwrap();
+        return error_response(&session, error);
+    }
+    let data = result.unwrap();
+
+    FlashMessage::info(format!(
+        "Marked {} notifications read.",
+        data.mark_notifications_read
+    ))
+    .send();
+
+    //redirect
+    HttpResponse::Found()
+        .append_header((actix_web::http::header::LOCATION, "/notifications"))
+        .finish()
+}
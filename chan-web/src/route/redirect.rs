@@ -1,4 +1,6 @@
+use crate::utility::csrf_token;
 use crate::utility::extract_theme_from_session;
+use crate::utility::unread_notifications;
 use actix_session::Session;
 use actix_web::{get, post, web, App, HttpRequest, HttpResponse, HttpServer, Responder};
 use actix_web_flash_messages::IncomingFlashMessages;
@@ -9,6 +11,8 @@ use askama_actix::{Template, TemplateToResponse};
 struct RedirectTemplate<'a> {
     dest_link: &'a str,
     theme: String,
+    csrf_token: String,
+    unread_notifications: i64,
     flash_messages: IncomingFlashMessages,
 }
 
@@ -29,6 +33,8 @@ pub async fn redirect(
     RedirectTemplate {
         dest_link: &dest_link,
         theme: extract_theme_from_session(&session),
+        csrf_token: csrf_token(&session),
+        unread_notifications: unread_notifications(&session),
         flash_messages: flash_messages,
     }
     .to_response()
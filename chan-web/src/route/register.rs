@@ -1,12 +1,23 @@
+use crate::executor::GraphQLExecutor;
+use crate::utility::captcha_challenge;
+use crate::utility::captcha_form_answer;
+use crate::utility::csrf_token;
+use crate::utility::error_page;
+use crate::utility::error_response;
+use crate::utility::execute;
+use crate::utility::execute_as_client;
 use crate::utility::extract_theme_from_session;
-use crate::utility::post_graphql_with_token_ex;
+use crate::utility::unread_notifications;
+use crate::utility::CaptchaChallenge;
 use actix_session::Session;
-use actix_web::dev::ConnectionInfo;
 use actix_web::guard::Connect;
+use actix_web::http::StatusCode;
 use actix_web::{get, post, web, App, HttpRequest, HttpResponse, HttpServer, Responder};
 use actix_web_flash_messages::{FlashMessage, IncomingFlashMessages};
 use askama_actix::{Template, TemplateToResponse};
-use chan_graphql_client::{register_account, GraphQLQuery, RegisterAccount, Response};
+use chan_graphql_client::{
+    register_account, CaptchaRequiredForRegistration, GraphQLQuery, RegisterAccount, Response,
+};
 use reqwest::{Client, RequestBuilder};
 use serde::{Deserialize, Serialize};
 use std::error::Error;
@@ -16,14 +27,38 @@ use tracing::{event, Level};
 #[template(path = "register.html")]
 struct RegisterTemplate {
     theme: String,
+    csrf_token: String,
+    unread_notifications: i64,
     flash_messages: IncomingFlashMessages,
+    captcha: CaptchaChallenge,
 }
 
 #[get("/register")]
-pub async fn register(session: Session, flash_messages: IncomingFlashMessages) -> impl Responder {
+pub async fn register(
+    session: Session,
+    executor: web::Data<GraphQLExecutor>,
+    flash_messages: IncomingFlashMessages,
+    request: HttpRequest,
+) -> impl Responder {
+    let variables = chan_graphql_client::captcha_required_for_registration::Variables;
+    let result = execute::<CaptchaRequiredForRegistration>(&executor, variables).await;
+    let captcha = match result {
+        Ok(data) => {
+            captcha_challenge(&executor, data.captcha_required_for_registration, &request).await
+        }
+        Err(e) => Err(e),
+    };
+    let captcha = match captcha {
+        Ok(captcha) => captcha,
+        Err(e) => return error_response(&session, &e),
+    };
+
     RegisterTemplate {
         theme: extract_theme_from_session(&session),
+        csrf_token: csrf_token(&session),
+        unread_notifications: unread_notifications(&session),
         flash_messages: flash_messages,
+        captcha: captcha,
     }
     .to_response()
 }
@@ -31,36 +66,37 @@ pub async fn register(session: Session, flash_messages: IncomingFlashMessages) -
 #[derive(Serialize, Deserialize)]
 pub struct RegisterFormParams {
     pub password: String,
+    pub captcha_uuid: Option<String>,
+    pub captcha_answer: Option<String>,
 }
 
 #[post("/register")]
 pub async fn registration_handler(
+    session: Session,
     params: web::Form<RegisterFormParams>,
-    reqwest_client: web::Data<Client>,
-    connection_info: ConnectionInfo,
+    executor: web::Data<GraphQLExecutor>,
+    request: HttpRequest,
 ) -> impl Responder {
+    let captcha = match captcha_form_answer(&params.captcha_uuid, &params.captcha_answer) {
+        Ok(captcha) => captcha.map(|(uuid, answer)| register_account::CaptchaAnswer {
+            uuid: uuid,
+            answer: answer,
+        }),
+        Err(_) => return error_page(&session, StatusCode::BAD_REQUEST, "Invalid uuid."),
+    };
+
     let variables = register_account::Variables {
         password: params.password.to_owned(),
+        captcha: captcha,
     };
 
-    let graphql_url = format!(
-        "{}://{}/graphql",
-        connection_info.scheme(),
-        connection_info.host()
-    );
-
-    let result = post_graphql_with_token_ex::<RegisterAccount, _>(
-        &reqwest_client,
-        &graphql_url,
-        variables,
-        "dummytoken", /* no need to use token */
-    )
-    .await;
+    //signups are limited per IP address of the user
+    let result = execute_as_client::<RegisterAccount>(&executor, variables, &request).await;
 
     if result.is_err() {
         debug_assert!(result.as_ref().err().is_some());
         let error = result.as_ref().err().unwrap();
-        return HttpResponse::InternalServerError().body(error.to_string());
+        return error_response(&session, error);
     }
 
     let data = result.unwrap();
@@ -1,4 +1,6 @@
+use crate::utility::csrf_token;
 use crate::utility::extract_theme_from_session;
+use crate::utility::unread_notifications;
 use actix_session::Session;
 use actix_web::{get, post, web, App, HttpResponse, HttpServer, Responder};
 use actix_web_flash_messages::IncomingFlashMessages;
@@ -8,6 +10,8 @@ use askama_actix::{Template, TemplateToResponse};
 #[template(path = "root.html")]
 struct RootTemplate {
     theme: String,
+    csrf_token: String,
+    unread_notifications: i64,
     flash_messages: IncomingFlashMessages,
 }
 
@@ -15,6 +19,8 @@ struct RootTemplate {
 pub async fn root(session: Session, flash_messages: IncomingFlashMessages) -> impl Responder {
     RootTemplate {
         theme: extract_theme_from_session(&session),
+        csrf_token: csrf_token(&session),
+        unread_notifications: unread_notifications(&session),
         flash_messages: flash_messages,
     }
     .to_response()
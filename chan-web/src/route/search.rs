@@ -1,8 +1,11 @@
+use crate::executor::GraphQLExecutor;
+use crate::utility::csrf_token;
+use crate::utility::error_response;
+use crate::utility::execute_with_token;
 use crate::utility::extract_theme_from_session;
-use crate::utility::post_graphql_with_token_ex;
+use crate::utility::unread_notifications;
 use actix_identity::{CookieIdentityPolicy, Identity, IdentityService};
 use actix_session::Session;
-use actix_web::dev::ConnectionInfo;
 use actix_web::guard::Connect;
 use actix_web::{get, post, web, App, HttpResponse, HttpServer, Responder};
 use actix_web_flash_messages::{FlashMessage, IncomingFlashMessages};
@@ -24,6 +27,8 @@ struct SearchResult<'a> {
 #[template(path = "search_result.html")]
 struct SearchResultTemplate<'a> {
     theme: String,
+    csrf_token: String,
+    unread_notifications: i64,
     flash_messages: IncomingFlashMessages,
     search_results: Vec<SearchResult<'a>>,
 }
@@ -32,6 +37,8 @@ struct SearchResultTemplate<'a> {
 #[template(path = "search.html")]
 struct SearchTemplate {
     theme: String,
+    csrf_token: String,
+    unread_notifications: i64,
     flash_messages: IncomingFlashMessages,
 }
 
@@ -39,6 +46,8 @@ struct SearchTemplate {
 pub async fn search(session: Session, flash_messages: IncomingFlashMessages) -> impl Responder {
     SearchTemplate {
         theme: extract_theme_from_session(&session),
+        csrf_token: csrf_token(&session),
+        unread_notifications: unread_notifications(&session),
         flash_messages: flash_messages,
     }
     .to_response()
@@ -52,8 +61,7 @@ pub struct SearchFormParams {
 #[post("/search")]
 pub async fn search_handler(
     params: web::Form<SearchFormParams>,
-    reqwest_client: web::Data<Client>,
-    connection_info: ConnectionInfo,
+    executor: web::Data<GraphQLExecutor>,
     session: Session,
     id: Identity,
     flash_messages: IncomingFlashMessages,
@@ -67,22 +75,10 @@ pub async fn search_handler(
         k: 25,
     };
 
-    let graphql_url = format!(
-        "{}://{}/graphql",
-        connection_info.scheme(),
-        connection_info.host()
-    );
-
-    let result = post_graphql_with_token_ex::<SearchTopK, _>(
-        &reqwest_client,
-        &graphql_url,
-        variables,
-        &id.identity().unwrap(),
-    )
-    .await;
+    let result = execute_with_token::<SearchTopK>(&executor, variables, &id).await;
     if result.is_err() {
         let error = result.as_ref().err().unwrap();
-        return HttpResponse::InternalServerError().body(error.to_string());
+        return error_response(&session, error);
     }
 
     let data = result.unwrap();
@@ -103,6 +99,8 @@ pub async fn search_handler(
 
     SearchResultTemplate {
         theme: extract_theme_from_session(&session),
+        csrf_token: csrf_token(&session),
+        unread_notifications: unread_notifications(&session),
         flash_messages: flash_messages,
         search_results: search_results,
     }
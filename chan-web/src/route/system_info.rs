@@ -1,8 +1,11 @@
+use crate::executor::GraphQLExecutor;
+use crate::utility::csrf_token;
+use crate::utility::error_response;
+use crate::utility::execute_with_token;
 use crate::utility::extract_theme_from_session;
-use crate::utility::post_graphql_with_token_ex;
+use crate::utility::unread_notifications;
 use actix_identity::Identity;
 use actix_session::Session;
-use actix_web::dev::ConnectionInfo;
 use actix_web::{get, post, web, App, HttpResponse, HttpServer, Responder};
 use actix_web_flash_messages::IncomingFlashMessages;
 use askama_actix::{Template, TemplateToResponse};
@@ -14,6 +17,8 @@ use reqwest::{Client, RequestBuilder};
 #[template(path = "system_info.html")]
 struct SystemInfoTemplate {
     theme: String,
+    csrf_token: String,
+    unread_notifications: i64,
     flash_messages: IncomingFlashMessages,
     system_info: SystemInfo,
 }
@@ -21,33 +26,20 @@ struct SystemInfoTemplate {
 #[get("/system_info")]
 pub async fn system_info(
     session: Session,
-    reqwest_client: web::Data<Client>,
+    executor: web::Data<GraphQLExecutor>,
     flash_messages: IncomingFlashMessages,
-    connection_info: ConnectionInfo,
     id: Identity,
 ) -> impl Responder {
     assert!(id.identity().is_some());
 
     let variables = get_system_info::Variables {};
 
-    let graphql_url = format!(
-        "{}://{}/graphql",
-        connection_info.scheme(),
-        connection_info.host()
-    );
-
-    let result = post_graphql_with_token_ex::<GetSystemInfo, _>(
-        &reqwest_client,
-        &graphql_url,
-        variables,
-        &id.identity().unwrap(),
-    )
-    .await;
+    let result = execute_with_token::<GetSystemInfo>(&executor, variables, &id).await;
 
     if result.is_err() {
         debug_assert!(result.as_ref().err().is_some());
         let error = result.as_ref().err().unwrap();
-        return HttpResponse::InternalServerError().body(error.to_string());
+        return error_response(&session, error);
     }
 
     let data = result.unwrap();
@@ -55,6 +47,8 @@ pub async fn system_info(
 
     SystemInfoTemplate {
         theme: extract_theme_from_session(&session),
+        csrf_token: csrf_token(&session),
+        unread_notifications: unread_notifications(&session),
         flash_messages: flash_messages,
         system_info: SystemInfo {
             free_mem: (system_info.free_mem / (1024 * 1024)) as usize, //mb
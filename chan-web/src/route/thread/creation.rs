@@ -1,9 +1,13 @@
+use crate::executor::GraphQLExecutor;
+use crate::utility::captcha_form_answer;
+use crate::utility::error_page;
+use crate::utility::error_response;
+use crate::utility::execute_with_token;
 use crate::utility::extract_theme_from_session;
-use crate::utility::post_graphql_with_token_ex;
 use actix_identity::{CookieIdentityPolicy, Identity, IdentityService};
 use actix_session::Session;
-use actix_web::dev::ConnectionInfo;
 use actix_web::guard::Connect;
+use actix_web::http::StatusCode;
 use actix_web::{get, post, web, App, HttpRequest, HttpResponse, HttpServer, Responder};
 use actix_web_flash_messages::{FlashMessage, IncomingFlashMessages};
 use askama_actix::{Template, TemplateToResponse};
@@ -20,46 +24,48 @@ pub struct ThreadCreationParams {
     pub title: String,
     pub post: String,
     pub parent_board_uuid: String,
+    pub captcha_uuid: Option<String>,
+    pub captcha_answer: Option<String>,
 }
 
 #[post("/thread_creation")]
 pub async fn thread_creation_handler(
+    session: Session,
     params: web::Form<ThreadCreationParams>,
-    reqwest_client: web::Data<Client>,
-    connection_info: ConnectionInfo,
+    executor: web::Data<GraphQLExecutor>,
     id: Identity,
 ) -> impl Responder {
     assert!(id.identity().is_some());
 
     let parent_board_uuid = match Uuid::from_str(&params.parent_board_uuid) {
         Ok(id) => id,
-        Err(_) => return HttpResponse::InternalServerError().body("Invalid uuid."),
+        Err(_) => return error_page(&session, StatusCode::BAD_REQUEST, "Invalid uuid."),
+    };
+    let captcha = match captcha_form_answer(&params.captcha_uuid, &params.captcha_answer) {
+        Ok(captcha) => {
+            captcha.map(
+                |(uuid, answer)| chan_graphql_client::create_thread::CaptchaAnswer {
+                    uuid: uuid,
+                    answer: answer,
+                },
+            )
+        }
+        Err(_) => return error_page(&session, StatusCode::BAD_REQUEST, "Invalid uuid."),
     };
 
     let variables = chan_graphql_client::create_thread::Variables {
         thread_title: params.title.to_owned(),
         parent_board_uuid: parent_board_uuid,
         first_post_text: params.post.to_owned(),
+        captcha: captcha,
     };
 
-    let graphql_url = format!(
-        "{}://{}/graphql",
-        connection_info.scheme(),
-        connection_info.host()
-    );
-
-    let result = post_graphql_with_token_ex::<CreateThread, _>(
-        &reqwest_client,
-        &graphql_url,
-        variables,
-        &id.identity().unwrap(),
-    )
-    .await;
+    let result = execute_with_token::<CreateThread>(&executor, variables, &id).await;
 
     if result.is_err() {
         debug_assert!(result.as_ref().err().is_some());
         let error = result.as_ref().err().unwrap();
-        return HttpResponse::InternalServerError().body(error.to_string());
+        return error_response(&session, error);
     }
     let data = result.unwrap();
 
@@ -1,8 +1,9 @@
+use crate::executor::GraphQLExecutor;
+use crate::utility::error_response;
+use crate::utility::execute_with_token;
 use crate::utility::extract_theme_from_session;
-use crate::utility::post_graphql_with_token_ex;
 use actix_identity::{CookieIdentityPolicy, Identity, IdentityService};
 use actix_session::Session;
-use actix_web::dev::ConnectionInfo;
 use actix_web::guard::Connect;
 use actix_web::{get, post, web, App, HttpRequest, HttpResponse, HttpServer, Responder};
 use actix_web_flash_messages::{FlashMessage, IncomingFlashMessages};
@@ -18,13 +19,15 @@ use uuid::Uuid;
 #[derive(Serialize, Deserialize)]
 pub struct ThreadRemovalParams {
     pub thread_uuid: Uuid,
+    /// Recorded in the moderation audit log.
+    pub reason: String,
 }
 
 #[post("/thread_removal")]
 pub async fn thread_removal_handler(
+    session: Session,
     params: web::Form<ThreadRemovalParams>,
-    reqwest_client: web::Data<Client>,
-    connection_info: ConnectionInfo,
+    executor: web::Data<GraphQLExecutor>,
     id: Identity,
     request: HttpRequest,
 ) -> impl Responder {
@@ -32,26 +35,18 @@ pub async fn thread_removal_handler(
 
     let variables = chan_graphql_client::remove_thread::Variables {
         thread_uuid: params.thread_uuid,
+        reason: match params.reason.trim() {
+            "" => None,
+            r => Some(r.to_owned()),
+        },
     };
 
-    let graphql_url = format!(
-        "{}://{}/graphql",
-        connection_info.scheme(),
-        connection_info.host()
-    );
-
-    let result = post_graphql_with_token_ex::<RemoveThread, _>(
-        &reqwest_client,
-        &graphql_url,
-        variables,
-        &id.identity().unwrap(),
-    )
-    .await;
+    let result = execute_with_token::<RemoveThread>(&executor, variables, &id).await;
 
     if result.is_err() {
         debug_assert!(result.as_ref().err().is_some());
         let error = result.as_ref().err().unwrap();
-        return HttpResponse::InternalServerError().body(error.to_string());
+        return error_response(&session, error);
     }
     let data = result.unwrap();
 
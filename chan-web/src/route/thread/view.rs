@@ -1,15 +1,22 @@
+use crate::executor::GraphQLExecutor;
 use crate::routes::thread::ThreadInfo;
 use crate::routes::threadpost::ThreadPostInfo;
+use crate::utility::captcha_challenge;
+use crate::utility::csrf_token;
+use crate::utility::error_page;
+use crate::utility::error_response;
+use crate::utility::execute_with_token;
 use crate::utility::extract_theme_from_session;
-use crate::utility::post_graphql_with_token_ex;
+use crate::utility::unread_notifications;
+use crate::utility::CaptchaChallenge;
 use actix_identity::{CookieIdentityPolicy, Identity, IdentityService};
 use actix_session::Session;
-use actix_web::dev::ConnectionInfo;
 use actix_web::guard::Connect;
+use actix_web::http::StatusCode;
 use actix_web::{get, post, web, App, HttpRequest, HttpResponse, HttpServer, Responder};
 use actix_web_flash_messages::{FlashMessage, IncomingFlashMessages};
 use askama_actix::{Template, TemplateToResponse};
-use chan_graphql_client::{GraphQLQuery, Response, ThreadById, ThreadPostsRange};
+use chan_graphql_client::{GraphQLQuery, MarkThreadRead, Response, ThreadById, ThreadPostsRange};
 use reqwest::{Client, RequestBuilder};
 use serde::{Deserialize, Serialize};
 use std::error::Error;
@@ -20,16 +27,20 @@ use uuid::Uuid;
 #[template(path = "thread/view.html")]
 struct ThreadViewTemplate<'a> {
     theme: String,
+    csrf_token: String,
+    unread_notifications: i64,
     flash_messages: IncomingFlashMessages,
     thread_info: ThreadInfo,
     threadposts: Vec<ThreadPostInfo<'a>>,
+    /// CAPTCHA of the post form.
+    captcha: CaptchaChallenge,
+    is_watched: bool,
 }
 
 #[get("/thread/{thread_id}")]
 pub async fn thread_view(
     session: Session,
-    reqwest_client: web::Data<Client>,
-    connection_info: ConnectionInfo,
+    executor: web::Data<GraphQLExecutor>,
     flash_messages: IncomingFlashMessages,
     thread_uuid: web::Path<String>,
     id: Identity,
@@ -65,27 +76,24 @@ pub async fn thread_view_range_post(
 #[get("/thread/{thread_id}/{l}/{r}")]
 pub async fn thread_view_range(
     session: Session,
-    reqwest_client: web::Data<Client>,
-    connection_info: ConnectionInfo,
+    executor: web::Data<GraphQLExecutor>,
     flash_messages: IncomingFlashMessages,
     id: Identity,
     path: web::Path<(String, u16, u16)>,
+    request: HttpRequest,
 ) -> impl Responder {
     use std::str::FromStr;
 
     assert!(id.identity().is_some()); //protected route
 
-    let graphql_url = format!(
-        "{}://{}/graphql",
-        connection_info.scheme(),
-        connection_info.host()
-    );
-
     let thread_uuid = match Uuid::from_str(&path.0) {
         Ok(uuid) => uuid,
         Err(e) => {
-            FlashMessage::success("Login success!").send();
-            return HttpResponse::InternalServerError().body(format!("Invalid id: {}", &path.0));
+            return error_page(
+                &session,
+                StatusCode::NOT_FOUND,
+                &format!("Invalid id: {}", &path.0),
+            );
         }
     };
 
@@ -93,59 +101,86 @@ pub async fn thread_view_range(
         thread_id: thread_uuid,
     };
 
-    let result = post_graphql_with_token_ex::<ThreadById, _>(
-        &reqwest_client,
-        &graphql_url,
-        variables,
-        &id.identity().unwrap(),
-    )
-    .await;
+    let result = execute_with_token::<ThreadById>(&executor, variables, &id).await;
 
     if result.is_err() {
         debug_assert!(result.as_ref().err().is_some());
         let error = result.as_ref().err().unwrap();
-        return HttpResponse::InternalServerError().body(error.to_string());
+        return error_response(&session, error);
     }
     let data = result.unwrap();
 
     assert!(data.thread.is_some());
     let thread = data.thread.unwrap();
-    let start = std::cmp::max(1, path.1) - 1;
-    let end = std::cmp::max(1, std::cmp::min(1000, path.2)) - 1;
+    let captcha_required = match &thread.board {
+        Some(board) => board.captcha_required_for_posting,
+        None => false,
+    };
+    //threadposts numbered l to r
+    let l = std::cmp::max(1, path.1);
+    let r = std::cmp::max(l, std::cmp::min(1000, path.2));
 
     //get thread posts
     let variables = chan_graphql_client::thread_posts_range::Variables {
         parent_thread_id: thread_uuid,
-        start: Some(start.into()),
-        end: Some(end.into()),
+        after: if l > 1 {
+            Some((l - 1).to_string())
+        } else {
+            None
+        },
+        first: Some((r - l + 1).into()),
     };
 
-    let result = post_graphql_with_token_ex::<ThreadPostsRange, _>(
-        &reqwest_client,
-        &graphql_url,
-        variables,
-        &id.identity().unwrap(),
-    )
-    .await;
+    let result = execute_with_token::<ThreadPostsRange>(&executor, variables, &id).await;
     if result.is_err() {
         debug_assert!(result.as_ref().err().is_some());
         let error = result.as_ref().err().unwrap();
-        return HttpResponse::InternalServerError().body(error.to_string());
+        return error_response(&session, error);
     }
     let data = result.unwrap();
     event!(Level::DEBUG, "posts {:?}", data.threadposts_by_thread_id);
+
+    let captcha = match captcha_challenge(&executor, captcha_required, &request).await {
+        Ok(captcha) => captcha,
+        Err(e) => return error_response(&session, &e),
+    };
     let mut threadpost_infos = Vec::new();
-    for threadpost in &data.threadposts_by_thread_id {
+    for threadpost in data
+        .threadposts_by_thread_id
+        .edges
+        .iter()
+        .flatten()
+        .flatten()
+        .map(|edge| &edge.node)
+    {
         threadpost_infos.push(ThreadPostInfo {
             number: threadpost.number.try_into().unwrap(),
             body_text: &threadpost.body_text,
+            original_body_text: threadpost.original_body_text.as_deref().unwrap_or_default(),
             posted_at: &threadpost.posted_at,
             poster_user_id: &threadpost.poster_user_id,
             uuid: &threadpost.uuid,
         });
     }
+
+    //mark the shown threadposts read, the marker skips nothing unread before l(0 only checks watching)
+    let last_shown_number = threadpost_infos.last().map(|t| t.number).unwrap_or(0);
+    let variables = chan_graphql_client::mark_thread_read::Variables {
+        thread_id: thread_uuid,
+        first_number: l.into(),
+        last_number: last_shown_number.into(),
+    };
+    let is_watched = match execute_with_token::<MarkThreadRead>(&executor, variables, &id).await {
+        Ok(data) => data.mark_thread_read,
+        Err(e) => {
+            event!(Level::ERROR, "Failed to mark the thread read: {}", e);
+            false
+        }
+    };
     ThreadViewTemplate {
         theme: extract_theme_from_session(&session),
+        csrf_token: csrf_token(&session),
+        unread_notifications: unread_notifications(&session),
         flash_messages: flash_messages,
         thread_info: ThreadInfo {
             title: thread.title,
@@ -154,6 +189,8 @@ pub async fn thread_view_range(
             creator_user_id: thread.creator_user_id,
         },
         threadposts: threadpost_infos,
+        captcha: captcha,
+        is_watched: is_watched,
     }
     .to_response()
 }
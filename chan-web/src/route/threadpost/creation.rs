@@ -1,9 +1,13 @@
+use crate::executor::GraphQLExecutor;
+use crate::utility::captcha_form_answer;
+use crate::utility::error_page;
+use crate::utility::error_response;
+use crate::utility::execute_with_token;
 use crate::utility::extract_theme_from_session;
-use crate::utility::post_graphql_with_token_ex;
 use actix_identity::{CookieIdentityPolicy, Identity, IdentityService};
 use actix_session::Session;
-use actix_web::dev::ConnectionInfo;
 use actix_web::guard::Connect;
+use actix_web::http::StatusCode;
 use actix_web::{get, post, web, App, HttpRequest, HttpResponse, HttpServer, Responder};
 use actix_web_flash_messages::{FlashMessage, IncomingFlashMessages};
 use askama_actix::{Template, TemplateToResponse};
@@ -19,13 +23,15 @@ use uuid::Uuid;
 pub struct ThreadPostCreationParams {
     pub post: String,
     pub parent_thread_uuid: String,
+    pub captcha_uuid: Option<String>,
+    pub captcha_answer: Option<String>,
 }
 
 #[post("/threadpost_creation")]
 pub async fn threadpost_creation_handler(
+    session: Session,
     params: web::Form<ThreadPostCreationParams>,
-    reqwest_client: web::Data<Client>,
-    connection_info: ConnectionInfo,
+    executor: web::Data<GraphQLExecutor>,
     id: Identity,
     request: HttpRequest,
 ) -> impl Responder {
@@ -33,32 +39,32 @@ pub async fn threadpost_creation_handler(
 
     let parent_thread_uuid = match Uuid::from_str(&params.parent_thread_uuid) {
         Ok(id) => id,
-        Err(_) => return HttpResponse::InternalServerError().body("Invalid uuid."),
+        Err(_) => return error_page(&session, StatusCode::BAD_REQUEST, "Invalid uuid."),
+    };
+    let captcha = match captcha_form_answer(&params.captcha_uuid, &params.captcha_answer) {
+        Ok(captcha) => {
+            captcha.map(
+                |(uuid, answer)| chan_graphql_client::post_thread_post::CaptchaAnswer {
+                    uuid: uuid,
+                    answer: answer,
+                },
+            )
+        }
+        Err(_) => return error_page(&session, StatusCode::BAD_REQUEST, "Invalid uuid."),
     };
 
     let variables = chan_graphql_client::post_thread_post::Variables {
         thread_uuid: parent_thread_uuid,
         post_body: params.post.to_owned(),
+        captcha: captcha,
     };
 
-    let graphql_url = format!(
-        "{}://{}/graphql",
-        connection_info.scheme(),
-        connection_info.host()
-    );
-
-    let result = post_graphql_with_token_ex::<PostThreadPost, _>(
-        &reqwest_client,
-        &graphql_url,
-        variables,
-        &id.identity().unwrap(),
-    )
-    .await;
+    let result = execute_with_token::<PostThreadPost>(&executor, variables, &id).await;
 
     if result.is_err() {
         debug_assert!(result.as_ref().err().is_some());
         let error = result.as_ref().err().unwrap();
-        return HttpResponse::InternalServerError().body(error.to_string());
+        return error_response(&session, error);
     }
     let data = result.unwrap();
 
@@ -4,6 +4,8 @@ pub struct ThreadPostInfo<'a> {
     pub posted_at: &'a chrono::DateTime<chrono::Utc>,
     pub poster_user_id: &'a str,
     pub body_text: &'a str,
+    /// Before the word filters, empty unless changed and visible to the user.
+    pub original_body_text: &'a str,
 }
 pub mod creation;
 pub mod removal;
@@ -0,0 +1,137 @@
+use crate::executor::GraphQLExecutor;
+use crate::utility::error_response;
+use crate::utility::execute_with_token;
+use actix_identity::Identity;
+use actix_session::Session;
+use actix_web::{post, web, HttpResponse, Responder};
+use actix_web_flash_messages::FlashMessage;
+use chan_graphql_client::{BanUser, LiftBan, SuspendUser};
+use serde::{Deserialize, Serialize};
+
+#[derive(Serialize, Deserialize)]
+pub struct SuspensionParams {
+    pub user_id: String,
+    pub reason: String,
+    pub duration_hours: i64,
+}
+
+/// Only for admin and moderator
+#[post("/user_suspension")]
+pub async fn user_suspension_handler(
+    session: Session,
+    params: web::Form<SuspensionParams>,
+    executor: web::Data<GraphQLExecutor>,
+    id: Identity,
+) -> impl Responder {
+    assert!(id.identity().is_some()); //protected route
+
+    let variables = chan_graphql_client::suspend_user::Variables {
+        user_id: params.user_id.to_owned(),
+        reason: params.reason.to_owned(),
+        duration_hours: params.duration_hours,
+    };
+
+    let result = execute_with_token::<SuspendUser>(&executor, variables, &id).await;
+
+    if result.is_err() {
+        debug_assert!(result.as_ref().err().is_some());
+        let error = result.as_ref().err().unwrap();
+        return error_response(&session, error);
+    }
+
+    FlashMessage::success(format!(
+        "Suspended {} for {} hours.",
+        params.user_id, params.duration_hours
+    ))
+    .send();
+
+    HttpResponse::Found()
+        .append_header((actix_web::http::header::LOCATION, "/manage"))
+        .finish()
+}
+
+#[derive(Serialize, Deserialize)]
+pub struct BanParams {
+    pub user_id: String,
+    pub reason: String,
+    /// Empty for permanent ban.
+    pub duration_hours: String,
+}
+
+/// Only for admin and moderator
+#[post("/user_ban")]
+pub async fn user_ban_handler(
+    session: Session,
+    params: web::Form<BanParams>,
+    executor: web::Data<GraphQLExecutor>,
+    id: Identity,
+) -> impl Responder {
+    assert!(id.identity().is_some()); //protected route
+
+    let duration_hours = if params.duration_hours.trim().is_empty() {
+        None
+    } else {
+        match params.duration_hours.trim().parse::<i64>() {
+            Ok(h) => Some(h),
+            Err(_) => return HttpResponse::BadRequest().body("Invalid duration."),
+        }
+    };
+
+    let variables = chan_graphql_client::ban_user::Variables {
+        user_id: params.user_id.to_owned(),
+        reason: params.reason.to_owned(),
+        duration_hours: duration_hours,
+    };
+
+    let result = execute_with_token::<BanUser>(&executor, variables, &id).await;
+
+    if result.is_err() {
+        debug_assert!(result.as_ref().err().is_some());
+        let error = result.as_ref().err().unwrap();
+        return error_response(&session, error);
+    }
+
+    match duration_hours {
+        Some(h) => FlashMessage::success(format!("Banned {} for {} hours.", params.user_id, h)),
+        None => FlashMessage::success(format!("Banned {} permanently.", params.user_id)),
+    }
+    .send();
+
+    HttpResponse::Found()
+        .append_header((actix_web::http::header::LOCATION, "/manage"))
+        .finish()
+}
+
+#[derive(Serialize, Deserialize)]
+pub struct BanLiftParams {
+    pub user_id: String,
+}
+
+/// Only for admin and moderator
+#[post("/ban_lift")]
+pub async fn ban_lift_handler(
+    session: Session,
+    params: web::Form<BanLiftParams>,
+    executor: web::Data<GraphQLExecutor>,
+    id: Identity,
+) -> impl Responder {
+    assert!(id.identity().is_some()); //protected route
+
+    let variables = chan_graphql_client::lift_ban::Variables {
+        user_id: params.user_id.to_owned(),
+    };
+
+    let result = execute_with_token::<LiftBan>(&executor, variables, &id).await;
+
+    if result.is_err() {
+        debug_assert!(result.as_ref().err().is_some());
+        let error = result.as_ref().err().unwrap();
+        return error_response(&session, error);
+    }
+
+    FlashMessage::success(format!("Lifted the ban of {}.", params.user_id)).send();
+
+    HttpResponse::Found()
+        .append_header((actix_web::http::header::LOCATION, "/manage"))
+        .finish()
+}
@@ -1,8 +1,9 @@
+use crate::executor::GraphQLExecutor;
+use crate::utility::error_response;
 use crate::utility::extract_theme_from_session;
-use crate::utility::post_graphql_with_token_ex;
+use crate::utility::execute_with_token;
 use actix_identity::{CookieIdentityPolicy, Identity, IdentityService};
 use actix_session::Session;
-use actix_web::dev::ConnectionInfo;
 use actix_web::guard::Connect;
 use actix_web::{get, post, web, App, HttpRequest, HttpResponse, HttpServer, Responder};
 use actix_web_flash_messages::{FlashMessage, IncomingFlashMessages};
@@ -19,13 +20,15 @@ use uuid::Uuid;
 pub struct UserTypeChangeParams {
     pub user_id: String,
    pub new_type: i64,
+    /// Recorded in the moderation audit log.
+    pub reason: String,
 }
 
 #[post("/user_type_change")]
 pub async fn user_type_change_handler(
+    session: Session,
     params: web::Form<UserTypeChangeParams>,
-    reqwest_client: web::Data<Client>,
-    connection_info: ConnectionInfo,
+    executor: web::Data<GraphQLExecutor>,
     id: Identity,
     request: HttpRequest,
 ) -> impl Responder {
@@ -33,27 +36,19 @@ pub async fn user_type_change_handler(
 
     let variables = chan_graphql_client::change_user_type::Variables {
         user_id: params.user_id.to_owned(),
-        user_type: params.new_type
+        new_type: params.new_type,
+        reason: match params.reason.trim() {
+            "" => None,
+            r => Some(r.to_owned()),
+        },
     };
 
-    let graphql_url = format!(
-        "{}://{}/graphql",
-        connection_info.scheme(),
-        connection_info.host()
-    );
-
-    let result = post_graphql_with_token_ex::<ChangeUserType, _>(
-        &reqwest_client,
-        &graphql_url,
-        variables,
-        &id.identity().unwrap(),
-    )
-    .await;
+    let result = execute_with_token::<ChangeUserType>(&executor, variables, &id).await;
 
     if result.is_err() {
         debug_assert!(result.as_ref().err().is_some());
         let error = result.as_ref().err().unwrap();
-        return HttpResponse::InternalServerError().body(error.to_string());
+        return error_response(&session, error);
     }
     let data = result.unwrap();
 
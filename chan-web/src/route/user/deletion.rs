@@ -0,0 +1,79 @@
+use crate::executor::GraphQLExecutor;
+use crate::utility::csrf_token;
+use crate::utility::error_response;
+use crate::utility::execute_with_token;
+use crate::utility::extract_theme_from_session;
+use crate::utility::unread_notifications;
+use actix_identity::Identity;
+use actix_session::Session;
+use actix_web::{get, post, web, HttpResponse, Responder};
+use actix_web_flash_messages::{FlashMessage, IncomingFlashMessages};
+use askama_actix::{Template, TemplateToResponse};
+use chan_graphql_client::DeleteMyAccount;
+use serde::{Deserialize, Serialize};
+use tracing::{event, Level};
+
+#[derive(Template)]
+#[template(path = "user/deletion.html")]
+struct AccountDeletionTemplate {
+    theme: String,
+    csrf_token: String,
+    unread_notifications: i64,
+    flash_messages: IncomingFlashMessages,
+}
+
+#[get("/account_deletion")]
+pub async fn account_deletion(
+    session: Session,
+    flash_messages: IncomingFlashMessages,
+    id: Identity,
+) -> impl Responder {
+    assert!(id.identity().is_some()); //protected route
+
+    AccountDeletionTemplate {
+        theme: extract_theme_from_session(&session),
+        csrf_token: csrf_token(&session),
+        unread_notifications: unread_notifications(&session),
+        flash_messages: flash_messages,
+    }
+    .to_response()
+}
+
+#[derive(Serialize, Deserialize)]
+pub struct AccountDeletionParams {
+    pub password: String,
+}
+
+#[post("/account_deletion")]
+pub async fn account_deletion_handler(
+    session: Session,
+    params: web::Form<AccountDeletionParams>,
+    executor: web::Data<GraphQLExecutor>,
+    id: Identity,
+) -> impl Responder {
+    assert!(id.identity().is_some()); //protected route
+
+    let variables = chan_graphql_client::delete_my_account::Variables {
+        password: params.password.to_owned(),
+    };
+
+    let result = execute_with_token::<DeleteMyAccount>(&executor, variables, &id).await;
+
+    if result.is_err() {
+        debug_assert!(result.as_ref().err().is_some());
+        let error = result.as_ref().err().unwrap();
+        return error_response(&session, error);
+    }
+
+    event!(Level::DEBUG, "Deleted account");
+
+    //the token is no longer valid
+    id.forget();
+
+    FlashMessage::success("Your account has been deleted.").send();
+
+    //redirect
+    HttpResponse::Found()
+        .append_header((actix_web::http::header::LOCATION, "/"))
+        .finish()
+}
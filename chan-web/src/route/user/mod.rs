@@ -4,6 +4,19 @@ pub struct UserInfo<'a> {
     user_type: &'a str,
     user_status: &'a str,
 }
+/// Active session of the user(only visible to the user and admin)
+pub struct SessionInfo {
+    token_uuid: uuid::Uuid,
+    issued_at: chrono::DateTime<chrono::Utc>,
+    expires_at: chrono::DateTime<chrono::Utc>,
+    last_used_at: String,
+    user_agent: String,
+    ip_address: String,
+}
 //pub mod change;
+pub mod ban;
+pub mod deletion;
+pub mod password;
+pub mod session;
 pub mod view;
 pub mod change;
\ No newline at end of file
@@ -0,0 +1,178 @@
+use crate::executor::GraphQLExecutor;
+use crate::utility::csrf_token;
+use crate::utility::error_response;
+use crate::utility::extract_theme_from_session;
+use crate::utility::unread_notifications;
+use crate::utility::{execute, execute_with_token};
+use actix_identity::Identity;
+use actix_session::Session;
+use actix_web::{get, post, web, HttpResponse, Responder};
+use actix_web_flash_messages::{FlashMessage, IncomingFlashMessages};
+use askama_actix::{Template, TemplateToResponse};
+use chan_graphql_client::{ChangePassword, IssuePasswordResetToken, ResetPassword};
+use serde::{Deserialize, Serialize};
+use tracing::{event, Level};
+
+#[derive(Template)]
+#[template(path = "user/password_change.html")]
+struct PasswordChangeTemplate {
+    theme: String,
+    csrf_token: String,
+    unread_notifications: i64,
+    flash_messages: IncomingFlashMessages,
+}
+
+#[get("/password_change")]
+pub async fn password_change(
+    session: Session,
+    flash_messages: IncomingFlashMessages,
+    id: Identity,
+) -> impl Responder {
+    assert!(id.identity().is_some()); //protected route
+
+    PasswordChangeTemplate {
+        theme: extract_theme_from_session(&session),
+        csrf_token: csrf_token(&session),
+        unread_notifications: unread_notifications(&session),
+        flash_messages: flash_messages,
+    }
+    .to_response()
+}
+
+#[derive(Serialize, Deserialize)]
+pub struct PasswordChangeParams {
+    pub old_password: String,
+    pub new_password: String,
+}
+
+#[post("/password_change")]
+pub async fn password_change_handler(
+    session: Session,
+    params: web::Form<PasswordChangeParams>,
+    executor: web::Data<GraphQLExecutor>,
+    id: Identity,
+) -> impl Responder {
+    assert!(id.identity().is_some()); //protected route
+
+    let variables = chan_graphql_client::change_password::Variables {
+        old_password: params.old_password.to_owned(),
+        new_password: params.new_password.to_owned(),
+    };
+
+    let result = execute_with_token::<ChangePassword>(&executor, variables, &id).await;
+
+    if result.is_err() {
+        debug_assert!(result.as_ref().err().is_some());
+        let error = result.as_ref().err().unwrap();
+        return error_response(&session, error);
+    }
+
+    event!(Level::DEBUG, "Changed password");
+
+    //all tokens are revoked, login again
+    id.forget();
+
+    FlashMessage::success("Changed the password. Please login again.").send();
+
+    //redirect to login page
+    HttpResponse::Found()
+        .append_header((actix_web::http::header::LOCATION, "/login"))
+        .finish()
+}
+
+#[derive(Template)]
+#[template(path = "user/password_reset.html")]
+struct PasswordResetTemplate {
+    theme: String,
+    csrf_token: String,
+    unread_notifications: i64,
+    flash_messages: IncomingFlashMessages,
+}
+
+#[get("/password_reset")]
+pub async fn password_reset(
+    session: Session,
+    flash_messages: IncomingFlashMessages,
+) -> impl Responder {
+    PasswordResetTemplate {
+        theme: extract_theme_from_session(&session),
+        csrf_token: csrf_token(&session),
+        unread_notifications: unread_notifications(&session),
+        flash_messages: flash_messages,
+    }
+    .to_response()
+}
+
+#[derive(Serialize, Deserialize)]
+pub struct PasswordResetParams {
+    pub user_id: String,
+    pub reset_token: String,
+    pub new_password: String,
+}
+
+#[post("/password_reset")]
+pub async fn password_reset_handler(
+    session: Session,
+    params: web::Form<PasswordResetParams>,
+    executor: web::Data<GraphQLExecutor>,
+) -> impl Responder {
+    let variables = chan_graphql_client::reset_password::Variables {
+        user_id: params.user_id.to_owned(),
+        reset_token: params.reset_token.to_owned(),
+        new_password: params.new_password.to_owned(),
+    };
+
+    let result = execute::<ResetPassword>(&executor, variables).await;
+
+    if result.is_err() {
+        debug_assert!(result.as_ref().err().is_some());
+        let error = result.as_ref().err().unwrap();
+        return error_response(&session, error);
+    }
+
+    FlashMessage::success("Reset the password. Please login with the new password.").send();
+
+    //redirect to login page
+    HttpResponse::Found()
+        .append_header((actix_web::http::header::LOCATION, "/login"))
+        .finish()
+}
+
+#[derive(Serialize, Deserialize)]
+pub struct PasswordResetIssueParams {
+    pub user_id: String,
+}
+
+/// Only for admin
+#[post("/password_reset_issue")]
+pub async fn password_reset_issue_handler(
+    session: Session,
+    params: web::Form<PasswordResetIssueParams>,
+    executor: web::Data<GraphQLExecutor>,
+    id: Identity,
+) -> impl Responder {
+    assert!(id.identity().is_some()); //protected route
+
+    let variables = chan_graphql_client::issue_password_reset_token::Variables {
+        user_id: params.user_id.to_owned(),
+    };
+
+    let result = execute_with_token::<IssuePasswordResetToken>(&executor, variables, &id).await;
+
+    if result.is_err() {
+        debug_assert!(result.as_ref().err().is_some());
+        let error = result.as_ref().err().unwrap();
+        return error_response(&session, error);
+    }
+    let data = result.unwrap();
+
+    FlashMessage::info(format!(
+        "Reset token for {}: {} (expires after 24 hours)",
+        params.user_id, data.issue_password_reset_token
+    ))
+    .send();
+
+    HttpResponse::Found()
+        .append_header((actix_web::http::header::LOCATION, "/manage"))
+        .finish()
+}
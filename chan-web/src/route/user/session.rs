@@ -0,0 +1,49 @@
+use crate::executor::GraphQLExecutor;
+use crate::utility::error_response;
+use crate::utility::execute_with_token;
+use actix_identity::Identity;
+use actix_session::Session;
+use actix_web::{post, web, HttpResponse, Responder};
+use actix_web_flash_messages::FlashMessage;
+use chan_graphql_client::RevokeSession;
+use serde::{Deserialize, Serialize};
+use tracing::{event, Level};
+use uuid::Uuid;
+
+#[derive(Serialize, Deserialize)]
+pub struct SessionRevocationParams {
+    pub user_id: String,
+    pub token_uuid: Uuid,
+}
+
+/// Only for the user and admin
+#[post("/session_revocation")]
+pub async fn session_revocation_handler(
+    session: Session,
+    params: web::Form<SessionRevocationParams>,
+    executor: web::Data<GraphQLExecutor>,
+    id: Identity,
+) -> impl Responder {
+    assert!(id.identity().is_some()); //protected route
+
+    let variables = chan_graphql_client::revoke_session::Variables {
+        user_id: params.user_id.to_owned(),
+        token_uuid: params.token_uuid,
+    };
+
+    let result = execute_with_token::<RevokeSession>(&executor, variables, &id).await;
+
+    if result.is_err() {
+        debug_assert!(result.as_ref().err().is_some());
+        let error = result.as_ref().err().unwrap();
+        return error_response(&session, error);
+    }
+
+    event!(Level::DEBUG, "Revoked session {}", params.token_uuid);
+    FlashMessage::success(format!("Revoked session {}", params.token_uuid)).send();
+
+    let redirect_url = format!("/user/{}", params.user_id);
+    HttpResponse::Found()
+        .append_header((actix_web::http::header::LOCATION, redirect_url))
+        .finish()
+}
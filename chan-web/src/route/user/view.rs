@@ -1,37 +1,45 @@
+use crate::executor::GraphQLExecutor;
 use crate::routes::thread::ThreadInfo;
 use crate::routes::threadpost::ThreadPostInfo;
+use crate::utility::csrf_token;
+use crate::utility::error_page;
+use crate::utility::error_response;
+use crate::utility::execute_with_token;
 use crate::utility::extract_theme_from_session;
-use crate::utility::post_graphql_with_token_ex;
+use crate::utility::unread_notifications;
 use actix_identity::{CookieIdentityPolicy, Identity, IdentityService};
 use actix_session::Session;
-use actix_web::dev::ConnectionInfo;
 use actix_web::guard::Connect;
+use actix_web::http::StatusCode;
 use actix_web::{get, post, web, App, HttpRequest, HttpResponse, HttpServer, Responder};
 use actix_web_flash_messages::{FlashMessage, IncomingFlashMessages};
 use askama_actix::{Template, TemplateToResponse};
 use chan_core::model::{UserStatus, UserType};
-use chan_graphql_client::{GraphQLQuery, Response, ThreadPostsRange, UserById};
+use chan_graphql_client::{GraphQLQuery, Response, Sessions, ThreadPostsRange, UserById};
 use reqwest::{Client, RequestBuilder};
 use serde::{Deserialize, Serialize};
 use std::error::Error;
 use tracing::{event, Id, Level};
 use uuid::Uuid;
 
-use super::UserInfo;
+use super::{SessionInfo, UserInfo};
 
 #[derive(Template)]
 #[template(path = "user/view.html")]
 struct UserViewTemplate<'a> {
     theme: String,
+    csrf_token: String,
+    unread_notifications: i64,
     flash_messages: IncomingFlashMessages,
     user_info: UserInfo<'a>,
+    show_sessions: bool,
+    sessions: Vec<SessionInfo>,
 }
 
 #[get("/user/{user_id}")]
 pub async fn user_view(
     session: Session,
-    reqwest_client: web::Data<Client>,
-    connection_info: ConnectionInfo,
+    executor: web::Data<GraphQLExecutor>,
     flash_messages: IncomingFlashMessages,
     user_id: web::Path<String>,
     id: Identity,
@@ -40,27 +48,15 @@ pub async fn user_view(
 
     assert!(id.identity().is_some()); //protected route
 
-    let graphql_url = format!(
-        "{}://{}/graphql",
-        connection_info.scheme(),
-        connection_info.host()
-    );
-
     let variables = chan_graphql_client::user_by_id::Variables {
         user_id: user_id.to_owned(),
     };
 
-    let result = post_graphql_with_token_ex::<UserById, _>(
-        &reqwest_client,
-        &graphql_url,
-        variables,
-        &id.identity().unwrap(),
-    )
-    .await;
+    let result = execute_with_token::<UserById>(&executor, variables, &id).await;
     if result.is_err() {
         debug_assert!(result.as_ref().err().is_some());
         let error = result.as_ref().err().unwrap();
-        return HttpResponse::InternalServerError().body(error.to_string());
+        return error_response(&session, error);
     }
     let data = result.unwrap();
     assert!(data.user.is_some());
@@ -68,18 +64,43 @@ pub async fn user_view(
     let user_status = match UserStatus::from_i32(user.user_status.try_into().unwrap()) {
         Ok(s) => s,
         Err(e) => {
-            return HttpResponse::InternalServerError().body(e.to_string());
+            return error_page(&session, StatusCode::INTERNAL_SERVER_ERROR, &e.to_string());
         }
     };
 
     let user_type = match UserType::from_i32(user.user_type.try_into().unwrap()) {
         Ok(t) => t,
         Err(e) => {
-            return HttpResponse::InternalServerError().body(e.to_string());
+            return error_page(&session, StatusCode::INTERNAL_SERVER_ERROR, &e.to_string());
         }
     };
+    //get sessions, only the user and admin are allowed to see
+    let variables = chan_graphql_client::sessions::Variables {
+        user_id: user.id.to_owned(),
+    };
+    let result = execute_with_token::<Sessions>(&executor, variables, &id).await;
+    let show_sessions = result.is_ok();
+    let mut sessions = Vec::new();
+    if let Ok(data) = result {
+        for s in data.sessions {
+            sessions.push(SessionInfo {
+                token_uuid: s.token_uuid,
+                issued_at: s.issued_at,
+                expires_at: s.expires_at,
+                last_used_at: match s.last_used_at {
+                    Some(t) => t.to_string(),
+                    None => "Never".to_string(),
+                },
+                user_agent: s.user_agent.unwrap_or_default(),
+                ip_address: s.ip_address.unwrap_or_default(),
+            });
+        }
+    }
+
     UserViewTemplate {
         theme: extract_theme_from_session(&session),
+        csrf_token: csrf_token(&session),
+        unread_notifications: unread_notifications(&session),
         flash_messages: flash_messages,
         user_info: UserInfo {
             registered_at: &user.registered_at,
@@ -97,6 +118,8 @@ pub async fn user_view(
             },
             id: &user.id,
         },
+        show_sessions: show_sessions,
+        sessions: sessions,
     }
     .to_response()
 }
@@ -0,0 +1,140 @@
+use crate::executor::GraphQLExecutor;
+use crate::utility::csrf_token;
+use crate::utility::error_response;
+use crate::utility::execute_with_token;
+use crate::utility::extract_theme_from_session;
+use crate::utility::unread_notifications;
+use actix_identity::Identity;
+use actix_session::Session;
+use actix_web::{get, post, web, HttpResponse, Responder};
+use actix_web_flash_messages::{FlashMessage, IncomingFlashMessages};
+use askama_actix::{Template, TemplateToResponse};
+use chan_graphql_client::{UnwatchThread, WatchThread, WatchedThreads};
+use serde::{Deserialize, Serialize};
+use uuid::Uuid;
+
+struct WatchedThreadInfo {
+    uuid: Uuid,
+    title: String,
+    /// First threadpost number to show, the one after the last read.
+    first_unread_number: i64,
+    new_post_count: i64,
+}
+
+#[derive(Template)]
+#[template(path = "watched.html")]
+struct WatchedTemplate {
+    theme: String,
+    csrf_token: String,
+    unread_notifications: i64,
+    flash_messages: IncomingFlashMessages,
+    threads: Vec<WatchedThreadInfo>,
+}
+
+#[get("/watched")]
+pub async fn watched(
+    session: Session,
+    executor: web::Data<GraphQLExecutor>,
+    flash_messages: IncomingFlashMessages,
+    id: Identity,
+) -> impl Responder {
+    assert!(id.identity().is_some()); //protected route
+
+    let result = execute_with_token::<WatchedThreads>(
+        &executor,
+        chan_graphql_client::watched_threads::Variables {},
+        &id,
+    )
+    .await;
+
+    if result.is_err() {
+        debug_assert!(result.as_ref().err().is_some());
+        let error = result.as_ref().err().unwrap();
+        return error_response(&session, error);
+    }
+    let data = result.unwrap();
+
+    let threads = data
+        .watched_threads
+        .into_iter()
+        .map(|watch| WatchedThreadInfo {
+            uuid: watch.thread_id,
+            title: watch.thread.map(|t| t.title).unwrap_or_default(),
+            first_unread_number: watch.last_read_number + 1,
+            new_post_count: watch.new_post_count,
+        })
+        .collect();
+
+    WatchedTemplate {
+        theme: extract_theme_from_session(&session),
+        csrf_token: csrf_token(&session),
+        unread_notifications: unread_notifications(&session),
+        flash_messages: flash_messages,
+        threads: threads,
+    }
+    .to_response()
+}
+
+#[derive(Serialize, Deserialize)]
+pub struct ThreadWatchFormParams {
+    pub thread_uuid: Uuid,
+}
+
+#[post("/watch")]
+pub async fn thread_watch_handler(
+    session: Session,
+    executor: web::Data<GraphQLExecutor>,
+    form: web::Form<ThreadWatchFormParams>,
+    id: Identity,
+) -> impl Responder {
+    assert!(id.identity().is_some()); //protected route
+
+    let variables = chan_graphql_client::watch_thread::Variables {
+        thread_id: form.thread_uuid,
+    };
+    let result = execute_with_token::<WatchThread>(&executor, variables, &id).await;
+
+    if result.is_err() {
+        debug_assert!(result.as_ref().err().is_some());
+        let error = result.as_ref().err().unwrap();
+        return error_response(&session, error);
+    }
+
+    FlashMessage::success("Watching the thread.").send();
+
+    //redirect
+    HttpResponse::Found()
+        .append_header((
+            actix_web::http::header::LOCATION,
+            format!("/thread/{}", form.thread_uuid),
+        ))
+        .finish()
+}
+
+#[post("/unwatch")]
+pub async fn thread_unwatch_handler(
+    session: Session,
+    executor: web::Data<GraphQLExecutor>,
+    form: web::Form<ThreadWatchFormParams>,
+    id: Identity,
+) -> impl Responder {
+    assert!(id.identity().is_some()); //protected route
+
+    let variables = chan_graphql_client::unwatch_thread::Variables {
+        thread_id: form.thread_uuid,
+    };
+    let result = execute_with_token::<UnwatchThread>(&executor, variables, &id).await;
+
+    if result.is_err() {
+        debug_assert!(result.as_ref().err().is_some());
+        let error = result.as_ref().err().unwrap();
+        return error_response(&session, error);
+    }
+
+    FlashMessage::success("Stopped watching the thread.").send();
+
+    //redirect
+    HttpResponse::Found()
+        .append_header((actix_web::http::header::LOCATION, "/watched"))
+        .finish()
+}
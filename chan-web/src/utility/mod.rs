@@ -1,8 +1,139 @@
-use crate::constant::THEME_SESSION_KEY;
+use crate::constant::{
+    CSRF_TOKEN_SESSION_KEY, THEME_SESSION_KEY, UNREAD_NOTIFICATIONS_SESSION_KEY,
+};
+use crate::executor::GraphQLExecutor;
+use actix_identity::Identity;
 use actix_session::Session;
-use actix_web::HttpResponse;
-use chan_graphql_client::GraphQLQuery;
+use actix_web::http::header::{HeaderValue, RETRY_AFTER, USER_AGENT};
+use actix_web::http::StatusCode;
+use actix_web::{HttpRequest, HttpResponse};
+use actix_web_flash_messages::FlashMessage;
+use anyhow::anyhow;
+use askama_actix::{Template, TemplateToResponse};
+use chan_core::error::ErrorCode;
+use chan_core::handler::client_ip_address;
+use chan_core::model::ClientInfo;
+use chan_graphql_client::{GraphQLQuery, IssueCaptcha, RefreshToken};
 use graphql_client::Response;
+use serde::{Deserialize, Serialize};
+use tracing::{event, Level};
+use uuid::Uuid;
+
+#[derive(Template)]
+#[template(path = "error.html")]
+struct ErrorTemplate {
+    theme: String,
+    csrf_token: String,
+    unread_notifications: i64,
+    flash_messages: Vec<FlashMessage>,
+    status: StatusCode,
+    message: String,
+}
+
+/// Themed error page.
+pub fn error_page(session: &Session, status: StatusCode, message: &str) -> HttpResponse {
+    let mut response = ErrorTemplate {
+        theme: extract_theme_from_session(session),
+        csrf_token: csrf_token(session),
+        unread_notifications: unread_notifications(session),
+        flash_messages: Vec::new(),
+        status: status,
+        message: message.to_owned(),
+    }
+    .to_response();
+    *response.status_mut() = status;
+    response
+}
+
+/// Themed error page for errors of GraphQL requests.
+/// The status is chosen by the error code, errors without a code are internal errors.
+pub fn error_response(session: &Session, error: &anyhow::Error) -> HttpResponse {
+    let response_error = error.downcast_ref::<GraphQLResponseError>();
+    let code = response_error.and_then(|e| e.code);
+    let status = match code {
+        Some(ErrorCode::NotFound) => StatusCode::NOT_FOUND,
+        Some(ErrorCode::Forbidden) => StatusCode::FORBIDDEN,
+        Some(ErrorCode::Unauthenticated) => StatusCode::UNAUTHORIZED,
+        Some(ErrorCode::Validation) => StatusCode::BAD_REQUEST,
+        Some(ErrorCode::RateLimited) => StatusCode::TOO_MANY_REQUESTS,
+        Some(ErrorCode::HeldForReview) => StatusCode::ACCEPTED,
+        None => StatusCode::INTERNAL_SERVER_ERROR,
+    };
+    let mut response = error_page(session, status, &error.to_string());
+    if let Some(retry_after) = response_error.and_then(|e| e.retry_after) {
+        response
+            .headers_mut()
+            .insert(RETRY_AFTER, HeaderValue::from(retry_after));
+    }
+    response
+}
+
+/// CSRF token of the session, embedded in every form.
+/// Created on first use, so the token stays the same for the session.
+pub fn csrf_token(session: &Session) -> String {
+    if let Ok(Some(token)) = session.get::<String>(CSRF_TOKEN_SESSION_KEY) {
+        return token;
+    }
+    let token = Uuid::new_v4().to_simple().to_string();
+    if let Err(e) = session.insert(CSRF_TOKEN_SESSION_KEY, &token) {
+        event!(Level::ERROR, "Failed to save the CSRF token: {}", e);
+    }
+    token
+}
+
+/// Number of unread notifications for the badge, refreshed by the NotificationBadge middleware.
+pub fn unread_notifications(session: &Session) -> i64 {
+    session
+        .get::<i64>(UNREAD_NOTIFICATIONS_SESSION_KEY)
+        .ok()
+        .flatten()
+        .unwrap_or(0)
+}
+
+/// CAPTCHA shown on a form.
+/// All are empty if the form doesn't require a CAPTCHA.
+#[derive(Default)]
+pub struct CaptchaChallenge {
+    pub uuid: String,
+    pub question: String,
+    /// data URL of the image, empty for challenges without one.
+    pub image: String,
+}
+
+/// Issue a challenge for the form, or an empty one if not required.
+/// Issued as the client, challenges are limited per client IP address.
+pub async fn captcha_challenge(
+    executor: &GraphQLExecutor,
+    required: bool,
+    request: &HttpRequest,
+) -> anyhow::Result<CaptchaChallenge> {
+    if !required {
+        return Ok(CaptchaChallenge::default());
+    }
+    let variables = chan_graphql_client::issue_captcha::Variables;
+    let data = execute_as_client::<IssueCaptcha>(executor, variables, request).await?;
+    Ok(CaptchaChallenge {
+        uuid: data.issue_captcha.uuid.to_string(),
+        question: data.issue_captcha.question,
+        image: data.issue_captcha.image.unwrap_or_default(),
+    })
+}
+
+/// uuid and answer of the CAPTCHA fields of a form, None if the form had no CAPTCHA.
+pub fn captcha_form_answer(
+    captcha_uuid: &Option<String>,
+    captcha_answer: &Option<String>,
+) -> Result<Option<(Uuid, String)>, uuid::Error> {
+    use std::str::FromStr;
+
+    match (captcha_uuid, captcha_answer) {
+        (Some(captcha_uuid), Some(captcha_answer)) => Ok(Some((
+            Uuid::from_str(captcha_uuid)?,
+            captcha_answer.to_owned(),
+        ))),
+        _ => Ok(None),
+    }
+}
 
 pub fn extract_theme_from_session(session: &Session) -> String {
     let result = session.get::<String>(THEME_SESSION_KEY);
@@ -16,46 +147,175 @@ pub fn extract_theme_from_session(session: &Session) -> String {
     opt.unwrap()
 }
 
-pub async fn post_graphql_with_token_ex<Q: GraphQLQuery, U: reqwest::IntoUrl>(
-    client: &reqwest::Client,
-    url: U,
+/// Tokens saved in the identity cookie.
+#[derive(Serialize, Deserialize)]
+pub struct AuthIdentity {
+    pub access_token: String,
+    pub refresh_token: Option<String>,
+}
+
+impl AuthIdentity {
+    pub fn from_identity(id: &Identity) -> Option<Self> {
+        let identity = id.identity()?;
+        match serde_json::from_str::<AuthIdentity>(&identity) {
+            Ok(auth) => Some(auth),
+            //identity saved before refresh tokens, it's a bare access token
+            Err(_) => Some(AuthIdentity {
+                access_token: identity,
+                refresh_token: None,
+            }),
+        }
+    }
+
+    /// Save tokens to the identity cookie.
+    pub fn remember(&self, id: &Identity) {
+        id.remember(serde_json::to_string(self).unwrap());
+    }
+}
+
+/// Run the operation with the access token of the user.
+/// If the access token is rejected, refresh it transparently and retry once.
+pub async fn execute_with_token<Q: GraphQLQuery>(
+    executor: &GraphQLExecutor,
     variables: Q::Variables,
-    token_string: &str,
+    id: &Identity,
 ) -> anyhow::Result<Q::ResponseData> {
-    use anyhow::anyhow;
-    let response = match post_graphql_with_token::<Q, _>(client, url, variables, token_string).await
-    {
-        Ok(response) => response,
-        Err(e) => {
-            return Err(anyhow!(format!("GraphQL returned an error: {:?}", e)));
-        }
+    let auth = match AuthIdentity::from_identity(id) {
+        Some(auth) => auth,
+        None => return Err(anyhow!("Not logged in.")),
     };
+    let body = Q::build_query(variables);
 
-    if let Some(errors) = response.errors {
-        let mut error_str = String::from("GraphQL response errors\n");
-        for error in &errors {
-            error_str.push_str(&format!("{}\n", error));
+    let mut response = executor
+        .execute::<Q>(&body, Some(&auth.access_token), None)
+        .await?;
+
+    if is_token_rejected(&response) {
+        if let Some(refresh_token) = &auth.refresh_token {
+            match refresh_auth_identity(executor, refresh_token).await {
+                Ok(new_auth) => {
+                    event!(Level::DEBUG, "Refreshed the access token");
+                    new_auth.remember(id);
+                    response = executor
+                        .execute::<Q>(&body, Some(&new_auth.access_token), None)
+                        .await?;
+                }
+                Err(e) => {
+                    event!(Level::DEBUG, "Failed to refresh the access token: {}", e);
+                }
+            }
         }
-        return Err(anyhow!(error_str));
     }
 
-    assert!(response.data.is_some());
-    Ok(response.data.unwrap())
+    response_to_data::<Q>(response)
+}
+
+/// Run the operation without token.(e.g. registration)
+pub async fn execute<Q: GraphQLQuery>(
+    executor: &GraphQLExecutor,
+    variables: Q::Variables,
+) -> anyhow::Result<Q::ResponseData> {
+    let body = Q::build_query(variables);
+    let response = executor.execute::<Q>(&body, None, None).await?;
+    response_to_data::<Q>(response)
 }
 
-async fn post_graphql_with_token<Q: GraphQLQuery, U: reqwest::IntoUrl>(
-    client: &reqwest::Client,
-    url: U,
+/// Same as execute, but as User-Agent and IP address of the user.
+/// Without this, the GraphQL server sees chan-web itself as the client.(e.g. sessions made by login)
+pub async fn execute_as_client<Q: GraphQLQuery>(
+    executor: &GraphQLExecutor,
     variables: Q::Variables,
-    token_string: &str,
-) -> Result<Response<Q::ResponseData>, reqwest::Error> {
+    request: &HttpRequest,
+) -> anyhow::Result<Q::ResponseData> {
+    let client_info = ClientInfo {
+        user_agent: request
+            .headers()
+            .get(USER_AGENT)
+            .and_then(|value| value.to_str().ok())
+            .map(|s| s.to_string()),
+        ip_address: client_ip_address(request),
+    };
     let body = Q::build_query(variables);
-    let reqwest_response = client
-        .post(url)
-        .header("Token", token_string)
-        .json(&body)
-        .send()
+    let response = executor
+        .execute::<Q>(&body, None, Some(&client_info))
         .await?;
+    response_to_data::<Q>(response)
+}
+
+/// Exchange the refresh token for new tokens.
+async fn refresh_auth_identity(
+    executor: &GraphQLExecutor,
+    refresh_token: &str,
+) -> anyhow::Result<AuthIdentity> {
+    let variables = chan_graphql_client::refresh_token::Variables {
+        refresh_token: refresh_token.to_owned(),
+    };
+    let data = execute::<RefreshToken>(executor, variables).await?;
+    Ok(AuthIdentity {
+        access_token: data.refresh_token.access_token,
+        refresh_token: Some(data.refresh_token.refresh_token),
+    })
+}
+
+/// The access token was rejected.(e.g. expired)
+/// Refreshing is worth a try for any rejection, a revoked session can't be refreshed anyway.
+fn is_token_rejected<T>(response: &Response<T>) -> bool {
+    match &response.errors {
+        Some(errors) => errors
+            .iter()
+            .any(|e| error_code(e) == Some(ErrorCode::Unauthenticated)),
+        None => false,
+    }
+}
 
-    Ok(reqwest_response.json().await?)
+/// Errors returned by the GraphQL server.
+/// code is taken from extensions.code of the errors, None for internal errors.
+#[derive(Debug)]
+pub struct GraphQLResponseError {
+    pub code: Option<ErrorCode>,
+    pub message: String,
+    /// Seconds until rate limited operations are allowed again.
+    pub retry_after: Option<u64>,
+}
+
+impl std::fmt::Display for GraphQLResponseError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{}", self.message)
+    }
+}
+
+impl std::error::Error for GraphQLResponseError {}
+
+fn error_code(error: &graphql_client::Error) -> Option<ErrorCode> {
+    error
+        .extensions
+        .as_ref()?
+        .get("code")?
+        .as_str()
+        .and_then(ErrorCode::from_str)
+}
+
+fn retry_after(error: &graphql_client::Error) -> Option<u64> {
+    error.extensions.as_ref()?.get("retryAfter")?.as_u64()
+}
+
+fn response_to_data<Q: GraphQLQuery>(
+    response: Response<Q::ResponseData>,
+) -> anyhow::Result<Q::ResponseData> {
+    if let Some(errors) = response.errors {
+        let message = errors
+            .iter()
+            .map(|e| e.message.to_owned())
+            .collect::<Vec<_>>()
+            .join("\n");
+        return Err(GraphQLResponseError {
+            code: errors.iter().find_map(error_code),
+            message: message,
+            retry_after: errors.iter().find_map(retry_after),
+        }
+        .into());
+    }
+
+    assert!(response.data.is_some());
+    Ok(response.data.unwrap())
 }
@@ -0,0 +1,5 @@
+-- Your SQL goes here
+-- Placeholder account which owns contents of removed accounts.
+-- Generated user ids are always 16 characters, so this id never collides.
+INSERT INTO users (id, registered_at, argon2_password, user_type, user_status)
+VALUES ('anonymous', NOW(), '', 3, 4);
@@ -0,0 +1,3 @@
+-- Your SQL goes here
+-- Tokens issued before this time are rejected.(e.g. after password change)
+ALTER TABLE users ADD COLUMN tokens_valid_after TIMESTAMPTZ;
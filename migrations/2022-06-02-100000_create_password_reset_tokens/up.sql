@@ -0,0 +1,14 @@
+-- Your SQL goes here
+-- One-time password reset tokens issued by admins.
+-- Only sha256 of the token is stored.
+CREATE TABLE password_reset_tokens(
+primary_key SERIAL PRIMARY KEY,
+token_hash TEXT UNIQUE NOT NULL,
+user_id TEXT NOT NULL,
+issuer_user_id TEXT NOT NULL,
+issued_at TIMESTAMPTZ NOT NULL,
+expires_at TIMESTAMPTZ NOT NULL,
+used_at TIMESTAMPTZ,
+CONSTRAINT fk_user_id FOREIGN KEY(user_id) REFERENCES users(id),
+CONSTRAINT fk_issuer_user_id FOREIGN KEY(issuer_user_id) REFERENCES users(id)
+)
@@ -0,0 +1,14 @@
+-- Your SQL goes here
+-- Tokens issued by login.
+-- A token is valid only while its record exists and is not revoked.
+CREATE TABLE issued_tokens(
+primary_key SERIAL PRIMARY KEY,
+token_uuid UUID UNIQUE NOT NULL,
+user_id TEXT NOT NULL,
+issued_at TIMESTAMPTZ NOT NULL,
+expires_at TIMESTAMPTZ NOT NULL,
+revoked_at TIMESTAMPTZ,
+CONSTRAINT fk_user_id FOREIGN KEY(user_id) REFERENCES users(id)
+);
+
+CREATE INDEX issued_tokens_user_id_index ON issued_tokens(user_id);
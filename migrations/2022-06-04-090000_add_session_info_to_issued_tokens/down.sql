@@ -0,0 +1,4 @@
+-- This file should undo anything in `up.sql`
+ALTER TABLE issued_tokens DROP COLUMN last_used_at;
+ALTER TABLE issued_tokens DROP COLUMN user_agent;
+ALTER TABLE issued_tokens DROP COLUMN ip_address;
@@ -0,0 +1,5 @@
+-- Your SQL goes here
+-- Session information captured at login.
+ALTER TABLE issued_tokens ADD COLUMN last_used_at TIMESTAMPTZ;
+ALTER TABLE issued_tokens ADD COLUMN user_agent TEXT;
+ALTER TABLE issued_tokens ADD COLUMN ip_address TEXT;
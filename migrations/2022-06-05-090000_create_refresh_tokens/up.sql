@@ -0,0 +1,18 @@
+-- Your SQL goes here
+-- Long-lived refresh tokens.
+-- Each refresh token can be used once, and is replaced by a new one.(rotation)
+-- Only sha256 of the token is stored.
+CREATE TABLE refresh_tokens(
+primary_key SERIAL PRIMARY KEY,
+token_hash TEXT UNIQUE NOT NULL,
+user_id TEXT NOT NULL,
+access_token_uuid UUID NOT NULL,
+issued_at TIMESTAMPTZ NOT NULL,
+expires_at TIMESTAMPTZ NOT NULL,
+used_at TIMESTAMPTZ,
+revoked_at TIMESTAMPTZ,
+CONSTRAINT fk_user_id FOREIGN KEY(user_id) REFERENCES users(id),
+CONSTRAINT fk_access_token_uuid FOREIGN KEY(access_token_uuid) REFERENCES issued_tokens(token_uuid)
+);
+
+CREATE INDEX refresh_tokens_user_id_index ON refresh_tokens(user_id);
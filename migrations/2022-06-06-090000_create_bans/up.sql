@@ -0,0 +1,20 @@
+-- Your SQL goes here
+-- Suspensions and bans issued by moderators.
+-- ends_at is NULL for permanent bans.
+CREATE TABLE bans(
+primary_key SERIAL PRIMARY KEY,
+uuid UUID UNIQUE NOT NULL,
+user_id TEXT NOT NULL,
+issuer_user_id TEXT NOT NULL,
+user_status INTEGER NOT NULL,
+reason TEXT NOT NULL,
+starts_at TIMESTAMPTZ NOT NULL,
+ends_at TIMESTAMPTZ,
+lifted_at TIMESTAMPTZ,
+lifter_user_id TEXT,
+CONSTRAINT fk_user_id FOREIGN KEY(user_id) REFERENCES users(id),
+CONSTRAINT fk_issuer_user_id FOREIGN KEY(issuer_user_id) REFERENCES users(id),
+CONSTRAINT fk_lifter_user_id FOREIGN KEY(lifter_user_id) REFERENCES users(id)
+);
+
+CREATE INDEX bans_user_id_index ON bans(user_id);
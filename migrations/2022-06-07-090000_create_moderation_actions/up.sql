@@ -0,0 +1,20 @@
+-- Your SQL goes here
+-- Audit log of moderator actions, only visible to admins.
+-- Append-only, rows are never updated or deleted.
+CREATE TABLE moderation_actions(
+primary_key SERIAL PRIMARY KEY,
+uuid UUID UNIQUE NOT NULL,
+actor_user_id TEXT NOT NULL,
+action_type INTEGER NOT NULL,
+target TEXT NOT NULL,
+reason TEXT,
+content_snapshot TEXT,
+created_at TIMESTAMPTZ NOT NULL,
+CONSTRAINT fk_actor_user_id FOREIGN KEY(actor_user_id) REFERENCES users(id)
+);
+
+CREATE INDEX moderation_actions_actor_user_id_index ON moderation_actions(actor_user_id);
+CREATE INDEX moderation_actions_target_index ON moderation_actions(target);
+
+CREATE RULE moderation_actions_no_update AS ON UPDATE TO moderation_actions DO INSTEAD NOTHING;
+CREATE RULE moderation_actions_no_delete AS ON DELETE TO moderation_actions DO INSTEAD NOTHING;
@@ -0,0 +1,13 @@
+-- Your SQL goes here
+-- Moderators assigned to a board by admins.
+CREATE TABLE board_moderators(
+primary_key SERIAL PRIMARY KEY,
+board_id UUID NOT NULL,
+user_id TEXT NOT NULL,
+assigner_user_id TEXT NOT NULL,
+assigned_at TIMESTAMPTZ NOT NULL,
+CONSTRAINT fk_board_id FOREIGN KEY(board_id) REFERENCES boards(uuid),
+CONSTRAINT fk_user_id FOREIGN KEY(user_id) REFERENCES users(id),
+CONSTRAINT fk_assigner_user_id FOREIGN KEY(assigner_user_id) REFERENCES users(id),
+UNIQUE(board_id,user_id)
+);
@@ -0,0 +1,13 @@
+-- Your SQL goes here
+-- CAPTCHA challenges.
+-- Each challenge can be answered once, and is deleted when it's answered.
+CREATE TABLE captchas(
+primary_key SERIAL PRIMARY KEY,
+uuid UUID UNIQUE NOT NULL,
+question TEXT NOT NULL,
+answer TEXT NOT NULL,
+issued_at TIMESTAMPTZ NOT NULL,
+expires_at TIMESTAMPTZ NOT NULL
+);
+
+CREATE INDEX captchas_expires_at_index ON captchas(expires_at);
@@ -0,0 +1,10 @@
+-- Your SQL goes here
+-- Request counters of the IP rate limiter, shared by every chan-main process.
+-- A row is reset when a request comes after window_ends_at.
+CREATE TABLE rate_limit_windows(
+key TEXT PRIMARY KEY,
+count BIGINT NOT NULL,
+window_ends_at TIMESTAMPTZ NOT NULL
+);
+
+CREATE INDEX rate_limit_windows_window_ends_at_index ON rate_limit_windows(window_ends_at);
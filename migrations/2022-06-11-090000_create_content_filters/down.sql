@@ -0,0 +1,5 @@
+-- This file should undo anything in `up.sql`
+
+DROP INDEX threadposts_posted_at_index;
+DROP TABLE held_threadposts;
+DROP TABLE content_filter_rules
@@ -0,0 +1,35 @@
+-- Your SQL goes here
+-- Banned words and regexes of boards, checked before a threadpost is inserted.
+-- action: 1 reject, 2 hold for review
+CREATE TABLE content_filter_rules(
+primary_key SERIAL PRIMARY KEY,
+uuid UUID UNIQUE NOT NULL,
+board_id UUID NOT NULL,
+pattern TEXT NOT NULL,
+is_regex BOOLEAN NOT NULL,
+action INTEGER NOT NULL,
+creator_user_id TEXT NOT NULL,
+created_at TIMESTAMPTZ NOT NULL,
+CONSTRAINT fk_board_id FOREIGN KEY(board_id) REFERENCES boards(uuid)
+);
+
+CREATE INDEX content_filter_rules_board_id_index ON content_filter_rules(board_id);
+
+-- Threadposts held by the filters, inserted to threadposts when a moderator approves them.
+CREATE TABLE held_threadposts(
+primary_key SERIAL PRIMARY KEY,
+uuid UUID UNIQUE NOT NULL,
+board_id UUID NOT NULL,
+parent_thread_id UUID NOT NULL,
+poster_user_id TEXT NOT NULL,
+body_text VARCHAR (4096) NOT NULL,
+reason TEXT NOT NULL,
+held_at TIMESTAMPTZ NOT NULL,
+CONSTRAINT fk_thread_id FOREIGN KEY(parent_thread_id) REFERENCES threads(uuid) ON DELETE CASCADE,
+CONSTRAINT fk_user_id FOREIGN KEY(poster_user_id) REFERENCES users(id) ON DELETE CASCADE
+);
+
+CREATE INDEX held_threadposts_board_id_index ON held_threadposts(board_id);
+
+-- Duplicate detection looks up recent threadposts.
+CREATE INDEX threadposts_posted_at_index ON threadposts(posted_at);
@@ -0,0 +1,5 @@
+-- This file should undo anything in `up.sql`
+
+ALTER TABLE threadposts DROP COLUMN original_body_text;
+ALTER TABLE threads DROP COLUMN original_title;
+DROP TABLE word_filter_rules
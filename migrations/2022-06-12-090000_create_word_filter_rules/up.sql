@@ -0,0 +1,19 @@
+-- Your SQL goes here
+-- Find-and-replace rules of boards, applied to titles and threadposts before they are inserted.
+CREATE TABLE word_filter_rules(
+primary_key SERIAL PRIMARY KEY,
+uuid UUID UNIQUE NOT NULL,
+board_id UUID NOT NULL,
+pattern TEXT NOT NULL,
+is_regex BOOLEAN NOT NULL,
+replacement TEXT NOT NULL,
+creator_user_id TEXT NOT NULL,
+created_at TIMESTAMPTZ NOT NULL,
+CONSTRAINT fk_board_id FOREIGN KEY(board_id) REFERENCES boards(uuid)
+);
+
+CREATE INDEX word_filter_rules_board_id_index ON word_filter_rules(board_id);
+
+-- Text as submitted, only for moderators. NULL if no rule changed it.
+ALTER TABLE threads ADD COLUMN original_title TEXT;
+ALTER TABLE threadposts ADD COLUMN original_body_text TEXT;
@@ -0,0 +1,4 @@
+-- This file should undo anything in `up.sql`
+
+DROP TABLE notifications;
+ALTER TABLE threadposts DROP CONSTRAINT threadposts_uuid_key;
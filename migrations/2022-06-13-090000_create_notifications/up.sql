@@ -0,0 +1,21 @@
+-- Your SQL goes here
+-- Foreign keys need a unique key, threadposts.uuid had none.
+ALTER TABLE threadposts ADD CONSTRAINT threadposts_uuid_key UNIQUE (uuid);
+
+-- Replies(>>number) to and mentions(@id) of users, made when a threadpost is inserted.
+-- kind: 1 reply, 2 mention
+CREATE TABLE notifications(
+primary_key SERIAL PRIMARY KEY,
+uuid UUID UNIQUE NOT NULL,
+recipient_user_id TEXT NOT NULL,
+kind INTEGER NOT NULL,
+actor_user_id TEXT NOT NULL,
+thread_id UUID NOT NULL,
+threadpost_id UUID NOT NULL,
+created_at TIMESTAMPTZ NOT NULL,
+read_at TIMESTAMPTZ,
+CONSTRAINT fk_recipient_user_id FOREIGN KEY(recipient_user_id) REFERENCES users(id) ON DELETE CASCADE,
+CONSTRAINT fk_threadpost_id FOREIGN KEY(threadpost_id) REFERENCES threadposts(uuid) ON DELETE CASCADE
+);
+
+CREATE INDEX notifications_recipient_user_id_index ON notifications(recipient_user_id, read_at);
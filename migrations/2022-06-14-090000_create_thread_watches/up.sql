@@ -0,0 +1,13 @@
+-- Your SQL goes here
+-- Threads watched by users.
+-- last_read_number: number of the last threadpost the user has read, replies after it are new.
+CREATE TABLE thread_watches(
+primary_key SERIAL PRIMARY KEY,
+user_id TEXT NOT NULL,
+thread_id UUID NOT NULL,
+last_read_number INTEGER NOT NULL,
+watched_at TIMESTAMPTZ NOT NULL,
+CONSTRAINT fk_user_id FOREIGN KEY(user_id) REFERENCES users(id) ON DELETE CASCADE,
+CONSTRAINT fk_thread_id FOREIGN KEY(thread_id) REFERENCES threads(uuid) ON DELETE CASCADE,
+UNIQUE(user_id, thread_id)
+);
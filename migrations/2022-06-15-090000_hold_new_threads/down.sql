@@ -0,0 +1,5 @@
+-- This file should undo anything in `up.sql`
+DELETE FROM held_threadposts WHERE parent_thread_id IS NULL;
+ALTER TABLE held_threadposts DROP CONSTRAINT held_thread_or_reply;
+ALTER TABLE held_threadposts DROP COLUMN thread_title;
+ALTER TABLE held_threadposts ALTER COLUMN parent_thread_id SET NOT NULL;
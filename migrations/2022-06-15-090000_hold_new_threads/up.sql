@@ -0,0 +1,6 @@
+-- Your SQL goes here
+-- New threads held by the filters, created with their first post when a moderator approves them.
+-- Held new threads have thread_title, held replies have parent_thread_id.
+ALTER TABLE held_threadposts ALTER COLUMN parent_thread_id DROP NOT NULL;
+ALTER TABLE held_threadposts ADD COLUMN thread_title TEXT;
+ALTER TABLE held_threadposts ADD CONSTRAINT held_thread_or_reply CHECK ((parent_thread_id IS NULL) <> (thread_title IS NULL));
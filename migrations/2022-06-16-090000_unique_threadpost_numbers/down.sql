@@ -0,0 +1,2 @@
+-- This file should undo anything in `up.sql`
+ALTER TABLE threadposts DROP CONSTRAINT threadposts_parent_thread_id_number_key;
@@ -0,0 +1,17 @@
+-- Your SQL goes here
+-- Threadposts were numbered by counting, so racing or post-removal inserts could share a number.
+-- Duplicates after the first are moved past the last number of their thread.
+UPDATE threadposts SET number = renumbered.new_number
+FROM (
+SELECT primary_key, max_number + ROW_NUMBER() OVER (PARTITION BY parent_thread_id ORDER BY primary_key) AS new_number
+FROM (
+SELECT primary_key, parent_thread_id,
+ROW_NUMBER() OVER (PARTITION BY parent_thread_id, number ORDER BY primary_key) AS rank_in_number,
+MAX(number) OVER (PARTITION BY parent_thread_id) AS max_number
+FROM threadposts
+) numbered
+WHERE rank_in_number > 1
+) renumbered
+WHERE threadposts.primary_key = renumbered.primary_key;
+
+ALTER TABLE threadposts ADD CONSTRAINT threadposts_parent_thread_id_number_key UNIQUE (parent_thread_id, number);
@@ -0,0 +1,3 @@
+-- Your SQL goes here
+-- Locked threads reject new threadposts.
+ALTER TABLE threads ADD COLUMN locked BOOLEAN NOT NULL DEFAULT FALSE;